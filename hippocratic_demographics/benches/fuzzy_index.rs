@@ -0,0 +1,97 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use hippocratic_demographics::fuzzy_index::*;
+use hippocratic_demographics::fuzzy_matching::*;
+use hippocratic_demographics::name_variants::GIVEN_NAME_VARIANTS;
+
+const SURNAMES: &[&str] = &[
+    "smith", "johnson", "williams", "brown", "jones", "garcia", "miller", "davis", "rodriguez", "martinez",
+    "hernandez", "lopez", "gonzalez", "wilson", "anderson", "thomas", "taylor", "moore", "jackson", "martin",
+    "lee", "perez", "thompson", "white", "harris", "sanchez", "clark", "ramirez", "lewis", "robinson",
+    "walker", "young", "allen", "king", "wright", "scott", "torres", "nguyen", "hill", "flores",
+];
+
+fn given_names() -> Vec<String> {
+    let mut seen = HashSet::new();
+    GIVEN_NAME_VARIANTS.iter()
+        .flat_map(|group| group.iter())
+        .map(|name| name.to_lowercase())
+        .filter(|name| seen.insert(name.clone()))
+        .collect()
+}
+
+fn full_names() -> Vec<String> {
+    given_names().iter()
+        .flat_map(|given| SURNAMES.iter().map(move |surname| format!("{} {}", given, surname)))
+        .collect()
+}
+
+/// Deterministic typos: a transposition, a dropped letter or a substitution, depending on the position.
+fn queries(corpus: &[String]) -> Vec<String> {
+    corpus.iter().step_by(corpus.len() / 50 + 1).enumerate().map(|(i, name)| {
+        let mut chars: Vec<char> = name.chars().collect();
+        let at = 1 + i % (chars.len() - 2);
+        match i % 3 {
+            0 => chars.swap(at, at + 1),
+            1 => { chars.remove(at); },
+            _ => chars[at] = 'x',
+        }
+        chars.into_iter().collect()
+    }).collect()
+}
+
+fn bench_corpus(c: &mut Criterion, corpus_name: &str, corpus: Vec<String>) {
+    let calc = Arc::new(OsaEditDistanceCalculator::new());
+    let queries = queries(&corpus);
+    let entries = || corpus.iter().enumerate().map(|(i, name)| (Arc::new(name.clone()), Arc::new(i)));
+    let indexes: Vec<Box<dyn FuzzyIndex<usize>>> = vec![
+        Box::new(BKTree::bulk_load(entries(), Arc::clone(&calc), 2)),
+        Box::new(VPTree::bulk_load(entries(), Arc::clone(&calc), 2)),
+        Box::new(SymSpellIndex::bulk_load(entries(), Arc::clone(&calc), 2)),
+    ];
+    let mut group = c.benchmark_group(format!("search {}", corpus_name));
+    for max_distance in 1..=3 {
+        group.bench_with_input(BenchmarkId::new("linear scan", max_distance), &max_distance, |b, max_distance| {
+            b.iter(|| for query in &queries {
+                black_box(corpus.iter().filter(|name| calc.get_edit_distance(name.as_str(), query) <= *max_distance).count());
+            })
+        });
+        for index in &indexes {
+            // Beyond what an index can answer itself it falls back to a linear scan, which is benchmarked above
+            if index.get_max_indexed_distance().is_some_and(|indexed| max_distance > indexed) {
+                continue;
+            }
+            group.bench_with_input(BenchmarkId::new(index.index_name(), max_distance), &max_distance, |b, max_distance| {
+                b.iter(|| for query in &queries {
+                    black_box(index.search_within(query, *max_distance));
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+fn bench_batch(c: &mut Criterion) {
+    let corpus = full_names();
+    let queries = queries(&corpus);
+    let bktree = BKTree::bulk_load(corpus.iter().enumerate().map(|(i, name)| (Arc::new(name.clone()), Arc::new(i))), Arc::new(OsaEditDistanceCalculator::new()), 2);
+    let mut group = c.benchmark_group("batch search full names");
+    group.bench_function("one at a time", |b| b.iter(|| for query in &queries {
+        black_box(bktree.search(query));
+    }));
+    group.bench_function("search_batch", |b| b.iter(|| black_box(bktree.search_batch(&queries))));
+    group.bench_function("par_search_within", |b| b.iter(|| for query in &queries {
+        black_box(bktree.par_search_within(query, 2));
+    }));
+    group.finish();
+}
+
+fn fuzzy_index_benchmarks(c: &mut Criterion) {
+    bench_corpus(c, "given names", given_names());
+    bench_corpus(c, "full names", full_names());
+    bench_batch(c);
+}
+
+criterion_group!(benches, fuzzy_index_benchmarks);
+criterion_main!(benches);
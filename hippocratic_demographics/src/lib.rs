@@ -1,3 +1,7 @@
+// The constructor and return idioms used throughout the crate (`X { a: a }`, `let ret_val = ...; ret_val`,
+// `return "...";`) are deliberate; keep them from failing clippy.
+#![allow(clippy::redundant_field_names, clippy::let_and_return, clippy::needless_return)]
+
 #[macro_use]
 extern crate lazy_static;
 
@@ -5,11 +9,12 @@ pub mod human {
     use std::collections::{HashSet, HashMap};
     use std::fmt;
     use std::hash::{Hash, Hasher};
-    use std::rc::Rc;
     use std::str::FromStr;
+    use std::sync::Arc;
     use super::option_date_time::OptionDate;
     use super::entity::*;
     use super::organization::Organization;
+    use super::phonetic::PhoneticEncoder;
 
     pub type SSN = TIN;
 
@@ -21,12 +26,12 @@ pub mod human {
         addresses: HashMap<AddressType, Address>,
         phone_numbers: HashMap<PhoneNumberType, PhoneNumber>,
         email_addresses: HashMap<EmailAddressType, EmailAddress>,
-        employers: HashSet<Rc<Organization>>,
+        employers: HashSet<Arc<Organization>>,
         // TODO: Any more fields?
     }
 
     impl Human {
-        pub fn new(name: HumanName, ssn: SSN, birth_date: OptionDate, addresses: HashMap<AddressType, Address>, phone_numbers: HashMap<PhoneNumberType, PhoneNumber>, email_addresses: HashMap<EmailAddressType, EmailAddress>, employers: HashSet<Rc<Organization>>) -> Self {
+        pub fn new(name: HumanName, ssn: SSN, birth_date: OptionDate, addresses: HashMap<AddressType, Address>, phone_numbers: HashMap<PhoneNumberType, PhoneNumber>, email_addresses: HashMap<EmailAddressType, EmailAddress>, employers: HashSet<Arc<Organization>>) -> Self {
             let ret_val = Human { name: name, ssn: ssn, birth_date: birth_date, addresses: addresses, phone_numbers: phone_numbers, email_addresses: email_addresses, employers: employers };
             ret_val
         }
@@ -43,7 +48,21 @@ pub mod human {
             &self.birth_date
         }
 
-        // TODO: Add more property accessors
+        pub fn get_addresses(&self) -> &HashMap<AddressType, Address> {
+            &self.addresses
+        }
+
+        pub fn get_phone_numbers(&self) -> &HashMap<PhoneNumberType, PhoneNumber> {
+            &self.phone_numbers
+        }
+
+        pub fn get_email_addresses(&self) -> &HashMap<EmailAddressType, EmailAddress> {
+            &self.email_addresses
+        }
+
+        pub fn get_employers(&self) -> &HashSet<Arc<Organization>> {
+            &self.employers
+        }
     }
 
     impl Hash for Human {
@@ -69,28 +88,87 @@ pub mod human {
         }
     }
 
-    #[derive(Debug, PartialEq, Eq, Hash)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
     pub enum HumanName {
-        FirstMiddleLast { first_name: Rc<String>, middle_name: Rc<String>, last_name: Rc<String> },
-        FirstLastNoMiddle { first_name: Rc<String>, last_name: Rc<String> },
-        FirstMiddleMaidenLast { first_name: Rc<String>, middle_name: Rc<String>, maiden_name: Rc<String>, last_name: Rc<String> },
-        FirstMiddleLastMothersMaiden { first_name: Rc<String>, middle_name: Rc<String>, last_name: Rc<String>, mothers_maiden_name: Rc<String> },
-        FamilyNameGivenNames { family_name: Rc<String>, given_names: Vec<Rc<String>> },
-        Patronymic1 { given_name: Rc<String>, fathers_name: Rc<String>, grandfathers_name: Rc<String> },
-        Patronymic2 { given_name: Rc<String>, middle_name: Rc<String>, fathers_name: Rc<String> },
-        FirstMiddleMultipleLastNames { first_name: Rc<String>, middle_name: Rc<String>, last_names: Vec<Rc<String>> },
-        Fallback { name_components: Vec<Rc<String>> },
+        FirstMiddleLast { first_name: Arc<String>, middle_name: Arc<String>, last_name: Arc<String> },
+        FirstLastNoMiddle { first_name: Arc<String>, last_name: Arc<String> },
+        FirstMiddleMaidenLast { first_name: Arc<String>, middle_name: Arc<String>, maiden_name: Arc<String>, last_name: Arc<String> },
+        FirstMiddleLastMothersMaiden { first_name: Arc<String>, middle_name: Arc<String>, last_name: Arc<String>, mothers_maiden_name: Arc<String> },
+        FamilyNameGivenNames { family_name: Arc<String>, given_names: Vec<Arc<String>> },
+        Patronymic1 { given_name: Arc<String>, fathers_name: Arc<String>, grandfathers_name: Arc<String> },
+        Patronymic2 { given_name: Arc<String>, middle_name: Arc<String>, fathers_name: Arc<String> },
+        FirstMiddleMultipleLastNames { first_name: Arc<String>, middle_name: Arc<String>, last_names: Vec<Arc<String>> },
+        Fallback { name_components: Vec<Arc<String>> },
+    }
+
+    /// The part a single name component plays within a `HumanName`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    pub enum NameComponentRole {
+        Given,
+        Middle,
+        Family,
+        Maiden,
+        MothersMaiden,
+        FathersName,
+        GrandfathersName,
+    }
+
+    impl HumanName {
+        /// Every component of the name, tagged with its role, in display order. `Fallback` names carry no role
+        /// information, so they are read in Western order: first component given, last component family, the rest middle.
+        pub fn get_components(&self) -> Vec<(NameComponentRole, Arc<String>)> {
+            use NameComponentRole::*;
+            match self {
+                HumanName::FirstMiddleLast{first_name, middle_name, last_name}                                      => vec![(Given, Arc::clone(first_name)), (Middle, Arc::clone(middle_name)), (Family, Arc::clone(last_name))],
+                HumanName::FirstLastNoMiddle{first_name, last_name}                                                 => vec![(Given, Arc::clone(first_name)), (Family, Arc::clone(last_name))],
+                HumanName::FirstMiddleMaidenLast{first_name, middle_name, maiden_name, last_name}                   => vec![(Given, Arc::clone(first_name)), (Middle, Arc::clone(middle_name)), (Maiden, Arc::clone(maiden_name)), (Family, Arc::clone(last_name))],
+                HumanName::FirstMiddleLastMothersMaiden{first_name, middle_name, last_name, mothers_maiden_name}    => vec![(Given, Arc::clone(first_name)), (Middle, Arc::clone(middle_name)), (Family, Arc::clone(last_name)), (MothersMaiden, Arc::clone(mothers_maiden_name))],
+                HumanName::FamilyNameGivenNames{family_name, given_names}                                           => {
+                    let mut ret_val = vec![(Family, Arc::clone(family_name))];
+                    for (i, n) in given_names.iter().enumerate() {
+                        ret_val.push((if i == 0 { Given } else { Middle }, Arc::clone(n)));
+                    }
+                    ret_val
+                },
+                HumanName::Patronymic1{given_name, fathers_name, grandfathers_name}                                 => vec![(Given, Arc::clone(given_name)), (FathersName, Arc::clone(fathers_name)), (GrandfathersName, Arc::clone(grandfathers_name))],
+                HumanName::Patronymic2{given_name, middle_name, fathers_name}                                       => vec![(Given, Arc::clone(given_name)), (Middle, Arc::clone(middle_name)), (FathersName, Arc::clone(fathers_name))],
+                HumanName::FirstMiddleMultipleLastNames{first_name, middle_name, last_names}                        => {
+                    let mut ret_val = vec![(Given, Arc::clone(first_name)), (Middle, Arc::clone(middle_name))];
+                    for n in last_names {
+                        ret_val.push((Family, Arc::clone(n)));
+                    }
+                    ret_val
+                },
+                HumanName::Fallback{name_components}                                                                => {
+                    let last = name_components.len().saturating_sub(1);
+                    name_components.iter().enumerate().map(|(i, n)| {
+                        let role = if i == 0 { Given } else if i == last { Family } else { Middle };
+                        (role, Arc::clone(n))
+                    }).collect()
+                },
+            }
+        }
+
+        /// Phonetic codes for each component of the name, tagged with the component's role.
+        pub fn get_phonetic_keys<Encoder: PhoneticEncoder>(&self, encoder: &Encoder) -> Vec<(NameComponentRole, Vec<String>)> {
+            self.get_components().into_iter().map(|(role, c)| (role, encoder.encode(&c))).collect()
+        }
+
+        /// The components playing `role`, in display order.
+        pub fn get_components_with_role(&self, role: NameComponentRole) -> Vec<Arc<String>> {
+            self.get_components().into_iter().filter(|(r, _)| *r == role).map(|(_, n)| n).collect()
+        }
     }
 
     #[derive(Debug)]
     pub struct HumanNameParseErr;
     pub type HumanNameResult = Result<HumanName, HumanNameParseErr>;
 
-    fn write_name_components(f: &mut fmt::Formatter, name_components: &Vec<Rc<String>>) -> fmt::Result {
-        if name_components.len() > 0 {
+    fn write_name_components(f: &mut fmt::Formatter, name_components: &[Arc<String>]) -> fmt::Result {
+        if !name_components.is_empty() {
             write!(f, "{}", name_components[0])?;
-            for i in 1..name_components.len() {
-                write!(f, " {}", name_components[i])?;
+            for c in &name_components[1..] {
+                write!(f, " {}", c)?;
             }
         }
         Ok(())
@@ -102,9 +180,9 @@ pub mod human {
         // TODO: Implement more sophisticated parsing
         fn from_str(s: &str) -> HumanNameResult {
             let name_components_iter = s.split_whitespace();
-            let mut name_components = Vec::<Rc<String>>::new();
+            let mut name_components = Vec::<Arc<String>>::new();
             for s in name_components_iter {
-                name_components.push(Rc::new(s.to_string()));
+                name_components.push(Arc::new(s.to_string()));
             }
             let ret_val = HumanName::Fallback { name_components: name_components };
             Ok(ret_val)
@@ -142,7 +220,7 @@ pub mod option_date_time {
     use std::str::FromStr;
     use regex::Regex;
 
-    #[derive(Debug, PartialEq, Eq, Hash)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
     pub struct OptionDate {
         year: Option<i64>,
         month: Option<u8>,
@@ -150,6 +228,83 @@ pub mod option_date_time {
         // TODO: Any more methods?
     }
 
+    impl OptionDate {
+        /// A date with any combination of components unknown, as in a birth date recorded only as a year. Fails if
+        /// the month is not 1 to 12, or the day is past the end of its month (of any month, when the month is
+        /// unknown; February counts 29 days unless the year is known and not a leap year).
+        pub fn new(year: Option<i64>, month: Option<u8>, day: Option<u8>) -> OptionDateResult {
+            if month.is_some_and(|m| !(1..=12).contains(&m)) {
+                return Err(OptionDateParseErr);
+            }
+            let last_day = month.map_or(31, |m| days_in_month(year, m));
+            if day.is_some_and(|d| d < 1 || d > last_day) {
+                return Err(OptionDateParseErr);
+            }
+            let ret_val = OptionDate { year: year, month: month, day: day };
+            Ok(ret_val)
+        }
+
+        pub fn get_year(&self) -> Option<i64> {
+            self.year
+        }
+
+        pub fn get_month(&self) -> Option<u8> {
+            self.month
+        }
+
+        pub fn get_day(&self) -> Option<u8> {
+            self.day
+        }
+
+        /// True if the year, month and day are all known.
+        pub fn is_complete(&self) -> bool {
+            self.year.is_some() && self.month.is_some() && self.day.is_some()
+        }
+    }
+
+    /// A date with the year, month and day all known: an actual day on the calendar, unlike a partial
+    /// `OptionDate`, so it has a well-defined distance to other dates.
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    pub struct CompleteDate {
+        year: i64,
+        month: u8,
+        day: u8,
+    }
+
+    impl CompleteDate {
+        /// `date`, if none of its components is unknown.
+        pub fn from_option_date(date: &OptionDate) -> Option<Self> {
+            match (date.year, date.month, date.day) {
+                (Some(year), Some(month), Some(day)) => Some(CompleteDate { year: year, month: month, day: day }),
+                _                                    => None,
+            }
+        }
+
+        pub fn get_year(&self) -> i64 {
+            self.year
+        }
+
+        pub fn get_month(&self) -> u8 {
+            self.month
+        }
+
+        pub fn get_day(&self) -> u8 {
+            self.day
+        }
+    }
+
+    /// Number of days in `month`, taking February as 29 days when the year is unknown.
+    fn days_in_month(year: Option<i64>, month: u8) -> u8 {
+        match month {
+            4 | 6 | 9 | 11 => 30,
+            2              => match year {
+                Some(y) if !(y % 4 == 0 && (y % 100 != 0 || y % 400 == 0)) => 28,
+                _                                                          => 29,
+            },
+            _              => 31,
+        }
+    }
+
     #[derive(Debug)]
     pub struct OptionDateParseErr;
     pub type OptionDateResult = Result<OptionDate, OptionDateParseErr>;
@@ -167,8 +322,7 @@ pub mod option_date_time {
                 let year: i64 = caps[1].parse().unwrap();
                 let month: u8 = caps[2].parse().unwrap();
                 let day: u8 = caps[3].parse().unwrap();
-                let ret_val = OptionDate { year: Some(year), month: Some(month), day: Some(day) };
-                Ok(ret_val)
+                OptionDate::new(Some(year), Some(month), Some(day))
             } else {
                 Err(OptionDateParseErr)
             }
@@ -176,9 +330,10 @@ pub mod option_date_time {
     }
 
     impl fmt::Display for OptionDate {
-        // TODO: Allow for the possibility that one or more of these optional components are missing
+        /// Year, month and day separated by hyphens, with "?" standing in for each unknown component.
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-            write!(f, "{}-{}-{}", self.year.unwrap(), self.month.unwrap(), self.day.unwrap())
+            let part = |component: Option<String>| component.unwrap_or_else(|| "?".to_string());
+            write!(f, "{}-{}-{}", part(self.year.map(|y| y.to_string())), part(self.month.map(|m| m.to_string())), part(self.day.map(|d| d.to_string())))
         }
     }
 
@@ -266,8 +421,8 @@ pub mod option_date_time {
 pub mod entity {
     use std::fmt;
     use std::hash::Hash;
-    use std::rc::Rc;
     use std::str::FromStr;
+    use std::sync::Arc;
     use regex::Regex;
     use unicode_segmentation::UnicodeSegmentation;
 
@@ -280,9 +435,9 @@ pub mod entity {
     // FIXME: Implement a custom type, with custom validation
     pub type EmailAddress = String;
     
-    #[derive(Debug, PartialEq, Eq, Hash)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
     pub struct TIN {
-        unencrypted_string: Rc<String>,
+        unencrypted_string: Arc<String>,
     }
     
     #[derive(Debug)]
@@ -292,22 +447,30 @@ pub mod entity {
     }
     pub type EncryptedBytesResult = Result<Vec<u8>, EncryptedBytesErr>;
     
+    /// Shortest TIN, in graphemes, whose last four may be displayed: a nine-digit SSN or EIN.
+    pub const MIN_TIN_LEN_TO_SHOW_LAST_FEW: usize = 9;
+
     #[derive(Debug)]
     pub struct TINParseErr;
     pub type TINResult = Result<TIN, TINParseErr>;
 
     impl TIN {
-        pub fn as_unencrypted_string(&self) -> Rc<String> {
-            Rc::clone(&self.unencrypted_string)
+        pub fn as_unencrypted_string(&self) -> Arc<String> {
+            Arc::clone(&self.unencrypted_string)
         }
         // FIXME
         pub fn as_encrypted_bytes(&self) -> EncryptedBytesResult {
             Err(EncryptedBytesErr::NotYetImplementedError)
         }
-        pub fn last_few_chars(&self) -> Rc<String> {
+        /// The last four graphemes, or "XXXX" when the TIN is too short for its tail to be shown without giving
+        /// most of it away.
+        pub fn last_few_chars(&self) -> Arc<String> {
             let g = UnicodeSegmentation::graphemes(self.unencrypted_string.as_str(), true).collect::<Vec<&str>>();
-            let last_few = &g[g.len()-4..];
-            Rc::new(last_few.join(""))
+            if g.len() < MIN_TIN_LEN_TO_SHOW_LAST_FEW {
+                return Arc::new("XXXX".to_string());
+            }
+            let last_few = &g[g.len() - 4..];
+            Arc::new(last_few.join(""))
         }
     }
     
@@ -316,7 +479,7 @@ pub mod entity {
         
         // TODO: Implement more sophisticated parsing
         fn from_str(s: &str) -> TINResult {
-            Ok(TIN { unencrypted_string: Rc::new(s.to_string()) })
+            Ok(TIN { unencrypted_string: Arc::new(s.to_string()) })
         }
     }
 
@@ -330,18 +493,48 @@ pub mod entity {
     pub struct AddressParseErr;
     pub type AddressResult = Result<Address, AddressParseErr>;
 
-    #[derive(Debug, PartialEq, Eq, Hash)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
     pub struct Address {
-        line_1: Rc<String>,
-        line_2: Rc<String>,
-        line_3: Rc<String>,
-        city: Rc<String>,
-        state_or_province: Rc<String>,
-        zip_or_postal_code: Rc<String>,
-        country: Rc<String>,
+        line_1: Arc<String>,
+        line_2: Arc<String>,
+        line_3: Arc<String>,
+        city: Arc<String>,
+        state_or_province: Arc<String>,
+        zip_or_postal_code: Arc<String>,
+        country: Arc<String>,
         // TODO: Any more methods?
     }
 
+    impl Address {
+        pub fn get_line_1(&self) -> Arc<String> {
+            Arc::clone(&self.line_1)
+        }
+
+        pub fn get_line_2(&self) -> Arc<String> {
+            Arc::clone(&self.line_2)
+        }
+
+        pub fn get_line_3(&self) -> Arc<String> {
+            Arc::clone(&self.line_3)
+        }
+
+        pub fn get_city(&self) -> Arc<String> {
+            Arc::clone(&self.city)
+        }
+
+        pub fn get_state_or_province(&self) -> Arc<String> {
+            Arc::clone(&self.state_or_province)
+        }
+
+        pub fn get_zip_or_postal_code(&self) -> Arc<String> {
+            Arc::clone(&self.zip_or_postal_code)
+        }
+
+        pub fn get_country(&self) -> Arc<String> {
+            Arc::clone(&self.country)
+        }
+    }
+
     impl FromStr for Address {
         type Err = AddressParseErr;
         
@@ -352,13 +545,13 @@ pub mod entity {
             }
             if RE.is_match(s) {
                 let caps = RE.captures(s).unwrap();
-                let line_1 = Rc::new(caps[1].to_string());
-                let line_2 = Rc::new("".to_string());
-                let line_3 = Rc::new("".to_string());
-                let city = Rc::new(caps[2].to_string());
-                let state_or_province = Rc::new(caps[3].to_string());
-                let zip_or_postal_code = Rc::new(caps[4].to_string());
-                let country = Rc::new(caps[5].to_string());
+                let line_1 = Arc::new(caps[1].to_string());
+                let line_2 = Arc::new("".to_string());
+                let line_3 = Arc::new("".to_string());
+                let city = Arc::new(caps[2].to_string());
+                let state_or_province = Arc::new(caps[3].to_string());
+                let zip_or_postal_code = Arc::new(caps[4].to_string());
+                let country = Arc::new(caps[5].to_string());
                 let ret_val = Address { line_1: line_1, line_2: line_2, line_3: line_3, city: city, state_or_province: state_or_province, zip_or_postal_code: zip_or_postal_code, country: country };
                 Ok(ret_val)
             } else {
@@ -379,13 +572,13 @@ pub mod organization {
     use std::collections::HashMap;
     // use std::fmt;
     use std::hash::{Hash, Hasher};
-    use std::rc::Rc;
+    use std::sync::Arc;
     // use std::str::FromStr;
     use super::entity::*;
 
     #[derive(Debug, PartialEq, Eq)]
     pub struct Organization {
-        name: Rc<String>,
+        name: Arc<String>,
         tin_number: TIN,
         addresses: HashMap<AddressType, Address>,
         phone_numbers: HashMap<PhoneNumberType, PhoneNumber>,
@@ -393,18 +586,30 @@ pub mod organization {
     }
 
     impl Organization {
-        pub fn new(name: Rc<String>, tin_number: TIN, addresses: HashMap<AddressType, Address>, phone_numbers: HashMap<PhoneNumberType, PhoneNumber>, email_addresses: HashMap<EmailAddressType, EmailAddress>) -> Self {
+        pub fn new(name: Arc<String>, tin_number: TIN, addresses: HashMap<AddressType, Address>, phone_numbers: HashMap<PhoneNumberType, PhoneNumber>, email_addresses: HashMap<EmailAddressType, EmailAddress>) -> Self {
             let ret_val = Organization { name: name, tin_number: tin_number, addresses: addresses, phone_numbers: phone_numbers, email_addresses: email_addresses };
             ret_val
         }
 
-        pub fn get_name(&self) -> Rc<String> {
-            Rc::clone(&self.name)
+        pub fn get_name(&self) -> Arc<String> {
+            Arc::clone(&self.name)
         }
 
         pub fn get_tin_number(&self) -> &TIN {
             &self.tin_number
         }
+
+        pub fn get_addresses(&self) -> &HashMap<AddressType, Address> {
+            &self.addresses
+        }
+
+        pub fn get_phone_numbers(&self) -> &HashMap<PhoneNumberType, PhoneNumber> {
+            &self.phone_numbers
+        }
+
+        pub fn get_email_addresses(&self) -> &HashMap<EmailAddressType, EmailAddress> {
+            &self.email_addresses
+        }
     }
 
     impl Hash for Organization {
@@ -447,106 +652,619 @@ pub mod health_insurance {
 }
 
 pub mod fuzzy_matching {
-    use std::collections::{HashSet, HashMap};
+    use std::cmp::Ordering;
+    use std::collections::{BTreeMap, HashSet, HashMap};
+    use std::collections::hash_map::{Entry, RandomState};
+    use std::convert::TryFrom;
     // use std::fmt;
-    use std::hash::{Hash, Hasher};
-    use std::rc::Rc;
+    use std::hash::{BuildHasher, Hash, Hasher};
+    use std::iter::FromIterator;
+    use std::num::NonZeroUsize;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
     // use std::str::FromStr;
+    use lru::LruCache;
+    use rayon::prelude::*;
     use strsim::*;
+    use unicode_segmentation::UnicodeSegmentation;
+    use super::option_date_time::CompleteDate;
 
     pub type Similarity = f64;
     pub type EditDistance = usize;
 
+    /// Similarity of a lone initial ("J") to a name component starting with the same letter.
+    pub const INITIAL_SIMILARITY: Similarity = 0.8;
+
     pub trait SimilarityCalculator {
         fn algorithm_name(&self) -> &'static str;
-        fn get_similarity(&self, item1: &String, item2: &String) -> Similarity;
+        fn get_similarity(&self, item1: &str, item2: &str) -> Similarity;
     }
 
-    pub trait EditDistanceCalculator {
+    /// The name of a distance, shared by every key type a calculator accepts.
+    pub trait DistanceAlgorithm {
         fn algorithm_name(&self) -> &'static str;
-        fn get_edit_distance(&self, item1: &String, item2: &String) -> EditDistance;
+
+        /// The name plus whatever settings change the distances, such as costs or an inner calculator. Stored
+        /// indexes record it so they are not searched with a different calculator than they were built with.
+        fn algorithm_identity(&self) -> String {
+            self.algorithm_name().to_string()
+        }
+    }
+
+    /// A distance between keys. For use in a `BKTree` it should be a metric: zero only between equal keys,
+    /// symmetric, and obeying the triangle inequality. Keys are `String`s unless stated otherwise; string calculators
+    /// implement it for `str` and get the `String` form from the impl below.
+    pub trait EditDistanceCalculator<Key: ?Sized = String>: DistanceAlgorithm {
+        fn get_edit_distance(&self, item1: &Key, item2: &Key) -> EditDistance;
+
+        /// The edit distance if it is at most `max_distance`, otherwise `None`. Calculators that can tell early that
+        /// the bound is exceeded stop there; the default computes the full distance.
+        fn get_bounded_edit_distance(&self, item1: &Key, item2: &Key, max_distance: EditDistance) -> Option<EditDistance> {
+            let dist = self.get_edit_distance(item1, item2);
+            if dist <= max_distance { Some(dist) } else { None }
+        }
         // fn max_possible_edit_distance(&self, item1: &String, item2: &String) -> EditDistance;
     }
 
+    impl<Calc: EditDistanceCalculator<str> + ?Sized> EditDistanceCalculator<String> for Calc {
+        fn get_edit_distance(&self, item1: &String, item2: &String) -> EditDistance {
+            EditDistanceCalculator::<str>::get_edit_distance(self, item1, item2)
+        }
+
+        fn get_bounded_edit_distance(&self, item1: &String, item2: &String, max_distance: EditDistance) -> Option<EditDistance> {
+            EditDistanceCalculator::<str>::get_bounded_edit_distance(self, item1, item2, max_distance)
+        }
+    }
+
+    /// Threshold used by trees built through `FromIterator`, which has no way to be given one.
+    pub const DEFAULT_MAX_DISTANCE_TO_CONSIDER: EditDistance = 2;
+    /// How many values `BKTree::bulk_load` samples when choosing the medoid for each subtree.
+    pub const MEDOID_SAMPLE_SIZE: usize = 32;
+    /// How many subtrees per worker thread `BKTree::par_search_within` gathers before handing them out, so that
+    /// lopsided subtrees still balance across threads.
+    pub const PARALLEL_SUBTREES_PER_THREAD: usize = 4;
+
     #[derive(Debug)]
-    pub struct BKTree<RecordType, EditDistanceCalc>
+    pub struct BKTree<RecordType, EditDistanceCalc, KeyType = String>
         where RecordType: PartialEq + Eq + Hash,
-        EditDistanceCalc: EditDistanceCalculator + Sized {
-            root_node: BKTreeNode<RecordType>,
-            edit_distance_calculator: Rc<EditDistanceCalc>,
+        EditDistanceCalc: EditDistanceCalculator<KeyType> + Sized,
+        KeyType: Eq + Hash {
+            root_node: Option<BKTreeNode<RecordType, KeyType>>,
+            edit_distance_calculator: Arc<EditDistanceCalc>,
             max_distance_to_consider: EditDistance,
+            len: usize,
     }
 
-    impl<RecordType: PartialEq + Eq + Hash, EditDistanceCalc: EditDistanceCalculator> BKTree<RecordType, EditDistanceCalc> {
-        pub fn new(first_value: Rc<String>, first_record: Rc<RecordType>, edit_distance_calculator: Rc<EditDistanceCalc>, max_distance_to_consider: EditDistance) -> Self {
-            let root_node = BKTreeNode::<RecordType>::new(first_value, first_record);
-            let ret_val = BKTree::<RecordType, EditDistanceCalc> { root_node: root_node, edit_distance_calculator: edit_distance_calculator, max_distance_to_consider: max_distance_to_consider };
+    impl<RecordType: PartialEq + Eq + Hash, EditDistanceCalc: EditDistanceCalculator<KeyType>, KeyType: Eq + Hash + Ord> BKTree<RecordType, EditDistanceCalc, KeyType> {
+        pub fn new(first_value: Arc<KeyType>, first_record: Arc<RecordType>, edit_distance_calculator: Arc<EditDistanceCalc>, max_distance_to_consider: EditDistance) -> Self {
+            let mut ret_val = BKTree::new_empty(edit_distance_calculator, max_distance_to_consider);
+            ret_val.insert(first_value, first_record);
+            ret_val
+        }
+
+        pub fn new_empty(edit_distance_calculator: Arc<EditDistanceCalc>, max_distance_to_consider: EditDistance) -> Self {
+            let ret_val = BKTree::<RecordType, EditDistanceCalc, KeyType> { root_node: None, edit_distance_calculator: edit_distance_calculator, max_distance_to_consider: max_distance_to_consider, len: 0 };
+            ret_val
+        }
+
+        /// Builds a tree from all `items` at once. Each subtree is rooted at the medoid of a sample of its values,
+        /// which keeps the tree much shallower than inserting values in whatever order they arrive.
+        pub fn bulk_load<I: IntoIterator<Item = (Arc<KeyType>, Arc<RecordType>)>>(items: I, edit_distance_calculator: Arc<EditDistanceCalc>, max_distance_to_consider: EditDistance) -> Self {
+            let mut ret_val = BKTree::new_empty(edit_distance_calculator, max_distance_to_consider);
+            let mut entries: Vec<BKTreeNode<RecordType, KeyType>> = Vec::new();
+            let mut entry_index_by_value = HashMap::<Arc<KeyType>, usize>::new();
+            for (value, record_found_in) in items {
+                let index = *entry_index_by_value.entry(Arc::clone(&value)).or_insert_with(|| {
+                    entries.push(BKTreeNode { value: Arc::clone(&value), records_found_in: HashSet::new(), children: HashMap::new() });
+                    entries.len() - 1
+                });
+                if entries[index].records_found_in.insert(record_found_in) {
+                    ret_val.len += 1;
+                }
+            }
+            ret_val.root_node = ret_val.build_subtree(entries);
+            ret_val
+        }
+
+        /// Builds a subtree from `entries`, each node rooted at its group's medoid. Work is kept on an explicit
+        /// stack, so a degenerate split cannot exhaust the call stack.
+        fn build_subtree(&self, entries: Vec<BKTreeNode<RecordType, KeyType>>) -> Option<BKTreeNode<RecordType, KeyType>> {
+            // Nodes are first built without their children, then attached child-first in reverse creation order
+            let mut built: Vec<Option<BKTreeNode<RecordType, KeyType>>> = Vec::new();
+            let mut links: Vec<(usize, EditDistance, usize)> = Vec::new();
+            let mut pending = vec![(entries, None::<(usize, EditDistance)>)];
+            while let Some((mut group, parent)) = pending.pop() {
+                if group.is_empty() {
+                    continue;
+                }
+                let medoid_index = self.sample_medoid(&group);
+                let root = group.swap_remove(medoid_index);
+                let mut subgroups = HashMap::<EditDistance, Vec<BKTreeNode<RecordType, KeyType>>>::new();
+                for entry in group {
+                    let dist = self.edit_distance_calculator.get_edit_distance(&root.value, &entry.value);
+                    subgroups.entry(dist).or_default().push(entry);
+                }
+                let index = built.len();
+                built.push(Some(root));
+                if let Some((parent_index, dist)) = parent {
+                    links.push((parent_index, dist, index));
+                }
+                for (dist, subgroup) in subgroups {
+                    pending.push((subgroup, Some((index, dist))));
+                }
+            }
+            for (parent_index, dist, child_index) in links.into_iter().rev() {
+                let child_node = built[child_index].take().expect("each node is attached once");
+                built[parent_index].as_mut().expect("parents are attached after their children").children.insert(dist, child_node);
+            }
+            built.into_iter().next().flatten()
+        }
+
+        /// Index of the sampled entry with the smallest total distance to the rest of the sample.
+        fn sample_medoid(&self, entries: &[BKTreeNode<RecordType, KeyType>]) -> usize {
+            let step = entries.len().div_ceil(MEDOID_SAMPLE_SIZE).max(1);
+            let sample: Vec<usize> = (0..entries.len()).step_by(step).collect();
+            let total_distance = |i: usize| -> EditDistance {
+                sample.iter().map(|&j| self.edit_distance_calculator.get_edit_distance(&entries[i].value, &entries[j].value)).sum()
+            };
+            sample.iter().copied().min_by_key(|&i| total_distance(i)).unwrap_or(0)
+        }
+
+        pub fn get_root_node(&self) -> Option<&BKTreeNode<RecordType, KeyType>> {
+            self.root_node.as_ref()
+        }
+
+        pub fn get_edit_distance_calculator(&self) -> Arc<EditDistanceCalc> {
+            Arc::clone(&self.edit_distance_calculator)
+        }
+
+        pub fn get_max_distance_to_consider(&self) -> EditDistance {
+            self.max_distance_to_consider
+        }
+
+        /// Number of (value, record) pairs indexed.
+        pub fn len(&self) -> usize {
+            self.len
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.len == 0
+        }
+
+        /// Every indexed (value, record) pair, in no particular order.
+        pub fn iter(&self) -> BKTreeIter<'_, RecordType, KeyType> {
+            BKTreeIter { stack: self.root_node.iter().collect(), current: None }
+        }
+
+        /// Adds `record_found_in` under `value`. Returns false if that record was already indexed under that value.
+        pub fn insert(&mut self, value: Arc<KeyType>, record_found_in: Arc<RecordType>) -> bool {
+            let mut node = match &mut self.root_node {
+                Some(root_node) => root_node,
+                None            => {
+                    self.root_node = Some(BKTreeNode::new(value, record_found_in));
+                    self.len += 1;
+                    return true;
+                },
+            };
+            loop {
+                if node.value == value {
+                    let inserted = node.records_found_in.insert(record_found_in);
+                    if inserted {
+                        self.len += 1;
+                    }
+                    return inserted;
+                }
+                let dist = self.edit_distance_calculator.get_edit_distance(&node.value, &value);
+                match node.children.entry(dist) {
+                    Entry::Occupied(child_node) => node = child_node.into_mut(),
+                    Entry::Vacant(slot)         => {
+                        // Distinct values can still be at distance 0 (two names with the same Soundex code, say);
+                        // the later one becomes a child at edge 0 rather than sharing this node
+                        slot.insert(BKTreeNode::new(value, record_found_in));
+                        self.len += 1;
+                        return true;
+                    },
+                }
+            }
+        }
+
+        /// Every indexed value within `max_distance_to_consider` of `value`, with its distance and the records it
+        /// was found in, closest first. Ties are ordered by value.
+        pub fn search(&self, value: &KeyType) -> Vec<BKTreeMatch<RecordType, KeyType>> {
+            self.search_within(value, self.max_distance_to_consider)
+        }
+
+        /// Like `search`, but with a threshold for this query only.
+        pub fn search_within(&self, value: &KeyType, max_distance: EditDistance) -> Vec<BKTreeMatch<RecordType, KeyType>> {
+            let mut rtn = Vec::new();
+            self.expand_search(self.root_node.iter().collect(), value, max_distance, &mut rtn, usize::MAX);
+            rtn.sort_by(BKTreeMatch::cmp_by_distance);
+            rtn
+        }
+
+        /// Searches the subtrees in `pending` for matches, adding them to `rtn`, until they are exhausted or
+        /// `pending_limit` subtrees are waiting. Returns the subtrees still to search.
+        fn expand_search<'a>(&'a self, mut pending: Vec<&'a BKTreeNode<RecordType, KeyType>>, value: &KeyType, max_distance: EditDistance, rtn: &mut Vec<BKTreeMatch<RecordType, KeyType>>, pending_limit: usize) -> Vec<&'a BKTreeNode<RecordType, KeyType>> {
+            while pending.len() < pending_limit {
+                let node = match pending.pop() {
+                    Some(node) => node,
+                    None       => break,
+                };
+                // Beyond the largest edge plus the threshold neither this node nor any child can match, so the
+                // exact distance is not needed
+                let distance_bound = node.children.keys().max().map_or(max_distance, |edge| edge.saturating_add(max_distance));
+                let cur_edit_distance = match self.edit_distance_calculator.get_bounded_edit_distance(&node.value, value, distance_bound) {
+                    Some(dist) => dist,
+                    None       => continue,
+                };
+                if cur_edit_distance <= max_distance && !node.is_tombstone() {
+                    rtn.push(node.to_match(cur_edit_distance));
+                }
+                // By the triangle inequality, only subtrees whose edge distance is within the threshold of the
+                // current distance can hold a match
+                let min_edge = cur_edit_distance.saturating_sub(max_distance);
+                let max_edge = cur_edit_distance.saturating_add(max_distance);
+                for (k, child_node) in &node.children {
+                    if (*k >= min_edge) && (*k <= max_edge) {
+                        pending.push(child_node);
+                    }
+                }
+            }
+            pending
+        }
+
+        /// The `k` indexed values closest to `value`, regardless of `max_distance_to_consider`, closest first.
+        pub fn nearest(&self, value: &KeyType, k: usize) -> Vec<BKTreeMatch<RecordType, KeyType>> {
+            let mut best: Vec<BKTreeMatch<RecordType, KeyType>> = Vec::with_capacity(k);
+            if k == 0 {
+                return best;
+            }
+            // Each pending subtree carries a lower bound on the distance from `value` to anything in it
+            let mut pending: Vec<(EditDistance, &BKTreeNode<RecordType, KeyType>)> = self.root_node.iter().map(|n| (0, n)).collect();
+            while let Some((lower_bound, node)) = pending.pop() {
+                let bound = if best.len() < k { EditDistance::MAX } else { best[best.len() - 1].distance };
+                if lower_bound > bound {
+                    continue;
+                }
+                let cur_edit_distance = self.edit_distance_calculator.get_edit_distance(&node.value, value);
+                if !node.is_tombstone() {
+                    let candidate = node.to_match(cur_edit_distance);
+                    if best.len() < k || BKTreeMatch::cmp_by_distance(&candidate, &best[best.len() - 1]) == Ordering::Less {
+                        let position = best.partition_point(|m| BKTreeMatch::cmp_by_distance(m, &candidate) == Ordering::Less);
+                        best.insert(position, candidate);
+                        best.truncate(k);
+                    }
+                }
+                // Push the children most likely to hold close values last, so they are visited first and the
+                // bound tightens early
+                let mut children: Vec<(EditDistance, &BKTreeNode<RecordType, KeyType>)> = node.children.iter()
+                    .map(|(edge, child_node)| (edge.abs_diff(cur_edit_distance), child_node))
+                    .collect();
+                children.sort_by_key(|(child_bound, _)| std::cmp::Reverse(*child_bound));
+                pending.extend(children);
+            }
+            best
+        }
+
+        /// The indexed value closest to `value` within `max_distance_to_consider`, if any. `value` itself wins over
+        /// other values at distance 0, and the search stops as soon as it is found.
+        pub fn best_match(&self, value: &KeyType) -> Option<BKTreeMatch<RecordType, KeyType>> {
+            let mut best: Option<BKTreeMatch<RecordType, KeyType>> = None;
+            let mut pending: Vec<(EditDistance, &BKTreeNode<RecordType, KeyType>)> = self.root_node.iter().map(|n| (0, n)).collect();
+            while let Some((lower_bound, node)) = pending.pop() {
+                let bound = match &best {
+                    Some(m) => m.distance,
+                    None    => self.max_distance_to_consider,
+                };
+                if lower_bound > bound {
+                    continue;
+                }
+                let distance_bound = node.children.keys().max().map_or(bound, |edge| edge.saturating_add(bound));
+                let cur_edit_distance = match self.edit_distance_calculator.get_bounded_edit_distance(&node.value, value, distance_bound) {
+                    Some(dist) => dist,
+                    None       => continue,
+                };
+                if cur_edit_distance <= bound && !node.is_tombstone() {
+                    let candidate = node.to_match(cur_edit_distance);
+                    if *node.value == *value {
+                        best = Some(candidate);
+                        break;
+                    }
+                    let is_better = match &best {
+                        Some(m) => BKTreeMatch::cmp_by_distance(&candidate, m) == Ordering::Less,
+                        None    => true,
+                    };
+                    if is_better {
+                        best = Some(candidate);
+                    }
+                }
+                pending.extend(node.children.iter().map(|(edge, child_node)| (edge.abs_diff(cur_edit_distance), child_node)));
+            }
+            best
+        }
+
+        /// The node holding exactly `value`, found by following edge distances down from the root.
+        fn find_node_mut(&mut self, value: &KeyType) -> Option<&mut BKTreeNode<RecordType, KeyType>> {
+            let mut node = self.root_node.as_mut()?;
+            loop {
+                if *node.value == *value {
+                    return Some(node);
+                }
+                let dist = self.edit_distance_calculator.get_edit_distance(&node.value, value);
+                match node.children.get_mut(&dist) {
+                    Some(child_node) => node = child_node,
+                    None             => return None,
+                }
+            }
+        }
+
+        /// Removes one record from the records `value` was found in. A value left with no records is tombstoned:
+        /// its node stays in place to keep the tree's structure, but searches skip it. Returns false if the record
+        /// was not indexed under `value`.
+        pub fn remove_record(&mut self, value: &KeyType, record_found_in: &Arc<RecordType>) -> bool {
+            let removed = match self.find_node_mut(value) {
+                Some(node) => node.records_found_in.remove(record_found_in),
+                None       => false,
+            };
+            if removed {
+                self.len -= 1;
+            }
+            removed
+        }
+
+        /// Tombstones `value`, dropping all of its records. Returns false if it was not indexed or already deleted.
+        pub fn remove_value(&mut self, value: &KeyType) -> bool {
+            let removed = match self.find_node_mut(value) {
+                Some(node) => {
+                    let record_count = node.records_found_in.len();
+                    node.records_found_in.clear();
+                    record_count
+                },
+                None       => 0,
+            };
+            self.len -= removed;
+            removed > 0
+        }
+
+        /// Moves a record from `old_value` to `new_value`, for when the indexed string of a record changes. The
+        /// record is removed from `old_value` as `remove_record` does, tombstoning it if no records remain. Returns
+        /// false, leaving the tree untouched, if the record was not indexed under `old_value`; also returns false
+        /// (the record is still removed from `old_value`) if it was already indexed under `new_value`.
+        pub fn rekey_record(&mut self, old_value: &KeyType, new_value: Arc<KeyType>, record_found_in: Arc<RecordType>) -> bool {
+            if !self.remove_record(old_value, &record_found_in) {
+                return false;
+            }
+            self.insert(new_value, record_found_in)
+        }
+
+        /// Number of tombstoned values still occupying nodes.
+        pub fn get_tombstone_count(&self) -> usize {
+            self.get_stats().tombstone_count
+        }
+
+        /// Shape of the tree, for spotting degenerate (deep, narrow) trees that search slowly.
+        pub fn get_stats(&self) -> BKTreeStats {
+            let mut ret_val = BKTreeStats { node_count: 0, tombstone_count: 0, depth: 0, average_leaf_depth: 0.0, fan_out_histogram: BTreeMap::new() };
+            let mut leaf_count = 0;
+            let mut total_leaf_depth = 0;
+            let mut pending: Vec<(usize, &BKTreeNode<RecordType, KeyType>)> = self.root_node.iter().map(|n| (1, n)).collect();
+            while let Some((depth, node)) = pending.pop() {
+                ret_val.node_count += 1;
+                if node.is_tombstone() {
+                    ret_val.tombstone_count += 1;
+                }
+                ret_val.depth = ret_val.depth.max(depth);
+                *ret_val.fan_out_histogram.entry(node.children.len()).or_insert(0) += 1;
+                if node.children.is_empty() {
+                    leaf_count += 1;
+                    total_leaf_depth += depth;
+                }
+                pending.extend(node.children.values().map(|child_node| (depth + 1, child_node)));
+            }
+            if leaf_count > 0 {
+                ret_val.average_leaf_depth = total_leaf_depth as f64 / leaf_count as f64;
+            }
             ret_val
         }
 
-        pub fn insert(&mut self, value: Rc<String>, record_found_in: Rc<RecordType>) -> bool {
-            self.root_node.recursive_insert(Rc::clone(&value), Rc::clone(&record_found_in), Rc::clone(&self.edit_distance_calculator))
+        /// Rebuilds the tree from its live values, dropping all tombstones, in the same way as `bulk_load`.
+        pub fn compact(&mut self) {
+            let mut live = Vec::new();
+            let mut pending: Vec<BKTreeNode<RecordType, KeyType>> = self.root_node.take().into_iter().collect();
+            while let Some(mut node) = pending.pop() {
+                pending.extend(node.children.drain().map(|(_, child_node)| child_node));
+                if !node.is_tombstone() {
+                    live.push(node);
+                }
+            }
+            self.root_node = self.build_subtree(live);
         }
+    }
 
-        pub fn search(&self, value: Rc<String>, record_found_in: Rc<RecordType>) -> Vec<(Rc<String>, Rc<RecordType>)> {
+    /// Parallel searches, run on rayon's global thread pool. Only available when the records, keys and calculator
+    /// can be shared between threads.
+    impl<RecordType, EditDistanceCalc, KeyType> BKTree<RecordType, EditDistanceCalc, KeyType>
+        where RecordType: PartialEq + Eq + Hash + Send + Sync,
+        EditDistanceCalc: EditDistanceCalculator<KeyType> + Send + Sync,
+        KeyType: Eq + Hash + Ord + Send + Sync {
+        /// Like `search_within`, but explores the subtrees of one query in parallel. Worth it for a single query with
+        /// a large threshold; for many queries, `search_within_batch` parallelizes better.
+        pub fn par_search_within(&self, value: &KeyType, max_distance: EditDistance) -> Vec<BKTreeMatch<RecordType, KeyType>> {
             let mut rtn = Vec::new();
-            self.recursive_search(&self.root_node, value, record_found_in, &mut rtn);
+            // Search sequentially until there are enough independent subtrees to keep every thread busy
+            let pending_limit = rayon::current_num_threads() * PARALLEL_SUBTREES_PER_THREAD;
+            let pending = self.expand_search(self.root_node.iter().collect(), value, max_distance, &mut rtn, pending_limit);
+            let found: Vec<Vec<BKTreeMatch<RecordType, KeyType>>> = pending.into_par_iter()
+                .map(|node| {
+                    let mut found = Vec::new();
+                    self.expand_search(vec![node], value, max_distance, &mut found, usize::MAX);
+                    found
+                })
+                .collect();
+            rtn.extend(found.into_iter().flatten());
+            rtn.sort_by(BKTreeMatch::cmp_by_distance);
             rtn
         }
 
-        fn recursive_search(&self, node: &BKTreeNode<RecordType>, value: Rc<String>, record_found_in: Rc<RecordType>, rtn: &mut Vec<(Rc<String>, Rc<RecordType>)>) {
-            let cur_edit_distance = self.edit_distance_calculator.get_edit_distance(&node.value, &value);
-            let min_distance = cur_edit_distance - self.max_distance_to_consider;
-            let max_distance = cur_edit_distance + self.max_distance_to_consider;
-            if cur_edit_distance <= self.max_distance_to_consider {
-                rtn.push((Rc::clone(&value), Rc::clone(&record_found_in)));
+        /// `search` for each of `values`, run in parallel. Results are in the same order as `values`.
+        pub fn search_batch(&self, values: &[KeyType]) -> Vec<Vec<BKTreeMatch<RecordType, KeyType>>> {
+            self.search_within_batch(values, self.max_distance_to_consider)
+        }
+
+        /// `search_within` for each of `values`, run in parallel. Results are in the same order as `values`.
+        pub fn search_within_batch(&self, values: &[KeyType], max_distance: EditDistance) -> Vec<Vec<BKTreeMatch<RecordType, KeyType>>> {
+            values.par_iter().map(|value| self.search_within(value, max_distance)).collect()
+        }
+
+        /// `best_match` for each of `values`, run in parallel. Results are in the same order as `values`.
+        pub fn best_match_batch(&self, values: &[KeyType]) -> Vec<Option<BKTreeMatch<RecordType, KeyType>>> {
+            values.par_iter().map(|value| self.best_match(value)).collect()
+        }
+    }
+
+    impl<RecordType, EditDistanceCalc, KeyType> Drop for BKTree<RecordType, EditDistanceCalc, KeyType>
+        where RecordType: PartialEq + Eq + Hash,
+        EditDistanceCalc: EditDistanceCalculator<KeyType> + Sized,
+        KeyType: Eq + Hash {
+        /// Tears the tree down level by level; the default, recursive drop could overflow the stack on a deep tree.
+        fn drop(&mut self) {
+            let mut pending: Vec<BKTreeNode<RecordType, KeyType>> = self.root_node.take().into_iter().collect();
+            while let Some(mut node) = pending.pop() {
+                pending.extend(node.children.drain().map(|(_, child_node)| child_node));
+            }
+        }
+    }
+
+    /// Shape statistics for a `BKTree`; see `BKTree::get_stats`. Depths count the root as 1.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct BKTreeStats {
+        pub node_count: usize,
+        pub tombstone_count: usize,
+        pub depth: usize,
+        pub average_leaf_depth: f64,
+        /// Number of nodes with each number of children.
+        pub fan_out_histogram: BTreeMap<usize, usize>,
+    }
+
+    impl<RecordType: PartialEq + Eq + Hash, EditDistanceCalc: EditDistanceCalculator<KeyType> + Default, KeyType: Eq + Hash + Ord> FromIterator<(Arc<KeyType>, Arc<RecordType>)> for BKTree<RecordType, EditDistanceCalc, KeyType> {
+        /// Bulk-loads the pairs with a default calculator and `DEFAULT_MAX_DISTANCE_TO_CONSIDER`.
+        fn from_iter<I: IntoIterator<Item = (Arc<KeyType>, Arc<RecordType>)>>(iter: I) -> Self {
+            BKTree::bulk_load(iter, Arc::new(EditDistanceCalc::default()), DEFAULT_MAX_DISTANCE_TO_CONSIDER)
+        }
+    }
+
+    impl<RecordType: PartialEq + Eq + Hash, EditDistanceCalc: EditDistanceCalculator<KeyType>, KeyType: Eq + Hash + Ord> Extend<(Arc<KeyType>, Arc<RecordType>)> for BKTree<RecordType, EditDistanceCalc, KeyType> {
+        fn extend<I: IntoIterator<Item = (Arc<KeyType>, Arc<RecordType>)>>(&mut self, iter: I) {
+            for (value, record_found_in) in iter {
+                self.insert(value, record_found_in);
             }
-            let children = &node.children;
-            for k in children.keys() {
-                if (k >= &min_distance) && (k <= &max_distance) {
-                    let child_node = &children[k];
-                    self.recursive_search(child_node, Rc::clone(&value), Rc::clone(&record_found_in), rtn);
+        }
+    }
+
+    impl<'a, RecordType: PartialEq + Eq + Hash, EditDistanceCalc: EditDistanceCalculator<KeyType>, KeyType: Eq + Hash + Ord> IntoIterator for &'a BKTree<RecordType, EditDistanceCalc, KeyType> {
+        type Item = (&'a Arc<KeyType>, &'a Arc<RecordType>);
+        type IntoIter = BKTreeIter<'a, RecordType, KeyType>;
+
+        fn into_iter(self) -> Self::IntoIter {
+            self.iter()
+        }
+    }
+
+    /// Iterator over the (value, record) pairs of a `BKTree`; see `BKTree::iter`.
+    pub struct BKTreeIter<'a, RecordType: PartialEq + Eq + Hash, KeyType: Eq + Hash = String> {
+        stack: Vec<&'a BKTreeNode<RecordType, KeyType>>,
+        current: Option<(&'a Arc<KeyType>, std::collections::hash_set::Iter<'a, Arc<RecordType>>)>,
+    }
+
+    impl<'a, RecordType: PartialEq + Eq + Hash, KeyType: Eq + Hash> Iterator for BKTreeIter<'a, RecordType, KeyType> {
+        type Item = (&'a Arc<KeyType>, &'a Arc<RecordType>);
+
+        fn next(&mut self) -> Option<Self::Item> {
+            loop {
+                if let Some((value, records)) = &mut self.current {
+                    if let Some(record) = records.next() {
+                        return Some((*value, record));
+                    }
                 }
+                let node = self.stack.pop()?;
+                self.stack.extend(node.children.values());
+                self.current = Some((&node.value, node.records_found_in.iter()));
             }
         }
     }
 
+    /// One indexed value found by a `BKTree` search.
+    #[derive(Debug, Clone)]
+    pub struct BKTreeMatch<RecordType, KeyType = String> {
+        value: Arc<KeyType>,
+        distance: EditDistance,
+        records_found_in: Vec<Arc<RecordType>>,
+    }
+
+    impl<RecordType, KeyType> BKTreeMatch<RecordType, KeyType> {
+        pub fn new(value: Arc<KeyType>, distance: EditDistance, records_found_in: Vec<Arc<RecordType>>) -> Self {
+            let ret_val = BKTreeMatch { value: value, distance: distance, records_found_in: records_found_in };
+            ret_val
+        }
+
+        pub fn get_value(&self) -> Arc<KeyType> {
+            self.value.clone()
+        }
+
+        pub fn get_distance(&self) -> EditDistance {
+            self.distance
+        }
+
+        pub fn get_records_found_in(&self) -> &[Arc<RecordType>] {
+            &self.records_found_in
+        }
+
+        pub(crate) fn cmp_by_distance(a: &Self, b: &Self) -> Ordering where KeyType: Ord {
+            a.distance.cmp(&b.distance).then_with(|| a.value.cmp(&b.value))
+        }
+    }
+
     #[derive(Debug)]
-    pub struct BKTreeNode<RecordType>
-        where RecordType: PartialEq + Eq + Hash {
-            value: Rc<String>,
-            records_found_in: HashSet<Rc<RecordType>>,
-            children: HashMap<EditDistance, BKTreeNode<RecordType>>,
+    pub struct BKTreeNode<RecordType, KeyType = String>
+        where RecordType: PartialEq + Eq + Hash,
+        KeyType: Eq + Hash {
+            value: Arc<KeyType>,
+            records_found_in: HashSet<Arc<RecordType>>,
+            children: HashMap<EditDistance, BKTreeNode<RecordType, KeyType>>,
     }
 
-    impl<RecordType: PartialEq + Eq + Hash> BKTreeNode<RecordType> {
-        pub fn new(value: Rc<String>, first_record_found_in: Rc<RecordType>) -> Self {
-            let mut records_found_in = HashSet::<Rc<RecordType>>::new();
+    impl<RecordType: PartialEq + Eq + Hash, KeyType: Eq + Hash> BKTreeNode<RecordType, KeyType> {
+        pub fn new(value: Arc<KeyType>, first_record_found_in: Arc<RecordType>) -> Self {
+            let mut records_found_in = HashSet::<Arc<RecordType>>::new();
             records_found_in.insert(first_record_found_in);
             let children = HashMap::new();
-            let ret_val = BKTreeNode::<RecordType> { value: value, records_found_in: records_found_in, children: children };
+            let ret_val = BKTreeNode::<RecordType, KeyType> { value: value, records_found_in: records_found_in, children: children };
             ret_val
         }
 
-        fn recursive_insert<EditDistanceCalc: EditDistanceCalculator>(&mut self, value: Rc<String>, record_found_in: Rc<RecordType>, edit_distance_calculator: Rc<EditDistanceCalc>) -> bool {
-            let dist = edit_distance_calculator.get_edit_distance(&self.value, &value);
-            if dist == 0 {
-                assert!(&self.value == &value);
-                return self.records_found_in.insert(record_found_in);
-            } else if self.children.contains_key(&dist) {
-                let mut c = &mut *self.children.get_mut(&dist).unwrap();
-                return c.recursive_insert::<EditDistanceCalc>(Rc::clone(&value), Rc::clone(&record_found_in), Rc::clone(&edit_distance_calculator));
-            } else {
-                let new_node = BKTreeNode::new(value, record_found_in);
-                match self.children.insert(dist, new_node) {
-                    Some(new_node) => return true,
-                    None => return false,
-                }
-            }
+        pub fn get_value(&self) -> Arc<KeyType> {
+            Arc::clone(&self.value)
+        }
+
+        pub fn get_records_found_in(&self) -> &HashSet<Arc<RecordType>> {
+            &self.records_found_in
+        }
+
+        pub fn get_children(&self) -> &HashMap<EditDistance, BKTreeNode<RecordType, KeyType>> {
+            &self.children
+        }
+
+        /// A node whose records have all been removed.
+        fn is_tombstone(&self) -> bool {
+            self.records_found_in.is_empty()
+        }
+
+        fn to_match(&self, distance: EditDistance) -> BKTreeMatch<RecordType, KeyType> {
+            BKTreeMatch::new(Arc::clone(&self.value), distance, self.records_found_in.iter().cloned().collect())
         }
     }
 
-    impl<RecordType: PartialEq + Eq + Hash> Hash for BKTreeNode<RecordType> {
+    impl<RecordType: PartialEq + Eq + Hash, KeyType: Eq + Hash> Hash for BKTreeNode<RecordType, KeyType> {
         fn hash<H: Hasher>(&self, state: &mut H) {
             self.value.hash(state);
             for r in &self.records_found_in {
@@ -555,15 +1273,15 @@ pub mod fuzzy_matching {
         }
     }
 
-    impl<RecordType: PartialEq + Eq + Hash> PartialEq for BKTreeNode<RecordType> {
+    impl<RecordType: PartialEq + Eq + Hash, KeyType: Eq + Hash> PartialEq for BKTreeNode<RecordType, KeyType> {
         fn eq(&self, other: &Self) -> bool {
             self.value == other.value && self.records_found_in == other.records_found_in
         }
     }
 
-    impl<RecordType: PartialEq + Eq + Hash> Eq for BKTreeNode<RecordType> {}
+    impl<RecordType: PartialEq + Eq + Hash, KeyType: Eq + Hash> Eq for BKTreeNode<RecordType, KeyType> {}
 
-    #[derive(Debug)]
+    #[derive(Debug, Default)]
     pub struct OsaEditDistanceCalculator {}
 
     impl OsaEditDistanceCalculator {
@@ -580,17 +1298,25 @@ pub mod fuzzy_matching {
     //     };
     // }
 
-    impl EditDistanceCalculator for OsaEditDistanceCalculator {
+    impl DistanceAlgorithm for OsaEditDistanceCalculator {
         fn algorithm_name(&self) -> &'static str {
             return "Optimal String Alignment";
         }
+    }
 
-        fn get_edit_distance(&self, item1: &String, item2: &String) -> EditDistance {
-            osa_distance(&item1.to_string(), &item2.to_string())
+    impl EditDistanceCalculator<str> for OsaEditDistanceCalculator {
+        fn get_edit_distance(&self, item1: &str, item2: &str) -> EditDistance {
+            osa_distance(item1, item2)
+        }
+
+        fn get_bounded_edit_distance(&self, item1: &str, item2: &str, max_distance: EditDistance) -> Option<EditDistance> {
+            let chars1: Vec<char> = item1.chars().collect();
+            let chars2: Vec<char> = item2.chars().collect();
+            bounded_generic_distance(&chars1, &chars2, max_distance, true)
         }
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Default)]
     pub struct LevenshteinEditDistanceCalculator {}
 
     impl LevenshteinEditDistanceCalculator {
@@ -600,13 +1326,26 @@ pub mod fuzzy_matching {
         }
     }
 
-    impl EditDistanceCalculator for LevenshteinEditDistanceCalculator {
+    impl DistanceAlgorithm for LevenshteinEditDistanceCalculator {
         fn algorithm_name(&self) -> &'static str {
             return "Levenshtein";
         }
+    }
 
-        fn get_edit_distance(&self, item1: &String, item2: &String) -> EditDistance {
-            levenshtein(&item1.to_string(), &item2.to_string())
+    impl EditDistanceCalculator<str> for LevenshteinEditDistanceCalculator {
+        fn get_edit_distance(&self, item1: &str, item2: &str) -> EditDistance {
+            levenshtein(item1, item2)
+        }
+
+        fn get_bounded_edit_distance(&self, item1: &str, item2: &str, max_distance: EditDistance) -> Option<EditDistance> {
+            let chars1: Vec<char> = item1.chars().collect();
+            let chars2: Vec<char> = item2.chars().collect();
+            let (pattern, text) = if chars1.len() <= chars2.len() { (chars1, chars2) } else { (chars2, chars1) };
+            if pattern.len() <= 64 {
+                bounded_myers_levenshtein(&pattern, &text, max_distance)
+            } else {
+                bounded_generic_distance(&pattern, &text, max_distance, false)
+            }
         }
     }
 
@@ -616,228 +1355,8332 @@ pub mod fuzzy_matching {
     //         Rc::new(ret_val)
     //     };
     // }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::collections::{HashSet, HashMap};
-    use std::rc::Rc;
-    use std::str::FromStr;
-    use super::entity::*;
-    use super::fuzzy_matching::*;
-    use super::health_insurance::*;
-    use super::human::*;
+    /// Splits `s` into extended grapheme clusters: what a reader sees as one character, such as "e" plus a
+    /// combining accent or a multi-code-point emoji.
+    pub fn graphemes(s: &str) -> Vec<&str> {
+        s.graphemes(true).collect()
+    }
+
+    /// Optimal string alignment distance over arbitrary sequences: Levenshtein plus adjacent transpositions, with
+    /// no substring edited more than once.
+    pub fn generic_osa_distance<Elem: PartialEq>(a: &[Elem], b: &[Elem]) -> EditDistance {
+        let b_len = b.len();
+        let mut prev_two_distances: Vec<EditDistance> = (0..=b_len).collect();
+        let mut prev_distances: Vec<EditDistance> = (0..=b_len).collect();
+        let mut curr_distances: Vec<EditDistance> = vec![0; b_len + 1];
+        for i in 0..a.len() {
+            curr_distances[0] = i + 1;
+            for j in 0..b_len {
+                let cost = if a[i] == b[j] { 0 } else { 1 };
+                curr_distances[j + 1] = (prev_distances[j + 1] + 1).min(curr_distances[j] + 1).min(prev_distances[j] + cost);
+                if i > 0 && j > 0 && a[i] == b[j - 1] && a[i - 1] == b[j] {
+                    curr_distances[j + 1] = curr_distances[j + 1].min(prev_two_distances[j - 1] + 1);
+                }
+            }
+            std::mem::swap(&mut prev_two_distances, &mut prev_distances);
+            std::mem::swap(&mut prev_distances, &mut curr_distances);
+        }
+        prev_distances[b_len]
+    }
+
+    /// Levenshtein (or, with `transpositions`, optimal string alignment) distance over arbitrary sequences, if it is
+    /// at most `max_distance`. Only the diagonal band of width `2 * max_distance + 1` is computed (Ukkonen's cutoff),
+    /// and the computation stops as soon as a whole row exceeds the bound.
+    pub fn bounded_generic_distance<Elem: PartialEq>(a: &[Elem], b: &[Elem], max_distance: EditDistance, transpositions: bool) -> Option<EditDistance> {
+        let (a_len, b_len) = (a.len(), b.len());
+        if a_len.abs_diff(b_len) > max_distance {
+            return None;
+        }
+        if a_len == 0 || b_len == 0 {
+            return Some(a_len.max(b_len));
+        }
+        // Any value above the bound is as good as another, so cells saturate here
+        let over = max_distance.saturating_add(1);
+        let mut prev_two_distances: Vec<EditDistance> = vec![over; b_len + 1];
+        let mut prev_distances: Vec<EditDistance> = (0..=b_len).map(|j| j.min(over)).collect();
+        let mut curr_distances: Vec<EditDistance> = vec![over; b_len + 1];
+        for i in 1..=a_len {
+            let lo = i.saturating_sub(max_distance).max(1);
+            let hi = i.saturating_add(max_distance).min(b_len);
+            // The cells just outside the band may hold values from an older row; the next rows read them
+            curr_distances[lo - 1] = if lo == 1 { i.min(over) } else { over };
+            if hi < b_len {
+                curr_distances[hi + 1] = over;
+            }
+            let mut row_min = curr_distances[lo - 1];
+            for j in lo..=hi {
+                let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+                let mut dist = (prev_distances[j - 1] + cost).min(prev_distances[j] + 1).min(curr_distances[j - 1] + 1);
+                if transpositions && i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                    dist = dist.min(prev_two_distances[j - 2] + 1);
+                }
+                curr_distances[j] = dist.min(over);
+                row_min = row_min.min(curr_distances[j]);
+            }
+            if row_min > max_distance {
+                return None;
+            }
+            std::mem::swap(&mut prev_two_distances, &mut prev_distances);
+            std::mem::swap(&mut prev_distances, &mut curr_distances);
+        }
+        let dist = prev_distances[b_len];
+        if dist <= max_distance { Some(dist) } else { None }
+    }
+
+    /// Levenshtein distance between `pattern` (at most 64 `char`s) and `text`, if it is at most `max_distance`,
+    /// using Myers' bit-parallel algorithm in Hyyrö's formulation: one column of the DP matrix per word operation.
+    /// The computation stops once even matching every remaining `char` of `text` could not bring it back in bound.
+    pub fn bounded_myers_levenshtein(pattern: &[char], text: &[char], max_distance: EditDistance) -> Option<EditDistance> {
+        assert!(pattern.len() <= 64, "Myers' algorithm needs a pattern of at most 64 chars");
+        let pattern_len = pattern.len();
+        if pattern_len.abs_diff(text.len()) > max_distance {
+            return None;
+        }
+        if pattern_len == 0 {
+            return Some(text.len());
+        }
+        let mut ascii_masks = [0u64; 128];
+        let mut other_masks = HashMap::<char, u64>::new();
+        for (i, c) in pattern.iter().enumerate() {
+            if c.is_ascii() {
+                ascii_masks[*c as usize] |= 1 << i;
+            } else {
+                *other_masks.entry(*c).or_insert(0) |= 1 << i;
+            }
+        }
+        let last_bit = 1u64 << (pattern_len - 1);
+        let mut positive_vertical = !0u64;
+        let mut negative_vertical = 0u64;
+        let mut score = pattern_len;
+        for (j, c) in text.iter().enumerate() {
+            let eq = if c.is_ascii() { ascii_masks[*c as usize] } else { other_masks.get(c).copied().unwrap_or(0) };
+            let x_vertical = eq | negative_vertical;
+            let x_horizontal = ((eq & positive_vertical).wrapping_add(positive_vertical) ^ positive_vertical) | eq;
+            let mut positive_horizontal = negative_vertical | !(x_horizontal | positive_vertical);
+            let mut negative_horizontal = positive_vertical & x_horizontal;
+            if positive_horizontal & last_bit != 0 {
+                score += 1;
+            } else if negative_horizontal & last_bit != 0 {
+                score -= 1;
+            }
+            if score.saturating_sub(text.len() - j - 1) > max_distance {
+                return None;
+            }
+            // Shifting in a 1 accounts for the first row of the matrix (D[0][j] = j): this is a global distance
+            positive_horizontal = (positive_horizontal << 1) | 1;
+            negative_horizontal <<= 1;
+            positive_vertical = negative_horizontal | !(x_vertical | positive_horizontal);
+            negative_vertical = positive_horizontal & x_vertical;
+        }
+        if score <= max_distance { Some(score) } else { None }
+    }
+
+    /// Levenshtein distance counted in grapheme clusters rather than `char`s.
+    #[derive(Debug, Default)]
+    pub struct GraphemeLevenshteinEditDistanceCalculator {}
+
+    impl GraphemeLevenshteinEditDistanceCalculator {
+        pub fn new() -> Self {
+            let ret_val = GraphemeLevenshteinEditDistanceCalculator {};
+            ret_val
+        }
+    }
+
+    impl DistanceAlgorithm for GraphemeLevenshteinEditDistanceCalculator {
+        fn algorithm_name(&self) -> &'static str {
+            return "Grapheme Levenshtein";
+        }
+    }
+
+    impl EditDistanceCalculator<str> for GraphemeLevenshteinEditDistanceCalculator {
+        fn get_edit_distance(&self, item1: &str, item2: &str) -> EditDistance {
+            generic_levenshtein(&graphemes(item1), &graphemes(item2))
+        }
+
+        fn get_bounded_edit_distance(&self, item1: &str, item2: &str, max_distance: EditDistance) -> Option<EditDistance> {
+            bounded_generic_distance(&graphemes(item1), &graphemes(item2), max_distance, false)
+        }
+    }
+
+    /// Optimal string alignment distance counted in grapheme clusters rather than `char`s.
+    #[derive(Debug, Default)]
+    pub struct GraphemeOsaEditDistanceCalculator {}
+
+    impl GraphemeOsaEditDistanceCalculator {
+        pub fn new() -> Self {
+            let ret_val = GraphemeOsaEditDistanceCalculator {};
+            ret_val
+        }
+    }
+
+    impl DistanceAlgorithm for GraphemeOsaEditDistanceCalculator {
+        fn algorithm_name(&self) -> &'static str {
+            return "Grapheme Optimal String Alignment";
+        }
+    }
+
+    impl EditDistanceCalculator<str> for GraphemeOsaEditDistanceCalculator {
+        fn get_edit_distance(&self, item1: &str, item2: &str) -> EditDistance {
+            generic_osa_distance(&graphemes(item1), &graphemes(item2))
+        }
+
+        fn get_bounded_edit_distance(&self, item1: &str, item2: &str, max_distance: EditDistance) -> Option<EditDistance> {
+            bounded_generic_distance(&graphemes(item1), &graphemes(item2), max_distance, true)
+        }
+    }
+
+    /// Unrestricted Damerau-Levenshtein distance counted in grapheme clusters. Unlike OSA, a transposed pair may be
+    /// edited again, so "ca" to "abc" is 2 rather than 3.
+    #[derive(Debug, Default)]
+    pub struct GraphemeDamerauLevenshteinEditDistanceCalculator {}
+
+    impl GraphemeDamerauLevenshteinEditDistanceCalculator {
+        pub fn new() -> Self {
+            let ret_val = GraphemeDamerauLevenshteinEditDistanceCalculator {};
+            ret_val
+        }
+    }
+
+    impl DistanceAlgorithm for GraphemeDamerauLevenshteinEditDistanceCalculator {
+        fn algorithm_name(&self) -> &'static str {
+            return "Grapheme Damerau-Levenshtein";
+        }
+    }
+
+    impl EditDistanceCalculator<str> for GraphemeDamerauLevenshteinEditDistanceCalculator {
+        fn get_edit_distance(&self, item1: &str, item2: &str) -> EditDistance {
+            generic_damerau_levenshtein(&graphemes(item1), &graphemes(item2))
+        }
+    }
+
+    /// Levenshtein distance over byte strings, such as phonetic codes stored as bytes.
+    #[derive(Debug, Default)]
+    pub struct ByteLevenshteinEditDistanceCalculator {}
+
+    impl ByteLevenshteinEditDistanceCalculator {
+        pub fn new() -> Self {
+            let ret_val = ByteLevenshteinEditDistanceCalculator {};
+            ret_val
+        }
+    }
+
+    impl DistanceAlgorithm for ByteLevenshteinEditDistanceCalculator {
+        fn algorithm_name(&self) -> &'static str {
+            return "Byte Levenshtein";
+        }
+    }
+
+    impl EditDistanceCalculator<Vec<u8>> for ByteLevenshteinEditDistanceCalculator {
+        fn get_edit_distance(&self, item1: &Vec<u8>, item2: &Vec<u8>) -> EditDistance {
+            generic_levenshtein(item1, item2)
+        }
+
+        fn get_bounded_edit_distance(&self, item1: &Vec<u8>, item2: &Vec<u8>, max_distance: EditDistance) -> Option<EditDistance> {
+            bounded_generic_distance(item1, item2, max_distance, false)
+        }
+    }
+
+    /// Levenshtein distance over token vectors: each inserted, deleted or replaced token costs 1.
+    #[derive(Debug, Default)]
+    pub struct TokenLevenshteinEditDistanceCalculator {}
+
+    impl TokenLevenshteinEditDistanceCalculator {
+        pub fn new() -> Self {
+            let ret_val = TokenLevenshteinEditDistanceCalculator {};
+            ret_val
+        }
+    }
+
+    impl DistanceAlgorithm for TokenLevenshteinEditDistanceCalculator {
+        fn algorithm_name(&self) -> &'static str {
+            return "Token Levenshtein";
+        }
+    }
+
+    impl EditDistanceCalculator<Vec<String>> for TokenLevenshteinEditDistanceCalculator {
+        fn get_edit_distance(&self, item1: &Vec<String>, item2: &Vec<String>) -> EditDistance {
+            generic_levenshtein(item1, item2)
+        }
+
+        fn get_bounded_edit_distance(&self, item1: &Vec<String>, item2: &Vec<String>, max_distance: EditDistance) -> Option<EditDistance> {
+            bounded_generic_distance(item1, item2, max_distance, false)
+        }
+    }
+
+    /// Absolute difference between numeric codes.
+    #[derive(Debug, Default)]
+    pub struct NumericDistanceCalculator {}
+
+    impl NumericDistanceCalculator {
+        pub fn new() -> Self {
+            let ret_val = NumericDistanceCalculator {};
+            ret_val
+        }
+    }
+
+    impl DistanceAlgorithm for NumericDistanceCalculator {
+        fn algorithm_name(&self) -> &'static str {
+            return "Numeric Difference";
+        }
+    }
+
+    impl EditDistanceCalculator<u64> for NumericDistanceCalculator {
+        fn get_edit_distance(&self, item1: &u64, item2: &u64) -> EditDistance {
+            EditDistance::try_from(item1.abs_diff(*item2)).unwrap_or(EditDistance::MAX)
+        }
+    }
+
+    impl EditDistanceCalculator<i64> for NumericDistanceCalculator {
+        fn get_edit_distance(&self, item1: &i64, item2: &i64) -> EditDistance {
+            EditDistance::try_from(item1.abs_diff(*item2)).unwrap_or(EditDistance::MAX)
+        }
+    }
+
+    /// Number of positions at which two fixed-width codes (ZIP codes, SSN digits) differ. Any difference in length
+    /// is added, so the distance stays a metric for codes of unequal width.
+    #[derive(Debug, Default)]
+    pub struct HammingEditDistanceCalculator {}
+
+    impl HammingEditDistanceCalculator {
+        pub fn new() -> Self {
+            let ret_val = HammingEditDistanceCalculator {};
+            ret_val
+        }
+    }
+
+    impl DistanceAlgorithm for HammingEditDistanceCalculator {
+        fn algorithm_name(&self) -> &'static str {
+            return "Hamming";
+        }
+    }
+
+    impl EditDistanceCalculator<str> for HammingEditDistanceCalculator {
+        fn get_edit_distance(&self, item1: &str, item2: &str) -> EditDistance {
+            let chars1: Vec<char> = item1.chars().collect();
+            let chars2: Vec<char> = item2.chars().collect();
+            let mismatches = chars1.iter().zip(chars2.iter()).filter(|(c1, c2)| c1 != c2).count();
+            mismatches + chars1.len().abs_diff(chars2.len())
+        }
+    }
+
+    /// Days since 1970-01-01 in the proleptic Gregorian calendar.
+    fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+        let year = if month <= 2 { year - 1 } else { year };
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let month = month as i64;
+        let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146097 + day_of_era - 719468
+    }
+
+    /// Largest number of days apart at which two birth dates still count as close.
+    pub const DEFAULT_BIRTH_DATE_MAX_DAYS: EditDistance = 7;
+
+    /// Number of days between two dates, a metric, so dates can be indexed in a `BKTree`. Only `CompleteDate`s are
+    /// accepted: a partial date has no single position on the calendar (1980 is no nearer 1980-01-03 than
+    /// 1980-12-30). Compare partial dates with `BirthDateComparator`, which looks only at the components both know.
+    #[derive(Debug, Default)]
+    pub struct DateDistanceCalculator {}
+
+    impl DateDistanceCalculator {
+        pub fn new() -> Self {
+            let ret_val = DateDistanceCalculator {};
+            ret_val
+        }
+
+        fn day_number(date: &CompleteDate) -> i64 {
+            days_from_civil(date.get_year(), date.get_month(), date.get_day())
+        }
+    }
+
+    impl DistanceAlgorithm for DateDistanceCalculator {
+        fn algorithm_name(&self) -> &'static str {
+            return "Date Difference in Days";
+        }
+    }
+
+    impl EditDistanceCalculator<CompleteDate> for DateDistanceCalculator {
+        fn get_edit_distance(&self, item1: &CompleteDate, item2: &CompleteDate) -> EditDistance {
+            EditDistance::try_from(DateDistanceCalculator::day_number(item1).abs_diff(DateDistanceCalculator::day_number(item2))).unwrap_or(EditDistance::MAX)
+        }
+    }
+
+    /// Cost of a plain edit in `EditCosts::new()`. Costs are integers so weighted distances remain usable as
+    /// `EditDistance`s (in BK-trees, for instance); the default of 10 leaves room for cheaper likely edits.
+    pub const DEFAULT_EDIT_COST: EditDistance = 10;
+    pub const DEFAULT_KEYBOARD_ADJACENT_COST: EditDistance = 5;
+    pub const DEFAULT_OCR_CONFUSION_COST: EditDistance = 3;
+
+    const QWERTY_ROWS: [&str; 4] = ["1234567890-=", "qwertyuiop[]", "asdfghjkl;'", "zxcvbnm,./"];
+
+    /// Character pairs (or short sequences) that OCR commonly confuses.
+    pub const OCR_CONFUSIONS: &[(&str, &str)] = &[
+        ("rn", "m"), ("cl", "d"), ("vv", "w"), ("ii", "u"), ("nn", "m"),
+        ("0", "O"), ("0", "o"), ("0", "D"), ("O", "D"), ("O", "Q"),
+        ("1", "l"), ("1", "I"), ("l", "I"), ("1", "i"), ("l", "i"),
+        ("2", "Z"), ("5", "S"), ("6", "G"), ("6", "b"), ("8", "B"), ("9", "g"), ("9", "q"),
+        ("c", "e"), ("h", "b"), ("u", "v"), ("n", "h"),
+    ];
+
+    /// Pairs of keys that are next to each other on a US QWERTY keyboard, including the diagonal neighbours of
+    /// the staggered rows. Letters are lowercase.
+    pub fn qwerty_adjacent_keys() -> Vec<(char, char)> {
+        let rows: Vec<Vec<char>> = QWERTY_ROWS.iter().map(|row| row.chars().collect()).collect();
+        let mut ret_val = Vec::new();
+        for (r, row) in rows.iter().enumerate() {
+            for (c, &key) in row.iter().enumerate() {
+                if let Some(&right) = row.get(c + 1) {
+                    ret_val.push((key, right));
+                }
+                // Each row sits half a key to the right of the one above it
+                if let Some(below) = rows.get(r + 1) {
+                    if c > 0 {
+                        if let Some(&below_left) = below.get(c - 1) {
+                            ret_val.push((key, below_left));
+                        }
+                    }
+                    if let Some(&below_right) = below.get(c) {
+                        ret_val.push((key, below_right));
+                    }
+                }
+            }
+        }
+        ret_val
+    }
+
+    /// Per-operation costs for `WeightedEditDistanceCalculator`. Substitution costs for specific pairs, which may
+    /// be multi-character ("rn" read as "m"), override the flat substitution cost and apply in both directions.
+    #[derive(Debug, Clone)]
+    pub struct EditCosts {
+        insertion_cost: EditDistance,
+        deletion_cost: EditDistance,
+        substitution_cost: EditDistance,
+        transposition_cost: EditDistance,
+        char_substitution_costs: HashMap<(char, char), EditDistance>,
+        sequence_substitution_costs: Vec<(Vec<char>, Vec<char>, EditDistance)>,
+    }
+
+    impl EditCosts {
+        /// Every operation costs `DEFAULT_EDIT_COST`.
+        pub fn new() -> Self {
+            EditCosts::uniform(DEFAULT_EDIT_COST)
+        }
+
+        pub fn uniform(cost: EditDistance) -> Self {
+            let ret_val = EditCosts {
+                insertion_cost: cost,
+                deletion_cost: cost,
+                substitution_cost: cost,
+                transposition_cost: cost,
+                char_substitution_costs: HashMap::new(),
+                sequence_substitution_costs: Vec::new(),
+            };
+            ret_val
+        }
+
+        /// Default costs, with adjacent-key substitutions costing `DEFAULT_KEYBOARD_ADJACENT_COST`.
+        pub fn qwerty_keyboard() -> Self {
+            EditCosts::new().with_qwerty_adjacency(DEFAULT_KEYBOARD_ADJACENT_COST)
+        }
+
+        /// Default costs, with `OCR_CONFUSIONS` costing `DEFAULT_OCR_CONFUSION_COST`.
+        pub fn ocr() -> Self {
+            EditCosts::new().with_ocr_confusions(DEFAULT_OCR_CONFUSION_COST)
+        }
+
+        pub fn with_insertion_cost(mut self, cost: EditDistance) -> Self {
+            self.insertion_cost = cost;
+            self
+        }
+
+        pub fn with_deletion_cost(mut self, cost: EditDistance) -> Self {
+            self.deletion_cost = cost;
+            self
+        }
+
+        pub fn with_substitution_cost(mut self, cost: EditDistance) -> Self {
+            self.substitution_cost = cost;
+            self
+        }
+
+        pub fn with_transposition_cost(mut self, cost: EditDistance) -> Self {
+            self.transposition_cost = cost;
+            self
+        }
+
+        /// Sets the cost of substituting `from` with `to` and vice versa. Either side may be several characters.
+        pub fn with_substitution(mut self, from: &str, to: &str, cost: EditDistance) -> Self {
+            let from: Vec<char> = from.chars().collect();
+            let to: Vec<char> = to.chars().collect();
+            if from.len() == 1 && to.len() == 1 {
+                self.char_substitution_costs.insert((from[0], to[0]), cost);
+                self.char_substitution_costs.insert((to[0], from[0]), cost);
+            } else if !from.is_empty() && !to.is_empty() {
+                self.sequence_substitution_costs.retain(|(f, t, _)| !((*f == from && *t == to) || (*f == to && *t == from)));
+                self.sequence_substitution_costs.push((from.clone(), to.clone(), cost));
+                self.sequence_substitution_costs.push((to, from, cost));
+            }
+            self
+        }
+
+        /// Adjacent QWERTY keys substitute for `cost`, in either letter case.
+        pub fn with_qwerty_adjacency(mut self, cost: EditDistance) -> Self {
+            for (key1, key2) in qwerty_adjacent_keys() {
+                self = self.with_substitution(&key1.to_string(), &key2.to_string(), cost);
+                if key1.is_alphabetic() && key2.is_alphabetic() {
+                    self = self.with_substitution(&key1.to_uppercase().to_string(), &key2.to_uppercase().to_string(), cost);
+                }
+            }
+            self
+        }
+
+        pub fn with_ocr_confusions(mut self, cost: EditDistance) -> Self {
+            for (from, to) in OCR_CONFUSIONS {
+                self = self.with_substitution(from, to, cost);
+            }
+            self
+        }
+
+        pub fn get_insertion_cost(&self) -> EditDistance {
+            self.insertion_cost
+        }
+
+        pub fn get_deletion_cost(&self) -> EditDistance {
+            self.deletion_cost
+        }
+
+        pub fn get_transposition_cost(&self) -> EditDistance {
+            self.transposition_cost
+        }
+
+        /// Cost of replacing one character with another: 0 for equal characters, otherwise the pair's own cost or
+        /// the flat substitution cost.
+        pub fn get_substitution_cost(&self, from: char, to: char) -> EditDistance {
+            if from == to {
+                return 0;
+            }
+            *self.char_substitution_costs.get(&(from, to)).unwrap_or(&self.substitution_cost)
+        }
+    }
+
+    impl Default for EditCosts {
+        fn default() -> Self {
+            EditCosts::new()
+        }
+    }
+
+    /// Edit distance with configurable costs per operation, over `char`s. Transpositions are of adjacent
+    /// characters, as in optimal string alignment. With insertion and deletion costs equal and symmetric
+    /// substitution costs no larger than an insertion plus a deletion, the distance is symmetric.
+    #[derive(Debug)]
+    pub struct WeightedEditDistanceCalculator {
+        costs: EditCosts,
+    }
+
+    impl WeightedEditDistanceCalculator {
+        pub fn new(costs: EditCosts) -> Self {
+            let ret_val = WeightedEditDistanceCalculator { costs: costs };
+            ret_val
+        }
+
+        pub fn get_costs(&self) -> &EditCosts {
+            &self.costs
+        }
+    }
+
+    impl DistanceAlgorithm for WeightedEditDistanceCalculator {
+        fn algorithm_name(&self) -> &'static str {
+            return "Weighted Edit Distance";
+        }
+
+        fn algorithm_identity(&self) -> String {
+            let costs = &self.costs;
+            let mut char_costs: Vec<(&(char, char), &EditDistance)> = costs.char_substitution_costs.iter().collect();
+            char_costs.sort();
+            format!("{} (insertion {}, deletion {}, substitution {}, transposition {}, pairs {:?}, sequences {:?})",
+                    self.algorithm_name(), costs.insertion_cost, costs.deletion_cost, costs.substitution_cost,
+                    costs.transposition_cost, char_costs, costs.sequence_substitution_costs)
+        }
+    }
+
+    impl EditDistanceCalculator<str> for WeightedEditDistanceCalculator {
+        fn get_edit_distance(&self, item1: &str, item2: &str) -> EditDistance {
+            let a: Vec<char> = item1.chars().collect();
+            let b: Vec<char> = item2.chars().collect();
+            let costs = &self.costs;
+            let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+            for i in 1..=a.len() {
+                distances[i][0] = distances[i - 1][0] + costs.deletion_cost;
+            }
+            for j in 1..=b.len() {
+                distances[0][j] = distances[0][j - 1] + costs.insertion_cost;
+            }
+            for i in 1..=a.len() {
+                for j in 1..=b.len() {
+                    let mut best = (distances[i - 1][j] + costs.deletion_cost)
+                        .min(distances[i][j - 1] + costs.insertion_cost)
+                        .min(distances[i - 1][j - 1] + costs.get_substitution_cost(a[i - 1], b[j - 1]));
+                    if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] && a[i - 1] != b[j - 1] {
+                        best = best.min(distances[i - 2][j - 2] + costs.transposition_cost);
+                    }
+                    for (from, to, cost) in &costs.sequence_substitution_costs {
+                        if from.len() <= i && to.len() <= j && a[i - from.len()..i] == from[..] && b[j - to.len()..j] == to[..] {
+                            best = best.min(distances[i - from.len()][j - to.len()] + cost);
+                        }
+                    }
+                    distances[i][j] = best;
+                }
+            }
+            distances[a.len()][b.len()]
+        }
+    }
+
+    /// Remembers the distances of recently compared pairs, evicting the least recently used, for workloads that
+    /// compare the same values again and again (frequent names against a large index, say). Pairs are keyed in
+    /// the order given, since weighted calculators need not be symmetric. The cache sits behind a mutex, so threads
+    /// sharing the calculator share the cache too.
+    ///
+    /// Lookups go by a hash of the borrowed pair, so values are only cloned when a missed pair is added. Two pairs
+    /// with the same hash share one slot: the later replaces the earlier, which is then simply a miss.
+    #[derive(Debug)]
+    pub struct CachingEditDistanceCalculator<InnerCalc: EditDistanceCalculator<Key>, Key: Clone + Eq + Hash = String> {
+        inner_calculator: Arc<InnerCalc>,
+        cache: Mutex<LruCache<u64, (Key, Key, EditDistance)>>,
+        hash_builder: RandomState,
+        hit_count: AtomicUsize,
+        miss_count: AtomicUsize,
+    }
+
+    impl<InnerCalc: EditDistanceCalculator<Key>, Key: Clone + Eq + Hash> CachingEditDistanceCalculator<InnerCalc, Key> {
+        /// Caches up to `capacity` pairs (at least one).
+        pub fn new(inner_calculator: Arc<InnerCalc>, capacity: usize) -> Self {
+            let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+            let ret_val = CachingEditDistanceCalculator { inner_calculator: inner_calculator, cache: Mutex::new(LruCache::new(capacity)), hash_builder: RandomState::new(), hit_count: AtomicUsize::new(0), miss_count: AtomicUsize::new(0) };
+            ret_val
+        }
+
+        pub fn get_inner_calculator(&self) -> Arc<InnerCalc> {
+            Arc::clone(&self.inner_calculator)
+        }
+
+        pub fn get_capacity(&self) -> usize {
+            self.cache.lock().unwrap().cap().get()
+        }
+
+        /// Number of lookups answered from the cache.
+        pub fn get_hit_count(&self) -> usize {
+            self.hit_count.load(AtomicOrdering::Relaxed)
+        }
+
+        /// Number of lookups that had to ask the inner calculator.
+        pub fn get_miss_count(&self) -> usize {
+            self.miss_count.load(AtomicOrdering::Relaxed)
+        }
+
+        pub fn clear(&self) {
+            self.cache.lock().unwrap().clear();
+        }
+
+        fn pair_hash(&self, item1: &Key, item2: &Key) -> u64 {
+            let mut hasher = self.hash_builder.build_hasher();
+            item1.hash(&mut hasher);
+            item2.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        fn cached_distance(&self, pair_hash: u64, item1: &Key, item2: &Key) -> Option<EditDistance> {
+            let cached = match self.cache.lock().unwrap().get(&pair_hash) {
+                Some((cached1, cached2, dist)) if cached1 == item1 && cached2 == item2 => Some(*dist),
+                _                                                                      => None,
+            };
+            match cached {
+                Some(_) => self.hit_count.fetch_add(1, AtomicOrdering::Relaxed),
+                None    => self.miss_count.fetch_add(1, AtomicOrdering::Relaxed),
+            };
+            cached
+        }
+    }
+
+    impl<InnerCalc: EditDistanceCalculator<Key>, Key: Clone + Eq + Hash> DistanceAlgorithm for CachingEditDistanceCalculator<InnerCalc, Key> {
+        /// The inner calculator's name: caching does not change any distance.
+        fn algorithm_name(&self) -> &'static str {
+            self.inner_calculator.algorithm_name()
+        }
+
+        fn algorithm_identity(&self) -> String {
+            self.inner_calculator.algorithm_identity()
+        }
+    }
+
+    impl<InnerCalc: EditDistanceCalculator<Key>, Key: Clone + Eq + Hash> EditDistanceCalculator<Key> for CachingEditDistanceCalculator<InnerCalc, Key> {
+        fn get_edit_distance(&self, item1: &Key, item2: &Key) -> EditDistance {
+            let pair_hash = self.pair_hash(item1, item2);
+            if let Some(dist) = self.cached_distance(pair_hash, item1, item2) {
+                return dist;
+            }
+            // The lock is not held while computing, so a slow comparison does not block other threads
+            let dist = self.inner_calculator.get_edit_distance(item1, item2);
+            self.cache.lock().unwrap().put(pair_hash, (item1.clone(), item2.clone(), dist));
+            dist
+        }
+
+        fn get_bounded_edit_distance(&self, item1: &Key, item2: &Key, max_distance: EditDistance) -> Option<EditDistance> {
+            let pair_hash = self.pair_hash(item1, item2);
+            if let Some(dist) = self.cached_distance(pair_hash, item1, item2) {
+                return if dist <= max_distance { Some(dist) } else { None };
+            }
+            // Only exact distances are cached; a bound being exceeded says nothing about a larger bound
+            let dist = self.inner_calculator.get_bounded_edit_distance(item1, item2, max_distance);
+            if let Some(dist) = dist {
+                self.cache.lock().unwrap().put(pair_hash, (item1.clone(), item2.clone(), dist));
+            }
+            dist
+        }
+    }
+
+    #[derive(Debug, Default)]
+    pub struct JaroWinklerSimilarityCalculator {}
+
+    impl JaroWinklerSimilarityCalculator {
+        pub fn new() -> Self {
+            let ret_val = JaroWinklerSimilarityCalculator {};
+            ret_val
+        }
+    }
+
+    impl SimilarityCalculator for JaroWinklerSimilarityCalculator {
+        fn algorithm_name(&self) -> &'static str {
+            return "Jaro-Winkler";
+        }
+
+        fn get_similarity(&self, item1: &str, item2: &str) -> Similarity {
+            jaro_winkler(item1, item2)
+        }
+    }
+
+    #[derive(Debug, Default)]
+    pub struct SorensenDiceSimilarityCalculator {}
+
+    impl SorensenDiceSimilarityCalculator {
+        pub fn new() -> Self {
+            let ret_val = SorensenDiceSimilarityCalculator {};
+            ret_val
+        }
+    }
+
+    impl SimilarityCalculator for SorensenDiceSimilarityCalculator {
+        fn algorithm_name(&self) -> &'static str {
+            return "Sørensen-Dice";
+        }
+
+        fn get_similarity(&self, item1: &str, item2: &str) -> Similarity {
+            sorensen_dice(item1, item2)
+        }
+    }
+
+    #[derive(Debug, Default)]
+    pub struct NormalizedLevenshteinSimilarityCalculator {}
+
+    impl NormalizedLevenshteinSimilarityCalculator {
+        pub fn new() -> Self {
+            let ret_val = NormalizedLevenshteinSimilarityCalculator {};
+            ret_val
+        }
+    }
+
+    impl SimilarityCalculator for NormalizedLevenshteinSimilarityCalculator {
+        fn algorithm_name(&self) -> &'static str {
+            return "Normalized Levenshtein";
+        }
+
+        fn get_similarity(&self, item1: &str, item2: &str) -> Similarity {
+            normalized_levenshtein(item1, item2)
+        }
+    }
+
+    /// Splits on whitespace and punctuation and lowercases, so "DOE, Jane" and "jane doe" give the same tokens.
+    pub fn tokenize(s: &str) -> Vec<String> {
+        s.unicode_words().map(|w| w.to_lowercase()).collect()
+    }
+
+    /// Jaccard index of the two strings' token sets. Two strings without any tokens are identical.
+    #[derive(Debug, Default)]
+    pub struct TokenJaccardSimilarityCalculator {}
+
+    impl TokenJaccardSimilarityCalculator {
+        pub fn new() -> Self {
+            let ret_val = TokenJaccardSimilarityCalculator {};
+            ret_val
+        }
+    }
+
+    impl SimilarityCalculator for TokenJaccardSimilarityCalculator {
+        fn algorithm_name(&self) -> &'static str {
+            return "Token Jaccard";
+        }
+
+        fn get_similarity(&self, item1: &str, item2: &str) -> Similarity {
+            let tokens1: HashSet<String> = tokenize(item1).into_iter().collect();
+            let tokens2: HashSet<String> = tokenize(item2).into_iter().collect();
+            let union = tokens1.union(&tokens2).count();
+            if union == 0 {
+                return 1.0;
+            }
+            tokens1.intersection(&tokens2).count() as Similarity / union as Similarity
+        }
+    }
+
+    /// Monge-Elkan: each token of one string is matched with its most similar token in the other, using an inner
+    /// calculator, and the best scores are averaged. The classic measure only looks from the first string to the
+    /// second; the symmetric variant averages both directions, so extra tokens on either side count against it.
+    #[derive(Debug)]
+    pub struct MongeElkanSimilarityCalculator<InnerCalc: SimilarityCalculator> {
+        inner_calculator: Arc<InnerCalc>,
+        symmetric: bool,
+    }
+
+    impl<InnerCalc: SimilarityCalculator> MongeElkanSimilarityCalculator<InnerCalc> {
+        pub fn new(inner_calculator: Arc<InnerCalc>) -> Self {
+            let ret_val = MongeElkanSimilarityCalculator { inner_calculator: inner_calculator, symmetric: false };
+            ret_val
+        }
+
+        pub fn new_symmetric(inner_calculator: Arc<InnerCalc>) -> Self {
+            let ret_val = MongeElkanSimilarityCalculator { inner_calculator: inner_calculator, symmetric: true };
+            ret_val
+        }
+
+        fn directed_similarity(&self, tokens1: &[String], tokens2: &[String]) -> Similarity {
+            let mut total = 0.0;
+            for t1 in tokens1 {
+                total += tokens2.iter().map(|t2| self.inner_calculator.get_similarity(t1, t2)).fold(0.0, Similarity::max);
+            }
+            total / tokens1.len() as Similarity
+        }
+    }
+
+    impl<InnerCalc: SimilarityCalculator> SimilarityCalculator for MongeElkanSimilarityCalculator<InnerCalc> {
+        fn algorithm_name(&self) -> &'static str {
+            return "Monge-Elkan";
+        }
+
+        fn get_similarity(&self, item1: &str, item2: &str) -> Similarity {
+            let tokens1 = tokenize(item1);
+            let tokens2 = tokenize(item2);
+            match (tokens1.is_empty(), tokens2.is_empty()) {
+                (true, true)                  => return 1.0,
+                (true, false) | (false, true) => return 0.0,
+                (false, false)                => {},
+            }
+            if self.symmetric {
+                (self.directed_similarity(&tokens1, &tokens2) + self.directed_similarity(&tokens2, &tokens1)) / 2.0
+            } else {
+                self.directed_similarity(&tokens1, &tokens2)
+            }
+        }
+    }
+
+    /// Normalized Levenshtein similarity after sorting each string's tokens, so word order does not matter.
+    #[derive(Debug, Default)]
+    pub struct TokenSortRatioSimilarityCalculator {}
+
+    impl TokenSortRatioSimilarityCalculator {
+        pub fn new() -> Self {
+            let ret_val = TokenSortRatioSimilarityCalculator {};
+            ret_val
+        }
+    }
+
+    impl SimilarityCalculator for TokenSortRatioSimilarityCalculator {
+        fn algorithm_name(&self) -> &'static str {
+            return "Token Sort Ratio";
+        }
+
+        fn get_similarity(&self, item1: &str, item2: &str) -> Similarity {
+            let mut tokens1 = tokenize(item1);
+            let mut tokens2 = tokenize(item2);
+            tokens1.sort();
+            tokens2.sort();
+            normalized_levenshtein(&tokens1.join(" "), &tokens2.join(" "))
+        }
+    }
+}
+
+pub mod bktree_storage {
+    use std::fmt;
+    use std::fs::File;
+    use std::hash::Hash;
+    use std::io::Write;
+    use std::path::Path;
+    use std::sync::Arc;
+    use memmap2::Mmap;
+    use super::fuzzy_matching::*;
+
+    // On-disk layout, all integers little-endian:
+    //
+    //   header:  magic (8 bytes) | format version (u32) | algorithm identity length (u32) | max distance (u64)
+    //            | node count (u64) | record count (u64) | root node offset (u64, NO_NODE if empty)
+    //            | algorithm identity (UTF-8)
+    //   node:    value length (u32) | record count (u32) | child count (u32) | reserved (u32)
+    //            | children: (edge distance (u64), node offset (u64)) sorted by distance
+    //            | record IDs (u64 each) | value (UTF-8)
+    //
+    // Nodes are written depth-first, parents before children, and offsets are from the start of the file.
+
+    pub const BKTREE_MAGIC: &[u8; 8] = b"HDBKTREE";
+    pub const BKTREE_FORMAT_VERSION: u32 = 1;
+    const HEADER_FIXED_SIZE: u64 = 48;
+    const NODE_FIXED_SIZE: u64 = 16;
+    const CHILD_ENTRY_SIZE: u64 = 16;
+    const RECORD_ID_SIZE: u64 = 8;
+    const NO_NODE: u64 = u64::MAX;
+
+    #[derive(Debug)]
+    pub enum BKTreeStorageErr {
+        IoError(std::io::Error),
+        BadMagic,
+        UnsupportedVersion(u32),
+        /// The file was written with a different edit distance algorithm than the one supplied to read it.
+        CalculatorMismatch { expected: String, found: String },
+        /// A length or offset points outside the file, or a value is not UTF-8; carries the offending offset.
+        Corrupt(u64),
+    }
+    pub type BKTreeStorageResult<T> = Result<T, BKTreeStorageErr>;
+
+    impl From<std::io::Error> for BKTreeStorageErr {
+        fn from(e: std::io::Error) -> Self {
+            BKTreeStorageErr::IoError(e)
+        }
+    }
+
+    impl fmt::Display for BKTreeStorageErr {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                BKTreeStorageErr::IoError(e)                             => write!(f, "I/O error: {}", e),
+                BKTreeStorageErr::BadMagic                               => write!(f, "not a BK-tree index file"),
+                BKTreeStorageErr::UnsupportedVersion(v)                  => write!(f, "unsupported BK-tree index format version {}", v),
+                BKTreeStorageErr::CalculatorMismatch { expected, found } => write!(f, "index was built with {} but {} was supplied", found, expected),
+                BKTreeStorageErr::Corrupt(offset)                        => write!(f, "BK-tree index is corrupt at offset {}", offset),
+            }
+        }
+    }
+
+    fn node_size<RecordType: PartialEq + Eq + Hash>(node: &BKTreeNode<RecordType>) -> u64 {
+        NODE_FIXED_SIZE
+            + CHILD_ENTRY_SIZE * node.get_children().len() as u64
+            + RECORD_ID_SIZE * node.get_records_found_in().len() as u64
+            + node.get_value().len() as u64
+    }
+
+    fn sorted_children<RecordType: PartialEq + Eq + Hash>(node: &BKTreeNode<RecordType>) -> Vec<(EditDistance, &BKTreeNode<RecordType>)> {
+        let mut ret_val: Vec<(EditDistance, &BKTreeNode<RecordType>)> = node.get_children().iter().map(|(d, c)| (*d, c)).collect();
+        ret_val.sort_by_key(|(d, _)| *d);
+        ret_val
+    }
+
+    /// Lists the nodes depth-first, parents before children and siblings by edge distance, together with the
+    /// positions of each node's children in that list. Kept iterative so deep trees cannot overflow the stack.
+    fn preorder_nodes<RecordType: PartialEq + Eq + Hash>(root_node: &BKTreeNode<RecordType>) -> (Vec<&BKTreeNode<RecordType>>, Vec<Vec<usize>>) {
+        let mut nodes = Vec::new();
+        let mut child_indices: Vec<Vec<usize>> = Vec::new();
+        let mut pending: Vec<(&BKTreeNode<RecordType>, Option<usize>)> = vec![(root_node, None)];
+        while let Some((node, parent_index)) = pending.pop() {
+            let index = nodes.len();
+            nodes.push(node);
+            child_indices.push(Vec::new());
+            if let Some(parent_index) = parent_index {
+                child_indices[parent_index].push(index);
+            }
+            // Pushed in reverse so the closest child is popped, and so written, first
+            pending.extend(sorted_children(node).into_iter().rev().map(|(_, child_node)| (child_node, Some(index))));
+        }
+        (nodes, child_indices)
+    }
+
+    fn write_node<W: Write, RecordType: PartialEq + Eq + Hash, F: Fn(&RecordType) -> u64>(node: &BKTreeNode<RecordType>, child_offsets: &[u64], writer: &mut W, record_id: &F) -> BKTreeStorageResult<()> {
+        let children = sorted_children(node);
+        let mut record_ids: Vec<u64> = node.get_records_found_in().iter().map(|r| record_id(r)).collect();
+        record_ids.sort_unstable();
+        let value = node.get_value();
+        writer.write_all(&(value.len() as u32).to_le_bytes())?;
+        writer.write_all(&(record_ids.len() as u32).to_le_bytes())?;
+        writer.write_all(&(children.len() as u32).to_le_bytes())?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        for ((dist, _), offset) in children.iter().zip(child_offsets) {
+            writer.write_all(&(*dist as u64).to_le_bytes())?;
+            writer.write_all(&offset.to_le_bytes())?;
+        }
+        for id in record_ids {
+            writer.write_all(&id.to_le_bytes())?;
+        }
+        writer.write_all(value.as_bytes())?;
+        Ok(())
+    }
+
+    /// Writes `tree` in the current format version. Records are stored as the IDs `record_id` gives them, so they
+    /// must be looked up again by the reader's caller. Wrap `writer` in a `BufWriter` when writing to a file.
+    pub fn write_bktree<W, RecordType, Calc, F>(tree: &BKTree<RecordType, Calc>, mut writer: W, record_id: F) -> BKTreeStorageResult<()>
+        where W: Write,
+        RecordType: PartialEq + Eq + Hash,
+        Calc: EditDistanceCalculator,
+        F: Fn(&RecordType) -> u64 {
+        let algorithm_identity = tree.get_edit_distance_calculator().algorithm_identity();
+        let (nodes, child_indices) = match tree.get_root_node() {
+            Some(root_node) => preorder_nodes(root_node),
+            None            => (Vec::new(), Vec::new()),
+        };
+        let mut offsets = Vec::with_capacity(nodes.len());
+        let mut next_offset = HEADER_FIXED_SIZE + algorithm_identity.len() as u64;
+        for node in &nodes {
+            offsets.push(next_offset);
+            next_offset += node_size(node);
+        }
+        writer.write_all(BKTREE_MAGIC)?;
+        writer.write_all(&BKTREE_FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&(algorithm_identity.len() as u32).to_le_bytes())?;
+        writer.write_all(&(tree.get_max_distance_to_consider() as u64).to_le_bytes())?;
+        writer.write_all(&(offsets.len() as u64).to_le_bytes())?;
+        writer.write_all(&(tree.len() as u64).to_le_bytes())?;
+        writer.write_all(&offsets.first().copied().unwrap_or(NO_NODE).to_le_bytes())?;
+        writer.write_all(algorithm_identity.as_bytes())?;
+        for (node, children) in nodes.iter().zip(&child_indices) {
+            let child_offsets: Vec<u64> = children.iter().map(|i| offsets[*i]).collect();
+            write_node(node, &child_offsets, &mut writer, &record_id)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// One value found by a `MappedBKTree` search, with the IDs of the records it was found in.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct MappedBKTreeMatch {
+        value: String,
+        distance: EditDistance,
+        record_ids: Vec<u64>,
+    }
+
+    impl MappedBKTreeMatch {
+        pub fn get_value(&self) -> &str {
+            &self.value
+        }
+
+        pub fn get_distance(&self) -> EditDistance {
+            self.distance
+        }
+
+        pub fn get_record_ids(&self) -> &[u64] {
+            &self.record_ids
+        }
+    }
+
+    /// Fixed-size part of a node, read straight out of the mapping.
+    struct NodeHeader {
+        value_len: u64,
+        record_count: u64,
+        child_count: u64,
+    }
+
+    /// A BK-tree index searched in place through a memory map. Only the nodes a search visits are read, so opening
+    /// is immediate however large the index is.
+    #[derive(Debug)]
+    pub struct MappedBKTree<Calc: EditDistanceCalculator<str>> {
+        mmap: Mmap,
+        edit_distance_calculator: Arc<Calc>,
+        max_distance_to_consider: EditDistance,
+        node_count: u64,
+        record_count: u64,
+        root_offset: u64,
+    }
+
+    impl<Calc: EditDistanceCalculator<str>> MappedBKTree<Calc> {
+        /// Maps the index at `path`. `edit_distance_calculator` must be the calculator the index was built with; its
+        /// `algorithm_identity` is checked against the one stored, which catches most but not every difference (see
+        /// `NameVariantEditDistanceCalculator`). The file must not be modified while it is mapped.
+        pub fn open<P: AsRef<Path>>(path: P, edit_distance_calculator: Arc<Calc>) -> BKTreeStorageResult<Self> {
+            let file = File::open(path)?;
+            // Safety: the mapping is read-only and, as documented, the file is not changed while it is open
+            let mmap = unsafe { Mmap::map(&file)? };
+            let mut ret_val = MappedBKTree { mmap: mmap, edit_distance_calculator: edit_distance_calculator, max_distance_to_consider: 0, node_count: 0, record_count: 0, root_offset: NO_NODE };
+            if ret_val.bytes(0, BKTREE_MAGIC.len() as u64).map_err(|_| BKTreeStorageErr::BadMagic)? != BKTREE_MAGIC {
+                return Err(BKTreeStorageErr::BadMagic);
+            }
+            let version = ret_val.read_u32(8)?;
+            if version != BKTREE_FORMAT_VERSION {
+                return Err(BKTreeStorageErr::UnsupportedVersion(version));
+            }
+            let identity_len = ret_val.read_u32(12)? as u64;
+            let found = ret_val.read_str(HEADER_FIXED_SIZE, identity_len)?;
+            let expected = ret_val.edit_distance_calculator.algorithm_identity();
+            if found != expected {
+                return Err(BKTreeStorageErr::CalculatorMismatch { expected: expected, found: found.to_string() });
+            }
+            ret_val.max_distance_to_consider = ret_val.read_u64(16)? as EditDistance;
+            ret_val.node_count = ret_val.read_u64(24)?;
+            ret_val.record_count = ret_val.read_u64(32)?;
+            ret_val.root_offset = ret_val.read_u64(40)?;
+            Ok(ret_val)
+        }
+
+        pub fn get_max_distance_to_consider(&self) -> EditDistance {
+            self.max_distance_to_consider
+        }
+
+        pub fn get_node_count(&self) -> u64 {
+            self.node_count
+        }
+
+        /// Number of (value, record) pairs in the index, as for `BKTree::len`.
+        pub fn len(&self) -> usize {
+            self.record_count as usize
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.record_count == 0
+        }
+
+        fn bytes(&self, offset: u64, len: u64) -> BKTreeStorageResult<&[u8]> {
+            let end = offset.checked_add(len).ok_or(BKTreeStorageErr::Corrupt(offset))?;
+            if end > self.mmap.len() as u64 {
+                return Err(BKTreeStorageErr::Corrupt(offset));
+            }
+            Ok(&self.mmap[offset as usize..end as usize])
+        }
+
+        fn read_u32(&self, offset: u64) -> BKTreeStorageResult<u32> {
+            let mut buf = [0u8; 4];
+            buf.copy_from_slice(self.bytes(offset, 4)?);
+            Ok(u32::from_le_bytes(buf))
+        }
+
+        fn read_u64(&self, offset: u64) -> BKTreeStorageResult<u64> {
+            let mut buf = [0u8; 8];
+            buf.copy_from_slice(self.bytes(offset, 8)?);
+            Ok(u64::from_le_bytes(buf))
+        }
+
+        fn read_str(&self, offset: u64, len: u64) -> BKTreeStorageResult<&str> {
+            std::str::from_utf8(self.bytes(offset, len)?).map_err(|_| BKTreeStorageErr::Corrupt(offset))
+        }
+
+        fn read_node_header(&self, offset: u64) -> BKTreeStorageResult<NodeHeader> {
+            let ret_val = NodeHeader {
+                value_len: self.read_u32(offset)? as u64,
+                record_count: self.read_u32(offset + 4)? as u64,
+                child_count: self.read_u32(offset + 8)? as u64,
+            };
+            Ok(ret_val)
+        }
+
+        /// Every indexed value within `max_distance_to_consider` of `value`, closest first, as `BKTree::search`.
+        pub fn search(&self, value: &str) -> BKTreeStorageResult<Vec<MappedBKTreeMatch>> {
+            self.search_within(value, self.max_distance_to_consider)
+        }
+
+        pub fn search_within(&self, value: &str, max_distance: EditDistance) -> BKTreeStorageResult<Vec<MappedBKTreeMatch>> {
+            let mut rtn = Vec::new();
+            let mut pending = if self.root_offset == NO_NODE { Vec::new() } else { vec![self.root_offset] };
+            // Every node is visited at most once, so a corrupt file with a cycle cannot loop forever
+            let mut visits: u64 = 0;
+            while let Some(offset) = pending.pop() {
+                visits += 1;
+                if visits > self.node_count {
+                    return Err(BKTreeStorageErr::Corrupt(offset));
+                }
+                let header = self.read_node_header(offset)?;
+                let children_offset = offset + NODE_FIXED_SIZE;
+                let records_offset = children_offset + CHILD_ENTRY_SIZE * header.child_count;
+                let value_offset = records_offset + RECORD_ID_SIZE * header.record_count;
+                // Compared in place; only values that match are copied out of the mapping
+                let node_value = self.read_str(value_offset, header.value_len)?;
+                // Children are sorted by edge distance, so the last edge bounds the distances worth computing exactly
+                let distance_bound = if header.child_count == 0 {
+                    max_distance
+                } else {
+                    let largest_edge = self.read_u64(children_offset + CHILD_ENTRY_SIZE * (header.child_count - 1))?;
+                    (largest_edge as EditDistance).saturating_add(max_distance)
+                };
+                let cur_edit_distance = match self.edit_distance_calculator.get_bounded_edit_distance(node_value, value, distance_bound) {
+                    Some(dist) => dist,
+                    None       => continue,
+                };
+                if cur_edit_distance <= max_distance && header.record_count > 0 {
+                    let record_ids = (0..header.record_count)
+                        .map(|i| self.read_u64(records_offset + RECORD_ID_SIZE * i))
+                        .collect::<BKTreeStorageResult<Vec<u64>>>()?;
+                    rtn.push(MappedBKTreeMatch { value: node_value.to_string(), distance: cur_edit_distance, record_ids: record_ids });
+                }
+                let min_edge = cur_edit_distance.saturating_sub(max_distance) as u64;
+                let max_edge = cur_edit_distance.saturating_add(max_distance) as u64;
+                for i in 0..header.child_count {
+                    let entry_offset = children_offset + CHILD_ENTRY_SIZE * i;
+                    let edge = self.read_u64(entry_offset)?;
+                    // Children are sorted by edge distance
+                    if edge > max_edge {
+                        break;
+                    }
+                    if edge >= min_edge {
+                        pending.push(self.read_u64(entry_offset + 8)?);
+                    }
+                }
+            }
+            rtn.sort_by(|a, b| a.distance.cmp(&b.distance).then_with(|| a.value.cmp(&b.value)));
+            Ok(rtn)
+        }
+    }
+}
+
+pub mod fuzzy_index {
+    use std::cmp::Ordering;
+    use std::collections::{HashMap, HashSet};
+    use std::collections::hash_map::Entry;
+    use std::hash::Hash;
+    use std::sync::Arc;
+    use super::fuzzy_matching::*;
+
+    /// A collection of values, each found in one or more records, that can be searched for values near a query.
+    /// Implemented by `BKTree`, `VPTree` and `SymSpellIndex`; which is fastest depends on the corpus and the
+    /// threshold, so code that only searches should take a `FuzzyIndex` rather than a particular index.
+    pub trait FuzzyIndex<RecordType, KeyType = String> {
+        fn index_name(&self) -> &'static str;
+        /// Adds `record_found_in` under `value`. Returns false if that record was already indexed under that value.
+        fn insert(&mut self, value: Arc<KeyType>, record_found_in: Arc<RecordType>) -> bool;
+        /// Every indexed value within `max_distance` of `value`, closest first.
+        fn search_within(&self, value: &KeyType, max_distance: EditDistance) -> Vec<BKTreeMatch<RecordType, KeyType>>;
+        fn get_max_distance_to_consider(&self) -> EditDistance;
+        /// Number of (value, record) pairs indexed.
+        fn len(&self) -> usize;
+
+        /// Largest `max_distance` that `search_within` answers from the index itself; above it, the search falls
+        /// back to comparing the query with every indexed value. `None` if the index answers any distance.
+        fn get_max_indexed_distance(&self) -> Option<EditDistance> {
+            None
+        }
+
+        fn is_empty(&self) -> bool {
+            self.len() == 0
+        }
+
+        /// Every indexed value within the index's own `max_distance_to_consider` of `value`, closest first.
+        fn search(&self, value: &KeyType) -> Vec<BKTreeMatch<RecordType, KeyType>> {
+            self.search_within(value, self.get_max_distance_to_consider())
+        }
+    }
+
+    impl<RecordType: PartialEq + Eq + Hash, EditDistanceCalc: EditDistanceCalculator<KeyType>, KeyType: Eq + Hash + Ord> FuzzyIndex<RecordType, KeyType> for BKTree<RecordType, EditDistanceCalc, KeyType> {
+        fn index_name(&self) -> &'static str {
+            return "BK-tree";
+        }
+
+        fn insert(&mut self, value: Arc<KeyType>, record_found_in: Arc<RecordType>) -> bool {
+            BKTree::insert(self, value, record_found_in)
+        }
+
+        fn search_within(&self, value: &KeyType, max_distance: EditDistance) -> Vec<BKTreeMatch<RecordType, KeyType>> {
+            BKTree::search_within(self, value, max_distance)
+        }
+
+        fn get_max_distance_to_consider(&self) -> EditDistance {
+            BKTree::get_max_distance_to_consider(self)
+        }
+
+        fn len(&self) -> usize {
+            BKTree::len(self)
+        }
+    }
+
+    type ValueGroup<RecordType, KeyType> = (Arc<KeyType>, HashSet<Arc<RecordType>>);
+
+    fn to_match<RecordType, KeyType>(value: &Arc<KeyType>, distance: EditDistance, records_found_in: &HashSet<Arc<RecordType>>) -> BKTreeMatch<RecordType, KeyType> {
+        BKTreeMatch::new(Arc::clone(value), distance, records_found_in.iter().cloned().collect())
+    }
+
+    #[derive(Debug)]
+    struct VPTreeNode<RecordType, KeyType> {
+        value: Arc<KeyType>,
+        records_found_in: HashSet<Arc<RecordType>>,
+        /// Values at most this far from `value` are under `inside`, and the rest under `outside`.
+        radius: EditDistance,
+        inside: Option<usize>,
+        outside: Option<usize>,
+    }
+
+    /// A vantage-point tree. Each node splits the values below it at the median distance from its own value, so a
+    /// search prunes whole halves of the tree rather than single edges, and degrades more gracefully than a `BKTree`
+    /// as the threshold grows. `bulk_load` builds a balanced tree; values added by `insert` are hung off the leaves,
+    /// so after heavy insertion `rebuild` restores the balance.
+    #[derive(Debug)]
+    pub struct VPTree<RecordType, EditDistanceCalc, KeyType = String>
+        where RecordType: PartialEq + Eq + Hash,
+        EditDistanceCalc: EditDistanceCalculator<KeyType>,
+        KeyType: Eq + Hash {
+            // Nodes live in one arena, root first, so neither traversal nor drop recurses
+            nodes: Vec<VPTreeNode<RecordType, KeyType>>,
+            edit_distance_calculator: Arc<EditDistanceCalc>,
+            max_distance_to_consider: EditDistance,
+            len: usize,
+    }
+
+    impl<RecordType: PartialEq + Eq + Hash, EditDistanceCalc: EditDistanceCalculator<KeyType>, KeyType: Eq + Hash + Ord> VPTree<RecordType, EditDistanceCalc, KeyType> {
+        pub fn new_empty(edit_distance_calculator: Arc<EditDistanceCalc>, max_distance_to_consider: EditDistance) -> Self {
+            let ret_val = VPTree::<RecordType, EditDistanceCalc, KeyType> { nodes: Vec::new(), edit_distance_calculator: edit_distance_calculator, max_distance_to_consider: max_distance_to_consider, len: 0 };
+            ret_val
+        }
+
+        /// Builds a balanced tree over all of `entries` at once.
+        pub fn bulk_load<I>(entries: I, edit_distance_calculator: Arc<EditDistanceCalc>, max_distance_to_consider: EditDistance) -> Self
+            where I: IntoIterator<Item = (Arc<KeyType>, Arc<RecordType>)> {
+            let mut groups = HashMap::<Arc<KeyType>, HashSet<Arc<RecordType>>>::new();
+            for (value, record_found_in) in entries {
+                groups.entry(value).or_default().insert(record_found_in);
+            }
+            let mut ret_val = VPTree::new_empty(edit_distance_calculator, max_distance_to_consider);
+            ret_val.build(groups.into_iter().collect());
+            ret_val
+        }
+
+        /// Rebuilds the tree from its current contents, as `bulk_load` would.
+        pub fn rebuild(&mut self) {
+            let groups = std::mem::take(&mut self.nodes).into_iter().map(|node| (node.value, node.records_found_in)).collect();
+            self.build(groups);
+        }
+
+        fn build(&mut self, groups: Vec<ValueGroup<RecordType, KeyType>>) {
+            self.len = groups.iter().map(|(_, records_found_in)| records_found_in.len()).sum();
+            self.nodes.clear();
+            // Each pending group remembers which side of which node its subtree hangs from
+            let mut pending = vec![(groups, None::<(usize, bool)>)];
+            while let Some((mut group, parent)) = pending.pop() {
+                let (value, records_found_in) = match group.pop() {
+                    Some(vantage_point) => vantage_point,
+                    None                => continue,
+                };
+                let mut by_distance: Vec<(EditDistance, ValueGroup<RecordType, KeyType>)> = group.into_iter()
+                    .map(|entry| (self.edit_distance_calculator.get_edit_distance(&value, &entry.0), entry))
+                    .collect();
+                by_distance.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| (a.1).0.cmp(&(b.1).0)));
+                let radius = if by_distance.is_empty() { 0 } else { by_distance[(by_distance.len() - 1) / 2].0 };
+                let split = by_distance.partition_point(|(dist, _)| *dist <= radius);
+                let outside_group: Vec<ValueGroup<RecordType, KeyType>> = by_distance.split_off(split).into_iter().map(|(_, entry)| entry).collect();
+                let inside_group: Vec<ValueGroup<RecordType, KeyType>> = by_distance.into_iter().map(|(_, entry)| entry).collect();
+                let index = self.nodes.len();
+                self.nodes.push(VPTreeNode { value: value, records_found_in: records_found_in, radius: radius, inside: None, outside: None });
+                self.link(parent, index);
+                if !inside_group.is_empty() {
+                    pending.push((inside_group, Some((index, true))));
+                }
+                if !outside_group.is_empty() {
+                    pending.push((outside_group, Some((index, false))));
+                }
+            }
+        }
+
+        fn link(&mut self, parent: Option<(usize, bool)>, child_index: usize) {
+            match parent {
+                Some((parent_index, true))  => self.nodes[parent_index].inside = Some(child_index),
+                Some((parent_index, false)) => self.nodes[parent_index].outside = Some(child_index),
+                None                        => (),
+            }
+        }
+
+        /// Adds `record_found_in` under `value`. Returns false if that record was already indexed under that value.
+        pub fn insert(&mut self, value: Arc<KeyType>, record_found_in: Arc<RecordType>) -> bool {
+            let mut parent = None;
+            let mut next = if self.nodes.is_empty() { None } else { Some(0) };
+            while let Some(index) = next {
+                let node = &mut self.nodes[index];
+                if node.value == value {
+                    let inserted = node.records_found_in.insert(record_found_in);
+                    if inserted {
+                        self.len += 1;
+                    }
+                    return inserted;
+                }
+                let dist = self.edit_distance_calculator.get_edit_distance(&node.value, &value);
+                // A leaf has nothing on either side yet, so it can take its radius from its first child
+                if node.inside.is_none() && node.outside.is_none() {
+                    node.radius = dist;
+                }
+                let is_inside = dist <= node.radius;
+                parent = Some((index, is_inside));
+                next = if is_inside { node.inside } else { node.outside };
+            }
+            let mut records_found_in = HashSet::new();
+            records_found_in.insert(record_found_in);
+            let index = self.nodes.len();
+            self.nodes.push(VPTreeNode { value: value, records_found_in: records_found_in, radius: 0, inside: None, outside: None });
+            self.link(parent, index);
+            self.len += 1;
+            true
+        }
+
+        /// Every indexed value within `max_distance_to_consider` of `value`, closest first.
+        pub fn search(&self, value: &KeyType) -> Vec<BKTreeMatch<RecordType, KeyType>> {
+            self.search_within(value, self.max_distance_to_consider)
+        }
+
+        /// Like `search`, but with a threshold for this query only.
+        pub fn search_within(&self, value: &KeyType, max_distance: EditDistance) -> Vec<BKTreeMatch<RecordType, KeyType>> {
+            let mut rtn = Vec::new();
+            let mut pending: Vec<usize> = if self.nodes.is_empty() { Vec::new() } else { vec![0] };
+            while let Some(index) = pending.pop() {
+                let node = &self.nodes[index];
+                // Without an outside subtree, distances beyond the radius plus the threshold rule out everything here
+                let distance_bound = match (node.inside, node.outside) {
+                    (_, Some(_))    => EditDistance::MAX,
+                    (Some(_), None) => node.radius.saturating_add(max_distance).max(max_distance),
+                    (None, None)    => max_distance,
+                };
+                let dist = match self.edit_distance_calculator.get_bounded_edit_distance(&node.value, value, distance_bound) {
+                    Some(dist) => dist,
+                    None       => continue,
+                };
+                if dist <= max_distance {
+                    rtn.push(to_match(&node.value, dist, &node.records_found_in));
+                }
+                // By the triangle inequality, anything inside is at least dist - radius away, and anything outside
+                // at least radius + 1 - dist
+                if let Some(inside) = node.inside {
+                    if dist.saturating_sub(max_distance) <= node.radius {
+                        pending.push(inside);
+                    }
+                }
+                if let Some(outside) = node.outside {
+                    if dist.saturating_add(max_distance) > node.radius {
+                        pending.push(outside);
+                    }
+                }
+            }
+            rtn.sort_by(BKTreeMatch::cmp_by_distance);
+            rtn
+        }
+
+        /// The `k` indexed values closest to `value`, regardless of `max_distance_to_consider`, closest first.
+        pub fn nearest(&self, value: &KeyType, k: usize) -> Vec<BKTreeMatch<RecordType, KeyType>> {
+            let mut best: Vec<BKTreeMatch<RecordType, KeyType>> = Vec::with_capacity(k);
+            if k == 0 {
+                return best;
+            }
+            let mut pending: Vec<(EditDistance, usize)> = if self.nodes.is_empty() { Vec::new() } else { vec![(0, 0)] };
+            while let Some((lower_bound, index)) = pending.pop() {
+                let bound = if best.len() < k { EditDistance::MAX } else { best[best.len() - 1].get_distance() };
+                if lower_bound > bound {
+                    continue;
+                }
+                let node = &self.nodes[index];
+                let dist = self.edit_distance_calculator.get_edit_distance(&node.value, value);
+                let candidate = to_match(&node.value, dist, &node.records_found_in);
+                if best.len() < k || BKTreeMatch::cmp_by_distance(&candidate, &best[best.len() - 1]) == Ordering::Less {
+                    let position = best.partition_point(|m| BKTreeMatch::cmp_by_distance(m, &candidate) == Ordering::Less);
+                    best.insert(position, candidate);
+                    best.truncate(k);
+                }
+                let inside = node.inside.map(|i| (dist.saturating_sub(node.radius), i));
+                let outside = node.outside.map(|i| ((node.radius + 1).saturating_sub(dist), i));
+                // Visit the side the query falls on first, so the bound tightens early
+                if dist <= node.radius {
+                    pending.extend(outside);
+                    pending.extend(inside);
+                } else {
+                    pending.extend(inside);
+                    pending.extend(outside);
+                }
+            }
+            best
+        }
+
+        pub fn get_edit_distance_calculator(&self) -> Arc<EditDistanceCalc> {
+            Arc::clone(&self.edit_distance_calculator)
+        }
+
+        pub fn get_max_distance_to_consider(&self) -> EditDistance {
+            self.max_distance_to_consider
+        }
+
+        /// Number of (value, record) pairs indexed.
+        pub fn len(&self) -> usize {
+            self.len
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.len == 0
+        }
+    }
+
+    impl<RecordType: PartialEq + Eq + Hash, EditDistanceCalc: EditDistanceCalculator<KeyType>, KeyType: Eq + Hash + Ord> FuzzyIndex<RecordType, KeyType> for VPTree<RecordType, EditDistanceCalc, KeyType> {
+        fn index_name(&self) -> &'static str {
+            return "VP-tree";
+        }
+
+        fn insert(&mut self, value: Arc<KeyType>, record_found_in: Arc<RecordType>) -> bool {
+            VPTree::insert(self, value, record_found_in)
+        }
+
+        fn search_within(&self, value: &KeyType, max_distance: EditDistance) -> Vec<BKTreeMatch<RecordType, KeyType>> {
+            VPTree::search_within(self, value, max_distance)
+        }
+
+        fn get_max_distance_to_consider(&self) -> EditDistance {
+            self.max_distance_to_consider
+        }
+
+        fn len(&self) -> usize {
+            self.len
+        }
+    }
+
+    /// Every string obtained from `value` by deleting at most `max_deletions` characters, `value` itself included.
+    pub fn deletion_neighbourhood(value: &str, max_deletions: EditDistance) -> HashSet<String> {
+        let mut ret_val = HashSet::new();
+        ret_val.insert(value.to_string());
+        let mut frontier = vec![value.to_string()];
+        for _ in 0..max_deletions {
+            let mut next_frontier = Vec::new();
+            for s in &frontier {
+                let chars: Vec<char> = s.chars().collect();
+                for i in 0..chars.len() {
+                    let deleted: String = chars[..i].iter().chain(&chars[i + 1..]).collect();
+                    if ret_val.insert(deleted.clone()) {
+                        next_frontier.push(deleted);
+                    }
+                }
+            }
+            frontier = next_frontier;
+        }
+        ret_val
+    }
+
+    /// A SymSpell-style index: every value is filed under each string reachable from it by at most
+    /// `max_distance_to_consider` deletions, and a query only computes distances to values sharing one of its own
+    /// deletions. Lookups cost about the same whatever the corpus size, at the price of memory that grows
+    /// combinatorially with the threshold, so it suits thresholds of 1 or 2. Searches beyond that threshold are
+    /// still answered, by a linear scan over every value.
+    ///
+    /// The candidates are complete for character-based Levenshtein, OSA and Damerau-Levenshtein calculators, since
+    /// each of their edits is matched by at most one deletion on each side. Other calculators may miss matches.
+    #[derive(Debug)]
+    pub struct SymSpellIndex<RecordType, EditDistanceCalc>
+        where RecordType: PartialEq + Eq + Hash,
+        EditDistanceCalc: EditDistanceCalculator {
+            entries: Vec<ValueGroup<RecordType, String>>,
+            entry_indexes: HashMap<Arc<String>, usize>,
+            deletions: HashMap<String, Vec<usize>>,
+            edit_distance_calculator: Arc<EditDistanceCalc>,
+            max_distance_to_consider: EditDistance,
+            len: usize,
+    }
+
+    impl<RecordType: PartialEq + Eq + Hash, EditDistanceCalc: EditDistanceCalculator> SymSpellIndex<RecordType, EditDistanceCalc> {
+        /// `max_distance_to_consider` is also the deepest deletion neighbourhood indexed; searches reaching further
+        /// than it fall back to a linear scan.
+        pub fn new(edit_distance_calculator: Arc<EditDistanceCalc>, max_distance_to_consider: EditDistance) -> Self {
+            let ret_val = SymSpellIndex::<RecordType, EditDistanceCalc> { entries: Vec::new(), entry_indexes: HashMap::new(), deletions: HashMap::new(), edit_distance_calculator: edit_distance_calculator, max_distance_to_consider: max_distance_to_consider, len: 0 };
+            ret_val
+        }
+
+        pub fn bulk_load<I>(entries: I, edit_distance_calculator: Arc<EditDistanceCalc>, max_distance_to_consider: EditDistance) -> Self
+            where I: IntoIterator<Item = (Arc<String>, Arc<RecordType>)> {
+            let mut ret_val = SymSpellIndex::new(edit_distance_calculator, max_distance_to_consider);
+            for (value, record_found_in) in entries {
+                ret_val.insert(value, record_found_in);
+            }
+            ret_val
+        }
+
+        /// Adds `record_found_in` under `value`. Returns false if that record was already indexed under that value.
+        pub fn insert(&mut self, value: Arc<String>, record_found_in: Arc<RecordType>) -> bool {
+            let inserted = match self.entry_indexes.entry(Arc::clone(&value)) {
+                Entry::Occupied(entry) => self.entries[*entry.get()].1.insert(record_found_in),
+                Entry::Vacant(slot)    => {
+                    let index = self.entries.len();
+                    slot.insert(index);
+                    for deleted in deletion_neighbourhood(&value, self.max_distance_to_consider) {
+                        self.deletions.entry(deleted).or_default().push(index);
+                    }
+                    let mut records_found_in = HashSet::new();
+                    records_found_in.insert(record_found_in);
+                    self.entries.push((value, records_found_in));
+                    true
+                },
+            };
+            if inserted {
+                self.len += 1;
+            }
+            inserted
+        }
+
+        /// Every indexed value within `max_distance_to_consider` of `value`, closest first.
+        pub fn search(&self, value: &String) -> Vec<BKTreeMatch<RecordType>> {
+            self.search_within(value, self.max_distance_to_consider)
+        }
+
+        /// Like `search`, but with a threshold for this query only. Thresholds above `max_distance_to_consider` are
+        /// beyond the deletions indexed, so they are answered by comparing `value` with every indexed value.
+        pub fn search_within(&self, value: &String, max_distance: EditDistance) -> Vec<BKTreeMatch<RecordType>> {
+            let mut candidates = HashSet::new();
+            if max_distance > self.max_distance_to_consider {
+                candidates.extend(0..self.entries.len());
+            } else {
+                for deleted in deletion_neighbourhood(value, max_distance) {
+                    if let Some(indexes) = self.deletions.get(&deleted) {
+                        candidates.extend(indexes.iter().copied());
+                    }
+                }
+            }
+            let mut rtn = Vec::new();
+            for index in candidates {
+                let (candidate, records_found_in) = &self.entries[index];
+                if let Some(dist) = self.edit_distance_calculator.get_bounded_edit_distance(candidate, value, max_distance) {
+                    rtn.push(to_match(candidate, dist, records_found_in));
+                }
+            }
+            rtn.sort_by(BKTreeMatch::cmp_by_distance);
+            rtn
+        }
+
+        pub fn get_edit_distance_calculator(&self) -> Arc<EditDistanceCalc> {
+            Arc::clone(&self.edit_distance_calculator)
+        }
+
+        pub fn get_max_distance_to_consider(&self) -> EditDistance {
+            self.max_distance_to_consider
+        }
+
+        /// Number of distinct deletion strings indexed, a rough measure of the index's memory use.
+        pub fn get_deletion_count(&self) -> usize {
+            self.deletions.len()
+        }
+
+        /// Number of (value, record) pairs indexed.
+        pub fn len(&self) -> usize {
+            self.len
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.len == 0
+        }
+    }
+
+    impl<RecordType: PartialEq + Eq + Hash, EditDistanceCalc: EditDistanceCalculator> FuzzyIndex<RecordType> for SymSpellIndex<RecordType, EditDistanceCalc> {
+        fn index_name(&self) -> &'static str {
+            return "SymSpell";
+        }
+
+        fn insert(&mut self, value: Arc<String>, record_found_in: Arc<RecordType>) -> bool {
+            SymSpellIndex::insert(self, value, record_found_in)
+        }
+
+        fn search_within(&self, value: &String, max_distance: EditDistance) -> Vec<BKTreeMatch<RecordType>> {
+            SymSpellIndex::search_within(self, value, max_distance)
+        }
+
+        fn get_max_distance_to_consider(&self) -> EditDistance {
+            self.max_distance_to_consider
+        }
+
+        fn len(&self) -> usize {
+            self.len
+        }
+
+        fn get_max_indexed_distance(&self) -> Option<EditDistance> {
+            Some(self.max_distance_to_consider)
+        }
+    }
+}
+
+pub mod human_index {
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::fmt;
+    use std::sync::Arc;
+    use super::fuzzy_matching::*;
+    use super::date_comparison::BirthDateComparator;
+    use super::human::Human;
+    use super::normalization::{normalize_postal_code, NormalizationPipeline};
+    use super::option_date_time::{CompleteDate, OptionDate};
+
+    /// A `Human` field that `HumanIndex` indexes and scores.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    pub enum HumanIndexField {
+        Name,
+        BirthDate,
+        PostalCode,
+        EmailAddress,
+    }
+
+    pub const ALL_HUMAN_INDEX_FIELDS: [HumanIndexField; 4] = [
+        HumanIndexField::Name,
+        HumanIndexField::BirthDate,
+        HumanIndexField::PostalCode,
+        HumanIndexField::EmailAddress,
+    ];
+
+    impl fmt::Display for HumanIndexField {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                HumanIndexField::Name         => write!(f, "name"),
+                HumanIndexField::BirthDate    => write!(f, "birth_date"),
+                HumanIndexField::PostalCode   => write!(f, "postal_code"),
+                HumanIndexField::EmailAddress => write!(f, "email_address"),
+            }
+        }
+    }
+
+    pub const DEFAULT_NAME_MAX_DISTANCE: EditDistance = 2;
+    pub const DEFAULT_POSTAL_CODE_MAX_DISTANCE: EditDistance = 1;
+    pub const DEFAULT_EMAIL_ADDRESS_MAX_DISTANCE: EditDistance = 2;
+
+    /// Relative weight of each field in a candidate's overall score. Names and birth dates identify a person far
+    /// better than a postal code shared with thousands of neighbours.
+    pub fn default_field_weight(field: HumanIndexField) -> f64 {
+        match field {
+            HumanIndexField::Name         => 4.0,
+            HumanIndexField::BirthDate    => 3.0,
+            HumanIndexField::PostalCode   => 1.0,
+            HumanIndexField::EmailAddress => 2.0,
+        }
+    }
+
+    fn normalize_email_address(email_address: &str) -> String {
+        email_address.trim().to_lowercase()
+    }
+
+    /// What to look for. Any combination of fields may be given; only those given are searched and scored.
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub struct HumanQuery {
+        name: Option<String>,
+        birth_date: Option<OptionDate>,
+        postal_code: Option<String>,
+        email_address: Option<String>,
+    }
+
+    impl HumanQuery {
+        pub fn new() -> Self {
+            let ret_val = HumanQuery { name: None, birth_date: None, postal_code: None, email_address: None };
+            ret_val
+        }
+
+        /// All or part of a name, in any order: "smith", "J Smith" and "Smith, Jane" all work.
+        pub fn with_name(mut self, name: &str) -> Self {
+            self.name = Some(name.to_string());
+            self
+        }
+
+        pub fn with_birth_date(mut self, birth_date: OptionDate) -> Self {
+            self.birth_date = Some(birth_date);
+            self
+        }
+
+        pub fn with_postal_code(mut self, postal_code: &str) -> Self {
+            self.postal_code = Some(postal_code.to_string());
+            self
+        }
+
+        pub fn with_email_address(mut self, email_address: &str) -> Self {
+            self.email_address = Some(email_address.to_string());
+            self
+        }
+
+        pub fn get_name(&self) -> Option<&String> {
+            self.name.as_ref()
+        }
+
+        pub fn get_birth_date(&self) -> Option<&OptionDate> {
+            self.birth_date.as_ref()
+        }
+
+        pub fn get_postal_code(&self) -> Option<&String> {
+            self.postal_code.as_ref()
+        }
+
+        pub fn get_email_address(&self) -> Option<&String> {
+            self.email_address.as_ref()
+        }
+
+        /// The fields this query searches on.
+        pub fn get_fields(&self) -> Vec<HumanIndexField> {
+            let mut ret_val = Vec::new();
+            if self.name.is_some() {
+                ret_val.push(HumanIndexField::Name);
+            }
+            if self.birth_date.is_some() {
+                ret_val.push(HumanIndexField::BirthDate);
+            }
+            if self.postal_code.is_some() {
+                ret_val.push(HumanIndexField::PostalCode);
+            }
+            if self.email_address.is_some() {
+                ret_val.push(HumanIndexField::EmailAddress);
+            }
+            ret_val
+        }
+    }
+
+    /// One record found by `HumanIndex::search`. `score` is the weighted mean of `field_scores`, which has an
+    /// entry, between 0 and 1, for every field in the query.
+    #[derive(Debug, Clone)]
+    pub struct HumanCandidate {
+        pub human: Arc<Human>,
+        pub score: Similarity,
+        pub field_scores: Vec<(HumanIndexField, Similarity)>,
+    }
+
+    /// A record's searchable values, normalized once when it is inserted.
+    #[derive(Debug)]
+    struct IndexedHuman {
+        human: Arc<Human>,
+        name_tokens: Vec<String>,
+        birth_date: Option<OptionDate>,
+        postal_codes: Vec<String>,
+        email_addresses: Vec<String>,
+    }
+
+    /// Fuzzy indexes over several fields of a set of `Human`s, searched together: a query combining a partial
+    /// name, birth date, postal code and email address gathers candidates from every field's index and ranks them
+    /// by a weighted blend of per-field similarities.
+    #[derive(Debug)]
+    pub struct HumanIndex {
+        records: Vec<IndexedHuman>,
+        pipeline: NormalizationPipeline,
+        name_tree: BKTree<usize, LevenshteinEditDistanceCalculator>,
+        /// Complete birth dates only; day distances to a partial date are meaningless (see `DateDistanceCalculator`).
+        birth_date_tree: BKTree<usize, DateDistanceCalculator, CompleteDate>,
+        /// Every record with a known birth year, by year, so partial dates can still be looked up.
+        birth_year_records: BTreeMap<i64, Vec<usize>>,
+        postal_code_tree: BKTree<usize, HammingEditDistanceCalculator>,
+        email_address_tree: BKTree<usize, LevenshteinEditDistanceCalculator>,
+        field_weights: BTreeMap<HumanIndexField, f64>,
+        name_max_distance: EditDistance,
+        birth_date_max_days: EditDistance,
+        postal_code_max_distance: EditDistance,
+        email_address_max_distance: EditDistance,
+    }
+
+    impl HumanIndex {
+        pub fn new() -> Self {
+            let ret_val = HumanIndex {
+                records: Vec::new(),
+                pipeline: NormalizationPipeline::for_matching(),
+                name_tree: BKTree::new_empty(Arc::new(LevenshteinEditDistanceCalculator::new()), DEFAULT_NAME_MAX_DISTANCE),
+                birth_date_tree: BKTree::new_empty(Arc::new(DateDistanceCalculator::new()), DEFAULT_BIRTH_DATE_MAX_DAYS),
+                birth_year_records: BTreeMap::new(),
+                postal_code_tree: BKTree::new_empty(Arc::new(HammingEditDistanceCalculator::new()), DEFAULT_POSTAL_CODE_MAX_DISTANCE),
+                email_address_tree: BKTree::new_empty(Arc::new(LevenshteinEditDistanceCalculator::new()), DEFAULT_EMAIL_ADDRESS_MAX_DISTANCE),
+                field_weights: ALL_HUMAN_INDEX_FIELDS.iter().map(|f| (*f, default_field_weight(*f))).collect(),
+                name_max_distance: DEFAULT_NAME_MAX_DISTANCE,
+                birth_date_max_days: DEFAULT_BIRTH_DATE_MAX_DAYS,
+                postal_code_max_distance: DEFAULT_POSTAL_CODE_MAX_DISTANCE,
+                email_address_max_distance: DEFAULT_EMAIL_ADDRESS_MAX_DISTANCE,
+            };
+            ret_val
+        }
+
+        /// Normalization applied to names, in the index and in queries, before they are split into tokens.
+        pub fn with_pipeline(mut self, pipeline: NormalizationPipeline) -> Self {
+            self.pipeline = pipeline;
+            self
+        }
+
+        pub fn set_field_weight(&mut self, field: HumanIndexField, weight: f64) {
+            self.field_weights.insert(field, weight);
+        }
+
+        pub fn get_field_weight(&self, field: HumanIndexField) -> f64 {
+            self.field_weights[&field]
+        }
+
+        /// Largest edit distance at which a query name token still matches a name component.
+        pub fn set_name_max_distance(&mut self, max_distance: EditDistance) {
+            self.name_max_distance = max_distance;
+        }
+
+        /// Largest difference, in days, at which a birth date still matches.
+        pub fn set_birth_date_max_days(&mut self, max_days: EditDistance) {
+            self.birth_date_max_days = max_days;
+        }
+
+        /// Largest number of differing characters at which a postal code still matches.
+        pub fn set_postal_code_max_distance(&mut self, max_distance: EditDistance) {
+            self.postal_code_max_distance = max_distance;
+        }
+
+        /// Largest edit distance at which an email address still matches.
+        pub fn set_email_address_max_distance(&mut self, max_distance: EditDistance) {
+            self.email_address_max_distance = max_distance;
+        }
+
+        fn name_tokens(&self, name: &str) -> Vec<String> {
+            tokenize(&self.pipeline.normalize(name))
+        }
+
+        /// Indexes `human` under each of its fields. Returns its record number, which `get` accepts.
+        pub fn insert(&mut self, human: Arc<Human>) -> usize {
+            let id = Arc::new(self.records.len());
+            let name_tokens = self.name_tokens(&human.get_name().to_string());
+            for token in &name_tokens {
+                self.name_tree.insert(Arc::new(token.clone()), Arc::clone(&id));
+            }
+            let birth_date = human.get_birth_date();
+            let birth_date = if birth_date.get_year().is_some() { Some(birth_date.clone()) } else { None };
+            if let Some(birth_date) = &birth_date {
+                if let Some(complete) = CompleteDate::from_option_date(birth_date) {
+                    self.birth_date_tree.insert(Arc::new(complete), Arc::clone(&id));
+                }
+                self.birth_year_records.entry(birth_date.get_year().expect("only dates with a year are kept")).or_default().push(*id);
+            }
+            let mut postal_codes: Vec<String> = human.get_addresses().values()
+                .map(|address| normalize_postal_code(&address.get_zip_or_postal_code()))
+                .filter(|postal_code| !postal_code.is_empty())
+                .collect();
+            postal_codes.sort();
+            postal_codes.dedup();
+            for postal_code in &postal_codes {
+                self.postal_code_tree.insert(Arc::new(postal_code.clone()), Arc::clone(&id));
+            }
+            let mut email_addresses: Vec<String> = human.get_email_addresses().values()
+                .map(|email_address| normalize_email_address(email_address))
+                .filter(|email_address| !email_address.is_empty())
+                .collect();
+            email_addresses.sort();
+            email_addresses.dedup();
+            for email_address in &email_addresses {
+                self.email_address_tree.insert(Arc::new(email_address.clone()), Arc::clone(&id));
+            }
+            self.records.push(IndexedHuman { human: human, name_tokens: name_tokens, birth_date: birth_date, postal_codes: postal_codes, email_addresses: email_addresses });
+            *id
+        }
+
+        pub fn get(&self, record_number: usize) -> Option<Arc<Human>> {
+            self.records.get(record_number).map(|r| Arc::clone(&r.human))
+        }
+
+        pub fn len(&self) -> usize {
+            self.records.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.records.is_empty()
+        }
+
+        /// Up to `limit` records matching `query` on at least one field, best first. Ties keep insertion order.
+        pub fn search(&self, query: &HumanQuery, limit: usize) -> Vec<HumanCandidate> {
+            let query_name_tokens = query.get_name().map(|name| self.name_tokens(name)).unwrap_or_default();
+            let query_postal_code = query.get_postal_code().map(|p| normalize_postal_code(p));
+            let query_email_address = query.get_email_address().map(|e| normalize_email_address(e));
+
+            let mut candidates = BTreeSet::<usize>::new();
+            // Initials are too unselective to look up; they only count towards the score of records found otherwise
+            for token in query_name_tokens.iter().filter(|t| t.chars().count() > 1) {
+                candidates.extend(record_numbers(self.name_tree.search_within(token, self.name_max_distance)));
+            }
+            if let Some(birth_date) = query.get_birth_date().filter(|d| d.get_year().is_some()) {
+                let same_year = self.birth_year_records.get(&birth_date.get_year().expect("filtered on the year")).into_iter().flatten();
+                if let Some(complete) = CompleteDate::from_option_date(birth_date) {
+                    candidates.extend(record_numbers(self.birth_date_tree.search_within(&complete, self.birth_date_max_days)));
+                    // Records with a partial date in the same year may still agree on everything they know
+                    candidates.extend(same_year.filter(|id| !self.records[**id].birth_date.as_ref().is_some_and(|d| d.is_complete())));
+                } else {
+                    candidates.extend(same_year);
+                }
+            }
+            if let Some(postal_code) = query_postal_code.as_ref().filter(|p| !p.is_empty()) {
+                candidates.extend(record_numbers(self.postal_code_tree.search_within(postal_code, self.postal_code_max_distance)));
+            }
+            if let Some(email_address) = query_email_address.as_ref().filter(|e| !e.is_empty()) {
+                candidates.extend(record_numbers(self.email_address_tree.search_within(email_address, self.email_address_max_distance)));
+            }
+
+            let fields = query.get_fields();
+            let total_weight: f64 = fields.iter().map(|f| self.get_field_weight(*f)).sum();
+            let mut ret_val: Vec<HumanCandidate> = candidates.into_iter().map(|id| {
+                let record = &self.records[id];
+                let field_scores: Vec<(HumanIndexField, Similarity)> = fields.iter().map(|field| {
+                    let score = match field {
+                        HumanIndexField::Name         => self.name_score(&query_name_tokens, &record.name_tokens),
+                        HumanIndexField::BirthDate    => self.birth_date_score(query.get_birth_date(), record.birth_date.as_ref()),
+                        HumanIndexField::PostalCode   => self.postal_code_score(query_postal_code.as_deref(), &record.postal_codes),
+                        HumanIndexField::EmailAddress => self.email_address_score(query_email_address.as_deref(), &record.email_addresses),
+                    };
+                    (*field, score)
+                }).collect();
+                let weighted: f64 = field_scores.iter().map(|(f, s)| self.get_field_weight(*f) * s).sum();
+                let score = if total_weight > 0.0 { weighted / total_weight } else { 0.0 };
+                HumanCandidate { human: Arc::clone(&record.human), score: score, field_scores: field_scores }
+            }).collect();
+            // Stable, so equal scores stay in record order
+            ret_val.sort_by(|a, b| b.score.total_cmp(&a.score));
+            ret_val.truncate(limit);
+            ret_val
+        }
+
+        /// Each query token is matched to its most similar component of the record's name, wherever it appears, and
+        /// the matches are averaged; components the query leaves out do not count against the record.
+        fn name_score(&self, query_tokens: &[String], record_tokens: &[String]) -> Similarity {
+            if query_tokens.is_empty() {
+                return 0.0;
+            }
+            let total: Similarity = query_tokens.iter()
+                .map(|q| record_tokens.iter().map(|r| self.token_similarity(q, r)).fold(0.0, Similarity::max))
+                .sum();
+            total / query_tokens.len() as Similarity
+        }
+
+        fn token_similarity(&self, a: &str, b: &str) -> Similarity {
+            if a == b {
+                return 1.0;
+            }
+            let (a_len, b_len) = (a.chars().count(), b.chars().count());
+            if a_len == 1 || b_len == 1 {
+                return if a.chars().next() == b.chars().next() { INITIAL_SIMILARITY } else { 0.0 };
+            }
+            bounded_similarity(LevenshteinEditDistanceCalculator::new().get_bounded_edit_distance(a, b, self.name_max_distance), a_len.max(b_len))
+        }
+
+        /// Complete dates score by how many days apart they are. When either date is partial only the components
+        /// both know are compared, by `BirthDateComparator`, so a year-only date scores as a partial agreement.
+        fn birth_date_score(&self, query_date: Option<&OptionDate>, record_date: Option<&OptionDate>) -> Similarity {
+            match (query_date, record_date) {
+                (Some(q), Some(r)) if q.get_year().is_some() => match (CompleteDate::from_option_date(q), CompleteDate::from_option_date(r)) {
+                    (Some(q), Some(r)) => {
+                        let dist = DateDistanceCalculator::new().get_bounded_edit_distance(&q, &r, self.birth_date_max_days);
+                        bounded_similarity(dist, self.birth_date_max_days + 1)
+                    },
+                    _                  => BirthDateComparator::new().get_similarity(q, r),
+                },
+                _                                            => 0.0,
+            }
+        }
+
+        fn postal_code_score(&self, query_postal_code: Option<&str>, record_postal_codes: &[String]) -> Similarity {
+            let query_postal_code = match query_postal_code {
+                Some(q) if !q.is_empty() => q.to_string(),
+                _                        => return 0.0,
+            };
+            record_postal_codes.iter()
+                .map(|r| bounded_similarity(HammingEditDistanceCalculator::new().get_bounded_edit_distance(&query_postal_code, r, self.postal_code_max_distance), query_postal_code.chars().count().max(r.chars().count())))
+                .fold(0.0, Similarity::max)
+        }
+
+        fn email_address_score(&self, query_email_address: Option<&str>, record_email_addresses: &[String]) -> Similarity {
+            let query_email_address = match query_email_address {
+                Some(q) if !q.is_empty() => q.to_string(),
+                _                        => return 0.0,
+            };
+            record_email_addresses.iter()
+                .map(|r| bounded_similarity(LevenshteinEditDistanceCalculator::new().get_bounded_edit_distance(&query_email_address, r, self.email_address_max_distance), query_email_address.chars().count().max(r.chars().count())))
+                .fold(0.0, Similarity::max)
+        }
+    }
+
+    impl Default for HumanIndex {
+        fn default() -> Self {
+            HumanIndex::new()
+        }
+    }
+
+    fn record_numbers<KeyType>(matches: Vec<BKTreeMatch<usize, KeyType>>) -> impl Iterator<Item = usize> {
+        matches.into_iter().flat_map(|m| m.get_records_found_in().iter().map(|id| **id).collect::<Vec<usize>>())
+    }
+
+    /// 1 for distance 0, falling linearly to 0 at `scale`; 0 when the distance was over its bound.
+    fn bounded_similarity(dist: Option<EditDistance>, scale: usize) -> Similarity {
+        match dist {
+            Some(dist) if scale > 0 => (1.0 - dist as Similarity / scale as Similarity).max(0.0),
+            Some(_)                 => 1.0,
+            None                    => 0.0,
+        }
+    }
+}
+
+pub mod csv_import {
+    use std::collections::{HashSet, HashMap};
+    use std::fmt;
+    use std::io::{Read, Write};
+    use std::marker::PhantomData;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use csv::{ByteRecord, Reader, ReaderBuilder, Writer};
+    use super::entity::*;
+    use super::human::*;
+    use super::option_date_time::*;
+    use super::organization::Organization;
+
+    #[derive(Debug)]
+    pub enum CsvImportErr {
+        CsvError(csv::Error),
+        MissingColumn(String),
+        UnmappedField(String),
+    }
+    pub type CsvImportResult<T> = Result<T, CsvImportErr>;
+
+    impl From<csv::Error> for CsvImportErr {
+        fn from(e: csv::Error) -> Self {
+            CsvImportErr::CsvError(e)
+        }
+    }
+
+    impl fmt::Display for CsvImportErr {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                CsvImportErr::CsvError(e)           => write!(f, "CSV error: {}", e),
+                CsvImportErr::MissingColumn(header) => write!(f, "column \"{}\" not found in CSV header", header),
+                CsvImportErr::UnmappedField(field)  => write!(f, "required field {} is not mapped to any column", field),
+            }
+        }
+    }
+
+    /// Why a single cell could not be turned into a field value.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum FieldParseErr {
+        MissingValue,
+        InvalidUtf8,
+        InvalidHumanName,
+        InvalidDate,
+        InvalidTIN,
+        InvalidAddress,
+    }
+
+    impl From<HumanNameParseErr> for FieldParseErr {
+        fn from(_: HumanNameParseErr) -> Self {
+            FieldParseErr::InvalidHumanName
+        }
+    }
+
+    impl From<OptionDateParseErr> for FieldParseErr {
+        fn from(_: OptionDateParseErr) -> Self {
+            FieldParseErr::InvalidDate
+        }
+    }
+
+    impl From<TINParseErr> for FieldParseErr {
+        fn from(_: TINParseErr) -> Self {
+            FieldParseErr::InvalidTIN
+        }
+    }
+
+    impl From<AddressParseErr> for FieldParseErr {
+        fn from(_: AddressParseErr) -> Self {
+            FieldParseErr::InvalidAddress
+        }
+    }
+
+    impl fmt::Display for FieldParseErr {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                FieldParseErr::MissingValue     => write!(f, "missing value"),
+                FieldParseErr::InvalidUtf8      => write!(f, "invalid UTF-8"),
+                FieldParseErr::InvalidHumanName => write!(f, "invalid human name"),
+                FieldParseErr::InvalidDate      => write!(f, "invalid date"),
+                FieldParseErr::InvalidTIN       => write!(f, "invalid TIN"),
+                FieldParseErr::InvalidAddress   => write!(f, "invalid address"),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub enum HumanField {
+        Name,
+        Ssn,
+        BirthDate,
+        Address(AddressType),
+        PhoneNumber(PhoneNumberType),
+        EmailAddress(EmailAddressType),
+        EmployerName,
+        EmployerTin,
+    }
+
+    impl fmt::Display for HumanField {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                HumanField::Name                    => write!(f, "name"),
+                HumanField::Ssn                     => write!(f, "ssn"),
+                HumanField::BirthDate               => write!(f, "birth_date"),
+                HumanField::Address(addr_type)      => write!(f, "address[{}]", addr_type),
+                HumanField::PhoneNumber(phone_type) => write!(f, "phone_number[{}]", phone_type),
+                HumanField::EmailAddress(email_type) => write!(f, "email_address[{}]", email_type),
+                HumanField::EmployerName            => write!(f, "employer_name"),
+                HumanField::EmployerTin             => write!(f, "employer_tin"),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub enum OrganizationField {
+        Name,
+        TinNumber,
+        Address(AddressType),
+        PhoneNumber(PhoneNumberType),
+        EmailAddress(EmailAddressType),
+    }
+
+    impl fmt::Display for OrganizationField {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                OrganizationField::Name                     => write!(f, "name"),
+                OrganizationField::TinNumber                => write!(f, "tin_number"),
+                OrganizationField::Address(addr_type)       => write!(f, "address[{}]", addr_type),
+                OrganizationField::PhoneNumber(phone_type)  => write!(f, "phone_number[{}]", phone_type),
+                OrganizationField::EmailAddress(email_type) => write!(f, "email_address[{}]", email_type),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct FieldImportErr<Field> {
+        pub column: String,
+        pub field: Field,
+        pub error: FieldParseErr,
+    }
+
+    /// A record type that can be assembled from mapped CSV cells.
+    pub trait CsvImportable: Sized {
+        type Field: Clone + PartialEq + fmt::Display;
+
+        fn required_fields() -> Vec<Self::Field>;
+        /// `cells` holds `(column header, field, trimmed cell text)` for every mapped column.
+        fn from_csv_fields(cells: &[(&str, &Self::Field, &str)]) -> Result<Self, Vec<FieldImportErr<Self::Field>>>;
+    }
+
+    fn field_err<Field: Clone>(column: &str, field: &Field, error: FieldParseErr) -> FieldImportErr<Field> {
+        FieldImportErr { column: column.to_string(), field: field.clone(), error: error }
+    }
+
+    fn parse_cell<T, Field>(column: &str, field: &Field, value: &str, errors: &mut Vec<FieldImportErr<Field>>) -> Option<T>
+        where T: FromStr,
+        FieldParseErr: From<T::Err>,
+        Field: Clone {
+            if value.is_empty() {
+                errors.push(field_err(column, field, FieldParseErr::MissingValue));
+                return None;
+            }
+            match value.parse::<T>() {
+                Ok(v) => Some(v),
+                Err(e) => {
+                    errors.push(field_err(column, field, FieldParseErr::from(e)));
+                    None
+                },
+            }
+    }
+
+    fn missing_required<Field: Clone + PartialEq + fmt::Display>(field: &Field, cells: &[(&str, &Field, &str)], errors: &mut Vec<FieldImportErr<Field>>) {
+        if !cells.iter().any(|(_, f, _)| *f == field) {
+            errors.push(field_err(&field.to_string(), field, FieldParseErr::MissingValue));
+        }
+    }
+
+    impl CsvImportable for Human {
+        type Field = HumanField;
+
+        fn required_fields() -> Vec<HumanField> {
+            vec![HumanField::Name, HumanField::Ssn, HumanField::BirthDate]
+        }
+
+        fn from_csv_fields(cells: &[(&str, &HumanField, &str)]) -> Result<Self, Vec<FieldImportErr<HumanField>>> {
+            let mut errors = Vec::new();
+            let mut name = None;
+            let mut ssn = None;
+            let mut birth_date = None;
+            let mut addresses = HashMap::<AddressType, Address>::new();
+            let mut phone_numbers = HashMap::<PhoneNumberType, PhoneNumber>::new();
+            let mut email_addresses = HashMap::<EmailAddressType, EmailAddress>::new();
+            let mut employer_name: Option<(&str, &str)> = None;
+            let mut employer_tin: Option<(&str, &str)> = None;
+            for &(column, field, value) in cells {
+                match field {
+                    HumanField::Name                     => name = parse_cell::<HumanName, _>(column, field, value, &mut errors),
+                    HumanField::Ssn                      => ssn = parse_cell::<SSN, _>(column, field, value, &mut errors),
+                    HumanField::BirthDate                => birth_date = parse_cell::<OptionDate, _>(column, field, value, &mut errors),
+                    HumanField::Address(addr_type)       => {
+                        if !value.is_empty() {
+                            if let Some(addr) = parse_cell::<Address, _>(column, field, value, &mut errors) {
+                                addresses.insert(addr_type.clone(), addr);
+                            }
+                        }
+                    },
+                    HumanField::PhoneNumber(phone_type)  => {
+                        if !value.is_empty() {
+                            phone_numbers.insert(phone_type.clone(), value.to_string());
+                        }
+                    },
+                    HumanField::EmailAddress(email_type) => {
+                        if !value.is_empty() {
+                            email_addresses.insert(email_type.clone(), value.to_string());
+                        }
+                    },
+                    HumanField::EmployerName             => employer_name = Some((column, value)).filter(|(_, v)| !v.is_empty()),
+                    HumanField::EmployerTin              => employer_tin = Some((column, value)).filter(|(_, v)| !v.is_empty()),
+                }
+            }
+            for field in Self::required_fields() {
+                missing_required(&field, cells, &mut errors);
+            }
+            let mut employers = HashSet::<Arc<Organization>>::new();
+            match (employer_name, employer_tin) {
+                (Some((_, org_name)), Some((tin_column, tin))) => {
+                    if let Some(tin_number) = parse_cell::<TIN, _>(tin_column, &HumanField::EmployerTin, tin, &mut errors) {
+                        let org = Organization::new(Arc::new(org_name.to_string()), tin_number, HashMap::new(), HashMap::new(), HashMap::new());
+                        employers.insert(Arc::new(org));
+                    }
+                },
+                (Some((name_column, _)), None) => errors.push(field_err(name_column, &HumanField::EmployerTin, FieldParseErr::MissingValue)),
+                (None, Some((tin_column, _))) => errors.push(field_err(tin_column, &HumanField::EmployerName, FieldParseErr::MissingValue)),
+                (None, None) => {},
+            }
+            match (name, ssn, birth_date) {
+                (Some(name), Some(ssn), Some(birth_date)) if errors.is_empty() => {
+                    Ok(Human::new(name, ssn, birth_date, addresses, phone_numbers, email_addresses, employers))
+                },
+                _ => Err(errors),
+            }
+        }
+    }
+
+    impl CsvImportable for Organization {
+        type Field = OrganizationField;
+
+        fn required_fields() -> Vec<OrganizationField> {
+            vec![OrganizationField::Name, OrganizationField::TinNumber]
+        }
+
+        fn from_csv_fields(cells: &[(&str, &OrganizationField, &str)]) -> Result<Self, Vec<FieldImportErr<OrganizationField>>> {
+            let mut errors = Vec::new();
+            let mut name = None;
+            let mut tin_number = None;
+            let mut addresses = HashMap::<AddressType, Address>::new();
+            let mut phone_numbers = HashMap::<PhoneNumberType, PhoneNumber>::new();
+            let mut email_addresses = HashMap::<EmailAddressType, EmailAddress>::new();
+            for &(column, field, value) in cells {
+                match field {
+                    OrganizationField::Name                     => {
+                        if value.is_empty() {
+                            errors.push(field_err(column, field, FieldParseErr::MissingValue));
+                        } else {
+                            name = Some(Arc::new(value.to_string()));
+                        }
+                    },
+                    OrganizationField::TinNumber                => tin_number = parse_cell::<TIN, _>(column, field, value, &mut errors),
+                    OrganizationField::Address(addr_type)       => {
+                        if !value.is_empty() {
+                            if let Some(addr) = parse_cell::<Address, _>(column, field, value, &mut errors) {
+                                addresses.insert(addr_type.clone(), addr);
+                            }
+                        }
+                    },
+                    OrganizationField::PhoneNumber(phone_type)  => {
+                        if !value.is_empty() {
+                            phone_numbers.insert(phone_type.clone(), value.to_string());
+                        }
+                    },
+                    OrganizationField::EmailAddress(email_type) => {
+                        if !value.is_empty() {
+                            email_addresses.insert(email_type.clone(), value.to_string());
+                        }
+                    },
+                }
+            }
+            for field in Self::required_fields() {
+                missing_required(&field, cells, &mut errors);
+            }
+            match (name, tin_number) {
+                (Some(name), Some(tin_number)) if errors.is_empty() => {
+                    Ok(Organization::new(name, tin_number, addresses, phone_numbers, email_addresses))
+                },
+                _ => Err(errors),
+            }
+        }
+    }
+
+    /// Which CSV column feeds which record field. Headers are matched case-insensitively, ignoring surrounding whitespace.
+    #[derive(Debug, Clone)]
+    pub struct CsvColumnMapping<Field> {
+        columns: Vec<(String, Field)>,
+    }
+
+    impl<Field: Clone + PartialEq + fmt::Display> CsvColumnMapping<Field> {
+        pub fn new() -> Self {
+            let ret_val = CsvColumnMapping { columns: Vec::new() };
+            ret_val
+        }
+
+        pub fn map_column(&mut self, header: &str, field: Field) {
+            self.columns.push((header.trim().to_string(), field));
+        }
+
+        pub fn get_columns(&self) -> &Vec<(String, Field)> {
+            &self.columns
+        }
+
+        fn resolve(&self, headers: &ByteRecord, required_fields: &[Field]) -> CsvImportResult<Vec<(usize, String, Field)>> {
+            for field in required_fields {
+                if !self.columns.iter().any(|(_, f)| f == field) {
+                    return Err(CsvImportErr::UnmappedField(field.to_string()));
+                }
+            }
+            let header_names: Vec<String> = headers.iter().map(|h| String::from_utf8_lossy(h).trim().to_lowercase()).collect();
+            let mut ret_val = Vec::new();
+            for (header, field) in &self.columns {
+                let wanted = header.to_lowercase();
+                match header_names.iter().position(|h| *h == wanted) {
+                    Some(index) => ret_val.push((index, header.clone(), field.clone())),
+                    None => return Err(CsvImportErr::MissingColumn(header.clone())),
+                }
+            }
+            Ok(ret_val)
+        }
+    }
+
+    impl<Field: Clone + PartialEq + fmt::Display> Default for CsvColumnMapping<Field> {
+        fn default() -> Self {
+            CsvColumnMapping::new()
+        }
+    }
+
+    /// A data row that could not be imported, with one entry per failing field.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct RowRejection<Field> {
+        pub row_number: u64,                                    // Line number in the source file; the header is line 1
+        pub errors: Vec<FieldImportErr<Field>>,
+    }
+
+    /// A row that could not be split into the header's columns, such as one with the wrong number of fields.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct MalformedRow {
+        pub row_number: u64,
+        pub error: String,
+    }
+
+    #[derive(Debug)]
+    pub enum CsvRowOutcome<T: CsvImportable> {
+        Imported(T),
+        Rejected(RowRejection<T::Field>),
+        Malformed(MalformedRow),
+    }
+
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct CsvImportSummary {
+        pub rows_read: u64,
+        pub rows_imported: u64,
+        pub rows_rejected: u64,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct CsvImporter<T: CsvImportable> {
+        mapping: CsvColumnMapping<T::Field>,
+        delimiter: u8,
+    }
+
+    pub type HumanCsvImporter = CsvImporter<Human>;
+    pub type OrganizationCsvImporter = CsvImporter<Organization>;
+
+    impl<T: CsvImportable> CsvImporter<T> {
+        pub fn new(mapping: CsvColumnMapping<T::Field>) -> Self {
+            let ret_val = CsvImporter { mapping: mapping, delimiter: b',' };
+            ret_val
+        }
+
+        pub fn with_delimiter(mapping: CsvColumnMapping<T::Field>, delimiter: u8) -> Self {
+            let ret_val = CsvImporter { mapping: mapping, delimiter: delimiter };
+            ret_val
+        }
+
+        /// Streams `reader` one row at a time; the file is never loaded into memory as a whole.
+        pub fn records<R: Read>(&self, reader: R) -> CsvImportResult<CsvRecords<R, T>> {
+            let mut csv_reader = ReaderBuilder::new().delimiter(self.delimiter).from_reader(reader);
+            let headers = csv_reader.byte_headers()?.clone();
+            let columns = self.mapping.resolve(&headers, &T::required_fields())?;
+            let ret_val = CsvRecords { reader: csv_reader, columns: columns, row: ByteRecord::new(), phantom: PhantomData };
+            Ok(ret_val)
+        }
+
+        /// Imports every row, handing good records to `on_record` and writing one reject line per failed field, or
+        /// per malformed row, to `rejects`. Reject lines carry the row number, column, field and error but never the
+        /// cell value, so the reject file does not become another copy of the PHI. Only I/O errors end the import.
+        pub fn import<R: Read, W: Write, F: FnMut(T)>(&self, reader: R, rejects: W, mut on_record: F) -> CsvImportResult<CsvImportSummary> {
+            let mut reject_writer = RejectWriter::new(rejects)?;
+            let mut summary = CsvImportSummary::default();
+            for outcome in self.records(reader)? {
+                summary.rows_read += 1;
+                match outcome? {
+                    CsvRowOutcome::Imported(record) => {
+                        summary.rows_imported += 1;
+                        on_record(record);
+                    },
+                    CsvRowOutcome::Rejected(rejection) => {
+                        summary.rows_rejected += 1;
+                        reject_writer.write_rejection(&rejection)?;
+                    },
+                    CsvRowOutcome::Malformed(malformed) => {
+                        summary.rows_rejected += 1;
+                        reject_writer.write_malformed_row(&malformed)?;
+                    },
+                }
+            }
+            reject_writer.flush()?;
+            Ok(summary)
+        }
+    }
+
+    pub struct CsvRecords<R: Read, T: CsvImportable> {
+        reader: Reader<R>,
+        columns: Vec<(usize, String, T::Field)>,
+        row: ByteRecord,
+        phantom: PhantomData<T>,
+    }
+
+    impl<R: Read, T: CsvImportable> Iterator for CsvRecords<R, T> {
+        type Item = CsvImportResult<CsvRowOutcome<T>>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            match self.reader.read_byte_record(&mut self.row) {
+                Ok(true) => {},
+                Ok(false) => return None,
+                Err(e) => return Some(malformed_row(e)),
+            }
+            let row_number = self.row.position().map(|p| p.line()).unwrap_or(0);
+            let mut errors = Vec::new();
+            let mut cells = Vec::with_capacity(self.columns.len());
+            for (index, column, field) in &self.columns {
+                match std::str::from_utf8(self.row.get(*index).unwrap_or(b"")) {
+                    Ok(value) => cells.push((column.as_str(), field, value.trim())),
+                    Err(_) => errors.push(field_err(column, field, FieldParseErr::InvalidUtf8)),
+                }
+            }
+            let outcome = match T::from_csv_fields(&cells) {
+                Ok(record) if errors.is_empty() => CsvRowOutcome::Imported(record),
+                Ok(_) => CsvRowOutcome::Rejected(RowRejection { row_number: row_number, errors: errors }),
+                Err(mut field_errors) => {
+                    // A cell rejected as invalid UTF-8 never reached `from_csv_fields`, which then reports it missing
+                    field_errors.retain(|e| e.error != FieldParseErr::MissingValue || !errors.iter().any(|u| u.field == e.field));
+                    errors.append(&mut field_errors);
+                    CsvRowOutcome::Rejected(RowRejection { row_number: row_number, errors: errors })
+                },
+            };
+            Some(Ok(outcome))
+        }
+    }
+
+    /// Turns a row-level parse error into a `Malformed` outcome so the import can carry on; I/O errors stay errors.
+    fn malformed_row<T: CsvImportable>(e: csv::Error) -> CsvImportResult<CsvRowOutcome<T>> {
+        let row_number = e.position().map(|p| p.line()).unwrap_or(0);
+        let error = match e.kind() {
+            csv::ErrorKind::Io(_)                                    => return Err(CsvImportErr::from(e)),
+            csv::ErrorKind::UnequalLengths { expected_len, len, .. } => format!("expected {} fields, found {}", expected_len, len),
+            _                                                        => "malformed row".to_string(),
+        };
+        Ok(CsvRowOutcome::Malformed(MalformedRow { row_number: row_number, error: error }))
+    }
+
+    /// Writes the reject file: a CSV with the columns `row`, `column`, `field` and `error`.
+    pub struct RejectWriter<W: Write> {
+        writer: Writer<W>,
+    }
+
+    impl<W: Write> RejectWriter<W> {
+        pub fn new(writer: W) -> CsvImportResult<Self> {
+            let mut writer = Writer::from_writer(writer);
+            writer.write_record(["row", "column", "field", "error"])?;
+            Ok(RejectWriter { writer: writer })
+        }
+
+        pub fn write_rejection<Field: fmt::Display>(&mut self, rejection: &RowRejection<Field>) -> CsvImportResult<()> {
+            for e in &rejection.errors {
+                self.writer.write_record([rejection.row_number.to_string(), e.column.clone(), e.field.to_string(), e.error.to_string()])?;
+            }
+            Ok(())
+        }
+
+        /// Writes a single line for the whole row, with the column and field left empty.
+        pub fn write_malformed_row(&mut self, malformed: &MalformedRow) -> CsvImportResult<()> {
+            self.writer.write_record([malformed.row_number.to_string(), String::new(), String::new(), malformed.error.clone()])?;
+            Ok(())
+        }
+
+        pub fn flush(&mut self) -> CsvImportResult<()> {
+            self.writer.flush().map_err(|e| CsvImportErr::CsvError(csv::Error::from(e)))
+        }
+    }
+}
+
+pub mod export {
+    use std::collections::BTreeSet;
+    use std::fmt;
+    use std::io::Write;
+    use std::sync::Arc;
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use csv::Writer;
+    use parquet::basic::{Compression, ConvertedType, Repetition, Type as PhysicalType};
+    use parquet::data_type::{ByteArray, ByteArrayType};
+    use parquet::errors::ParquetError;
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::schema::types::Type;
+    use super::entity::*;
+    use super::human::*;
+    use super::option_date_time::OptionDate;
+
+    #[derive(Debug)]
+    pub enum ExportErr {
+        CsvError(csv::Error),
+        ParquetError(ParquetError),
+        IoError(std::io::Error),
+    }
+    pub type ExportResult<T> = Result<T, ExportErr>;
+
+    impl From<csv::Error> for ExportErr {
+        fn from(e: csv::Error) -> Self {
+            ExportErr::CsvError(e)
+        }
+    }
+
+    impl From<ParquetError> for ExportErr {
+        fn from(e: ParquetError) -> Self {
+            ExportErr::ParquetError(e)
+        }
+    }
+
+    impl From<std::io::Error> for ExportErr {
+        fn from(e: std::io::Error) -> Self {
+            ExportErr::IoError(e)
+        }
+    }
+
+    impl fmt::Display for ExportErr {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                ExportErr::CsvError(e)     => write!(f, "CSV error: {}", e),
+                ExportErr::ParquetError(e) => write!(f, "Parquet error: {}", e),
+                ExportErr::IoError(e)      => write!(f, "I/O error: {}", e),
+            }
+        }
+    }
+
+    /// How much identifying information an export may carry.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum RedactionProfile {
+        /// Everything, including the unencrypted TIN.
+        Full,
+        /// Everything, but the TIN is written in its masked display form (`XXX-XX-6789`).
+        MaskedTin,
+        /// The HIPAA Safe Harbor method: no names, TIN, phone numbers, email addresses or employers; birth dates
+        /// reduced to the year, with everyone aged 90 or over collapsed into a single "YYYY or earlier" year; addresses
+        /// reduced to state and the first three digits of the ZIP code, written as "000" for `RESTRICTED_ZIP3S`.
+        DeIdentified,
+    }
+
+    /// ZIP3 areas with 20,000 or fewer people, which Safe Harbor requires to be written as "000". This is the list
+    /// HHS publishes from the 2000 Census.
+    pub const RESTRICTED_ZIP3S: [&str; 17] = [
+        "036", "059", "063", "102", "203", "556", "692", "790", "821", "823", "830", "831", "878", "879", "884", "890", "893",
+    ];
+
+    /// Safe Harbor treats ages over 89 as a single category.
+    pub const OLDEST_REPORTABLE_AGE: i64 = 89;
+
+    /// The de-identified birth year: ages are worked out from years alone, so anyone who may be 90 by the end of
+    /// `reference_year` is collapsed into the "or earlier" year.
+    fn deidentified_birth_year(year: i64, reference_year: i64) -> String {
+        let earliest_reportable_year = reference_year - OLDEST_REPORTABLE_AGE;
+        if year < earliest_reportable_year {
+            format!("{:04} or earlier", earliest_reportable_year - 1)
+        } else {
+            format!("{:04}", year)
+        }
+    }
+
+    /// The first three digits of a US ZIP code, "000" for restricted areas, and nothing for other postal codes.
+    fn deidentified_zip3(postal_code: &str) -> Option<String> {
+        let zip3: String = postal_code.chars().take(3).collect();
+        if zip3.len() < 3 || !zip3.chars().all(|c| c.is_ascii_digit()) {
+            None
+        } else if RESTRICTED_ZIP3S.contains(&zip3.as_str()) {
+            Some("000".to_string())
+        } else {
+            Some(zip3)
+        }
+    }
+
+    /// The current calendar year in UTC.
+    pub fn current_year() -> i64 {
+        let days = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() / 86400).unwrap_or(0) as i64;
+        // Howard Hinnant's civil_from_days, year only
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let day_of_era = z - era * 146097;
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        year_of_era + era * 400 + if month_index >= 10 { 1 } else { 0 }
+    }
+
+    const NAME_COLUMNS: [(NameComponentRole, &str); 7] = [
+        (NameComponentRole::Given,            "name_given"),
+        (NameComponentRole::Middle,           "name_middle"),
+        (NameComponentRole::Family,           "name_family"),
+        (NameComponentRole::Maiden,           "name_maiden"),
+        (NameComponentRole::MothersMaiden,    "name_mothers_maiden"),
+        (NameComponentRole::FathersName,      "name_fathers"),
+        (NameComponentRole::GrandfathersName, "name_grandfathers"),
+    ];
+
+    fn column_suffix(purpose: &str) -> String {
+        purpose.chars().map(|c| if c.is_alphanumeric() { c.to_ascii_lowercase() } else { '_' }).collect()
+    }
+
+    fn format_birth_date(date: &OptionDate) -> Option<String> {
+        match (date.get_year(), date.get_month(), date.get_day()) {
+            (Some(y), Some(m), Some(d)) => Some(format!("{:04}-{:02}-{:02}", y, m, d)),
+            (Some(y), Some(m), None)    => Some(format!("{:04}-{:02}", y, m)),
+            (Some(y), None, _)          => Some(format!("{:04}", y)),
+            (None, _, _)                => None,
+        }
+    }
+
+    fn non_empty(s: &str) -> Option<String> {
+        if s.is_empty() { None } else { Some(s.to_string()) }
+    }
+
+    /// The flat column layout of an export: which contact purposes get columns, and under which redaction profile.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct HumanExportLayout {
+        profile: RedactionProfile,
+        reference_year: i64,
+        address_types: Vec<AddressType>,
+        phone_number_types: Vec<PhoneNumberType>,
+        email_address_types: Vec<EmailAddressType>,
+    }
+
+    impl HumanExportLayout {
+        pub fn new(profile: RedactionProfile, address_types: Vec<AddressType>, phone_number_types: Vec<PhoneNumberType>, email_address_types: Vec<EmailAddressType>) -> Self {
+            let ret_val = HumanExportLayout { profile: profile, reference_year: current_year(), address_types: address_types, phone_number_types: phone_number_types, email_address_types: email_address_types };
+            ret_val
+        }
+
+        /// A layout with one column per contact purpose found in any of `records`, in sorted order.
+        pub fn from_records<'a, I: IntoIterator<Item = &'a Human>>(profile: RedactionProfile, records: I) -> Self {
+            let mut address_types = BTreeSet::new();
+            let mut phone_number_types = BTreeSet::new();
+            let mut email_address_types = BTreeSet::new();
+            for h in records {
+                address_types.extend(h.get_addresses().keys().cloned());
+                phone_number_types.extend(h.get_phone_numbers().keys().cloned());
+                email_address_types.extend(h.get_email_addresses().keys().cloned());
+            }
+            HumanExportLayout::new(profile, address_types.into_iter().collect(), phone_number_types.into_iter().collect(), email_address_types.into_iter().collect())
+        }
+
+        /// The year ages are worked out against for `DeIdentified` birth years; the current year by default.
+        pub fn with_reference_year(mut self, reference_year: i64) -> Self {
+            self.reference_year = reference_year;
+            self
+        }
+
+        pub fn get_profile(&self) -> RedactionProfile {
+            self.profile
+        }
+
+        pub fn get_reference_year(&self) -> i64 {
+            self.reference_year
+        }
+
+        pub fn get_column_names(&self) -> Vec<String> {
+            let mut ret_val = Vec::new();
+            if self.profile == RedactionProfile::DeIdentified {
+                ret_val.push("birth_year".to_string());
+                for t in &self.address_types {
+                    let suffix = column_suffix(t);
+                    ret_val.push(format!("address_{}_state", suffix));
+                    ret_val.push(format!("address_{}_zip3", suffix));
+                }
+                return ret_val;
+            }
+            ret_val.extend(NAME_COLUMNS.iter().map(|(_, c)| c.to_string()));
+            ret_val.push("ssn".to_string());
+            ret_val.push("birth_date".to_string());
+            ret_val.extend(self.address_types.iter().map(|t| format!("address_{}", column_suffix(t))));
+            ret_val.extend(self.phone_number_types.iter().map(|t| format!("phone_{}", column_suffix(t))));
+            ret_val.extend(self.email_address_types.iter().map(|t| format!("email_{}", column_suffix(t))));
+            ret_val.push("employer_names".to_string());
+            ret_val
+        }
+
+        /// One value per column of `get_column_names`, `None` where the record has nothing to put there.
+        pub fn flatten(&self, human: &Human) -> Vec<Option<String>> {
+            let mut ret_val = Vec::new();
+            if self.profile == RedactionProfile::DeIdentified {
+                ret_val.push(human.get_birth_date().get_year().map(|y| deidentified_birth_year(y, self.reference_year)));
+                for t in &self.address_types {
+                    match human.get_addresses().get(t) {
+                        Some(addr) => {
+                            ret_val.push(non_empty(&addr.get_state_or_province()));
+                            ret_val.push(deidentified_zip3(&addr.get_zip_or_postal_code()));
+                        },
+                        None => {
+                            ret_val.push(None);
+                            ret_val.push(None);
+                        },
+                    }
+                }
+                return ret_val;
+            }
+            let name = human.get_name();
+            for (role, _) in &NAME_COLUMNS {
+                let parts: Vec<String> = name.get_components_with_role(*role).iter().map(|n| n.to_string()).collect();
+                ret_val.push(non_empty(&parts.join(" ")));
+            }
+            ret_val.push(match self.profile {
+                RedactionProfile::Full => non_empty(&human.get_ssn().as_unencrypted_string()),
+                _                      => Some(human.get_ssn().to_string()),
+            });
+            ret_val.push(format_birth_date(human.get_birth_date()));
+            ret_val.extend(self.address_types.iter().map(|t| human.get_addresses().get(t).map(|a| a.to_string())));
+            ret_val.extend(self.phone_number_types.iter().map(|t| human.get_phone_numbers().get(t).cloned()));
+            ret_val.extend(self.email_address_types.iter().map(|t| human.get_email_addresses().get(t).cloned()));
+            let mut employer_names: Vec<String> = human.get_employers().iter().map(|e| e.get_name().to_string()).collect();
+            employer_names.sort();
+            ret_val.push(non_empty(&employer_names.join("; ")));
+            ret_val
+        }
+    }
+
+    /// Writes flattened `Human` records as CSV, one row per record, with a header row.
+    pub struct HumanCsvExporter<W: Write> {
+        layout: HumanExportLayout,
+        writer: Writer<W>,
+    }
+
+    impl<W: Write> HumanCsvExporter<W> {
+        pub fn new(layout: HumanExportLayout, writer: W) -> ExportResult<Self> {
+            let mut writer = Writer::from_writer(writer);
+            writer.write_record(layout.get_column_names())?;
+            Ok(HumanCsvExporter { layout: layout, writer: writer })
+        }
+
+        pub fn write_human(&mut self, human: &Human) -> ExportResult<()> {
+            let row = self.layout.flatten(human);
+            self.writer.write_record(row.iter().map(|v| v.as_deref().unwrap_or("")))?;
+            Ok(())
+        }
+
+        pub fn finish(mut self) -> ExportResult<W> {
+            self.writer.flush()?;
+            self.writer.into_inner().map_err(|e| ExportErr::IoError(e.into_error()))
+        }
+    }
+
+    /// Writes flattened `Human` records as Apache Parquet. Every column is an optional UTF-8 string; rows are
+    /// buffered and written out `row_group_size` at a time, with Snappy compression.
+    pub struct HumanParquetExporter<W: Write + Send> {
+        layout: HumanExportLayout,
+        writer: SerializedFileWriter<W>,
+        row_group_size: usize,
+        buffered_rows: Vec<Vec<Option<String>>>,
+    }
+
+    pub const DEFAULT_ROW_GROUP_SIZE: usize = 64 * 1024;
+
+    impl<W: Write + Send> HumanParquetExporter<W> {
+        pub fn new(layout: HumanExportLayout, writer: W) -> ExportResult<Self> {
+            HumanParquetExporter::with_row_group_size(layout, writer, DEFAULT_ROW_GROUP_SIZE)
+        }
+
+        pub fn with_row_group_size(layout: HumanExportLayout, writer: W, row_group_size: usize) -> ExportResult<Self> {
+            let mut fields = Vec::new();
+            for name in layout.get_column_names() {
+                let field = Type::primitive_type_builder(&name, PhysicalType::BYTE_ARRAY)
+                    .with_repetition(Repetition::OPTIONAL)
+                    .with_converted_type(ConvertedType::UTF8)
+                    .build()?;
+                fields.push(Arc::new(field));
+            }
+            let schema = Type::group_type_builder("human").with_fields(fields).build()?;
+            let props = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+            let writer = SerializedFileWriter::new(writer, Arc::new(schema), Arc::new(props))?;
+            let ret_val = HumanParquetExporter { layout: layout, writer: writer, row_group_size: row_group_size.max(1), buffered_rows: Vec::new() };
+            Ok(ret_val)
+        }
+
+        pub fn write_human(&mut self, human: &Human) -> ExportResult<()> {
+            self.buffered_rows.push(self.layout.flatten(human));
+            if self.buffered_rows.len() >= self.row_group_size {
+                self.flush_row_group()?;
+            }
+            Ok(())
+        }
+
+        fn flush_row_group(&mut self) -> ExportResult<()> {
+            if self.buffered_rows.is_empty() {
+                return Ok(());
+            }
+            let mut row_group_writer = self.writer.next_row_group()?;
+            let mut column_index = 0;
+            while let Some(mut column_writer) = row_group_writer.next_column()? {
+                let mut values = Vec::new();
+                let mut def_levels = Vec::with_capacity(self.buffered_rows.len());
+                for row in &self.buffered_rows {
+                    match &row[column_index] {
+                        Some(v) => {
+                            values.push(ByteArray::from(v.as_str()));
+                            def_levels.push(1);
+                        },
+                        None => def_levels.push(0),
+                    }
+                }
+                column_writer.typed::<ByteArrayType>().write_batch(&values, Some(&def_levels), None)?;
+                column_writer.close()?;
+                column_index += 1;
+            }
+            row_group_writer.close()?;
+            self.buffered_rows.clear();
+            Ok(())
+        }
+
+        pub fn finish(mut self) -> ExportResult<W> {
+            self.flush_row_group()?;
+            Ok(self.writer.into_inner()?)
+        }
+    }
+}
+
+pub mod record_linkage {
+    use std::collections::HashMap;
+    use std::fmt;
+    use super::address_comparison::{AddressComparator, StandardizedAddress};
+    use super::date_comparison::{BirthDateComparator, DateMatchKind};
+    use super::human::Human;
+    use super::name_comparison::HumanNameComparator;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    pub enum LinkageField {
+        Name,
+        BirthDate,
+        Tin,
+        Address,
+        PhoneNumber,
+        EmailAddress,
+    }
+
+    pub const ALL_LINKAGE_FIELDS: [LinkageField; 6] = [
+        LinkageField::Name,
+        LinkageField::BirthDate,
+        LinkageField::Tin,
+        LinkageField::Address,
+        LinkageField::PhoneNumber,
+        LinkageField::EmailAddress,
+    ];
+
+    impl fmt::Display for LinkageField {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                LinkageField::Name         => write!(f, "name"),
+                LinkageField::BirthDate    => write!(f, "birth_date"),
+                LinkageField::Tin          => write!(f, "tin"),
+                LinkageField::Address      => write!(f, "address"),
+                LinkageField::PhoneNumber  => write!(f, "phone_number"),
+                LinkageField::EmailAddress => write!(f, "email_address"),
+            }
+        }
+    }
+
+    /// The outcome of comparing one field of two records. `Missing` means at least one side had no value, and
+    /// contributes nothing to the match weight.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum FieldComparison {
+        Agree,
+        Disagree,
+        Missing,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct ComparisonVector {
+        comparisons: Vec<(LinkageField, FieldComparison)>,
+    }
+
+    impl ComparisonVector {
+        pub fn new(comparisons: Vec<(LinkageField, FieldComparison)>) -> Self {
+            let ret_val = ComparisonVector { comparisons: comparisons };
+            ret_val
+        }
+
+        pub fn get_comparisons(&self) -> &Vec<(LinkageField, FieldComparison)> {
+            &self.comparisons
+        }
+
+        pub fn get(&self, field: LinkageField) -> FieldComparison {
+            self.comparisons.iter().find(|(f, _)| *f == field).map(|(_, c)| *c).unwrap_or(FieldComparison::Missing)
+        }
+    }
+
+    fn digits_only(s: &str) -> String {
+        s.chars().filter(|c| c.is_ascii_digit()).collect()
+    }
+
+    fn alphanumerics_only(s: &str) -> String {
+        s.chars().filter(|c| c.is_alphanumeric()).flat_map(|c| c.to_lowercase()).collect()
+    }
+
+    fn agreement(agree: bool) -> FieldComparison {
+        if agree { FieldComparison::Agree } else { FieldComparison::Disagree }
+    }
+
+    /// Turns a pair of `Human`s into a `ComparisonVector`, field by field.
+    #[derive(Debug, Clone)]
+    pub struct RecordComparator {
+        fields: Vec<LinkageField>,
+        name_comparator: HumanNameComparator,
+        name_similarity_threshold: f64,
+        birth_date_comparator: BirthDateComparator,
+        birth_date_similarity_threshold: f64,
+        address_comparator: AddressComparator,
+        address_similarity_threshold: f64,
+    }
+
+    pub const DEFAULT_NAME_SIMILARITY_THRESHOLD: f64 = 0.9;
+    /// Low enough that a transposed day and month or a single mistyped digit still agree.
+    pub const DEFAULT_BIRTH_DATE_SIMILARITY_THRESHOLD: f64 = 0.8;
+    pub const DEFAULT_ADDRESS_SIMILARITY_THRESHOLD: f64 = 0.9;
+
+    impl RecordComparator {
+        pub fn new() -> Self {
+            RecordComparator::with_fields(ALL_LINKAGE_FIELDS.to_vec())
+        }
+
+        pub fn with_fields(fields: Vec<LinkageField>) -> Self {
+            let ret_val = RecordComparator { fields: fields, name_comparator: HumanNameComparator::new(), name_similarity_threshold: DEFAULT_NAME_SIMILARITY_THRESHOLD, birth_date_comparator: BirthDateComparator::new(), birth_date_similarity_threshold: DEFAULT_BIRTH_DATE_SIMILARITY_THRESHOLD, address_comparator: AddressComparator::new(), address_similarity_threshold: DEFAULT_ADDRESS_SIMILARITY_THRESHOLD };
+            ret_val
+        }
+
+        pub fn get_fields(&self) -> &Vec<LinkageField> {
+            &self.fields
+        }
+
+        /// Compares names component by component; see `HumanNameComparator`.
+        pub fn set_name_comparator(&mut self, comparator: HumanNameComparator) {
+            self.name_comparator = comparator;
+        }
+
+        /// `HumanNameComparator` similarity at or above which two names count as agreeing.
+        pub fn set_name_similarity_threshold(&mut self, threshold: f64) {
+            self.name_similarity_threshold = threshold;
+        }
+
+        /// Scores birth dates allowing for common typing errors; see `BirthDateComparator`.
+        pub fn set_birth_date_comparator(&mut self, comparator: BirthDateComparator) {
+            self.birth_date_comparator = comparator;
+        }
+
+        /// `BirthDateComparator` similarity at or above which two birth dates count as agreeing. Partial dates that
+        /// agree on every component both know but fall short of it count as `Missing`.
+        pub fn set_birth_date_similarity_threshold(&mut self, threshold: f64) {
+            self.birth_date_similarity_threshold = threshold;
+        }
+
+        /// Compares addresses component by component; see `AddressComparator`.
+        pub fn set_address_comparator(&mut self, comparator: AddressComparator) {
+            self.address_comparator = comparator;
+        }
+
+        /// `AddressComparator` similarity at or above which two addresses count as agreeing.
+        pub fn set_address_similarity_threshold(&mut self, threshold: f64) {
+            self.address_similarity_threshold = threshold;
+        }
+
+        pub fn compare(&self, a: &Human, b: &Human) -> ComparisonVector {
+            ComparisonVector::new(self.fields.iter().map(|f| (*f, self.compare_field(*f, a, b))).collect())
+        }
+
+        pub fn compare_field(&self, field: LinkageField, a: &Human, b: &Human) -> FieldComparison {
+            match field {
+                LinkageField::Name         => {
+                    if a.get_name().get_components().is_empty() || b.get_name().get_components().is_empty() {
+                        return FieldComparison::Missing;
+                    }
+                    agreement(self.name_comparator.get_similarity(a.get_name(), b.get_name()) >= self.name_similarity_threshold)
+                },
+                LinkageField::BirthDate    => {
+                    let comparison = self.birth_date_comparator.compare(a.get_birth_date(), b.get_birth_date());
+                    let agrees = comparison.similarity >= self.birth_date_similarity_threshold;
+                    // Agreeing on too little of the date to pass the threshold (the year alone, say) is not evidence
+                    // against a match, so it counts as missing rather than as a disagreement
+                    if comparison.kind == DateMatchKind::Missing || (comparison.kind == DateMatchKind::PartialAgreement && !agrees) {
+                        return FieldComparison::Missing;
+                    }
+                    agreement(agrees)
+                },
+                LinkageField::Tin          => {
+                    let tin_a = alphanumerics_only(&a.get_ssn().as_unencrypted_string());
+                    let tin_b = alphanumerics_only(&b.get_ssn().as_unencrypted_string());
+                    if tin_a.is_empty() || tin_b.is_empty() {
+                        return FieldComparison::Missing;
+                    }
+                    agreement(tin_a == tin_b)
+                },
+                LinkageField::Address      => {
+                    let addrs_a: Vec<StandardizedAddress> = a.get_addresses().values().map(|addr| self.address_comparator.standardize(addr)).collect();
+                    let addrs_b: Vec<StandardizedAddress> = b.get_addresses().values().map(|addr| self.address_comparator.standardize(addr)).collect();
+                    if addrs_a.is_empty() || addrs_b.is_empty() {
+                        return FieldComparison::Missing;
+                    }
+                    agreement(addrs_a.iter().any(|x| addrs_b.iter().any(|y| self.address_comparator.compare_standardized(x, y).similarity >= self.address_similarity_threshold)))
+                },
+                LinkageField::PhoneNumber  => {
+                    let phones_a: Vec<String> = a.get_phone_numbers().values().map(|p| digits_only(p)).filter(|p| !p.is_empty()).collect();
+                    let phones_b: Vec<String> = b.get_phone_numbers().values().map(|p| digits_only(p)).filter(|p| !p.is_empty()).collect();
+                    if phones_a.is_empty() || phones_b.is_empty() {
+                        return FieldComparison::Missing;
+                    }
+                    agreement(phones_a.iter().any(|p| phones_b.contains(p)))
+                },
+                LinkageField::EmailAddress => {
+                    let emails_a: Vec<String> = a.get_email_addresses().values().map(|e| e.trim().to_lowercase()).filter(|e| !e.is_empty()).collect();
+                    let emails_b: Vec<String> = b.get_email_addresses().values().map(|e| e.trim().to_lowercase()).filter(|e| !e.is_empty()).collect();
+                    if emails_a.is_empty() || emails_b.is_empty() {
+                        return FieldComparison::Missing;
+                    }
+                    agreement(emails_a.iter().any(|e| emails_b.contains(e)))
+                },
+            }
+        }
+    }
+
+    impl Default for RecordComparator {
+        fn default() -> Self {
+            RecordComparator::new()
+        }
+    }
+
+    /// `m` is the probability that the field agrees given the pair is a true match; `u` the probability that it
+    /// agrees given the pair is not.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct FieldParameters {
+        pub m: f64,
+        pub u: f64,
+    }
+
+    const MIN_PROBABILITY: f64 = 1e-6;
+    const MAX_PROBABILITY: f64 = 1.0 - 1e-6;
+
+    fn clamp_probability(p: f64) -> f64 {
+        p.clamp(MIN_PROBABILITY, MAX_PROBABILITY)
+    }
+
+    impl FieldParameters {
+        pub fn new(m: f64, u: f64) -> Self {
+            let ret_val = FieldParameters { m: clamp_probability(m), u: clamp_probability(u) };
+            ret_val
+        }
+
+        pub fn agreement_weight(&self) -> f64 {
+            (self.m / self.u).log2()
+        }
+
+        pub fn disagreement_weight(&self) -> f64 {
+            ((1.0 - self.m) / (1.0 - self.u)).log2()
+        }
+
+        pub fn weight(&self, comparison: FieldComparison) -> f64 {
+            match comparison {
+                FieldComparison::Agree    => self.agreement_weight(),
+                FieldComparison::Disagree => self.disagreement_weight(),
+                FieldComparison::Missing  => 0.0,
+            }
+        }
+    }
+
+    /// Total log2 match weight of a pair, along with each field's contribution.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct MatchWeight {
+        pub total: f64,
+        pub field_weights: Vec<(LinkageField, f64)>,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct EmEstimate {
+        pub iterations: usize,
+        pub converged: bool,
+        pub log_likelihood: f64,
+    }
+
+    pub const DEFAULT_M_PROBABILITY: f64 = 0.9;
+    pub const DEFAULT_U_PROBABILITY: f64 = 0.1;
+    pub const DEFAULT_MATCH_PROPORTION: f64 = 0.1;
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct FellegiSunterModel {
+        parameters: HashMap<LinkageField, FieldParameters>,
+        match_proportion: f64,
+    }
+
+    impl FellegiSunterModel {
+        /// A model over `fields` with generic starting parameters, suitable as the starting point for `estimate_parameters`.
+        pub fn new(fields: &[LinkageField]) -> Self {
+            let parameters = fields.iter().map(|f| (*f, FieldParameters::new(DEFAULT_M_PROBABILITY, DEFAULT_U_PROBABILITY))).collect();
+            let ret_val = FellegiSunterModel { parameters: parameters, match_proportion: DEFAULT_MATCH_PROPORTION };
+            ret_val
+        }
+
+        pub fn with_parameters(parameters: HashMap<LinkageField, FieldParameters>, match_proportion: f64) -> Self {
+            let ret_val = FellegiSunterModel { parameters: parameters, match_proportion: clamp_probability(match_proportion) };
+            ret_val
+        }
+
+        pub fn get_parameters(&self, field: LinkageField) -> Option<&FieldParameters> {
+            self.parameters.get(&field)
+        }
+
+        pub fn set_parameters(&mut self, field: LinkageField, parameters: FieldParameters) {
+            self.parameters.insert(field, parameters);
+        }
+
+        /// Estimated proportion of compared pairs that are true matches.
+        pub fn get_match_proportion(&self) -> f64 {
+            self.match_proportion
+        }
+
+        /// Fields the model has no parameters for are ignored.
+        pub fn match_weight(&self, vector: &ComparisonVector) -> MatchWeight {
+            let mut field_weights = Vec::new();
+            let mut total = 0.0;
+            for (field, comparison) in vector.get_comparisons() {
+                if let Some(params) = self.parameters.get(field) {
+                    let w = params.weight(*comparison);
+                    total += w;
+                    field_weights.push((*field, w));
+                }
+            }
+            MatchWeight { total: total, field_weights: field_weights }
+        }
+
+        /// log P(vector | match) and log P(vector | non-match), assuming fields are conditionally independent.
+        fn log_likelihoods(&self, vector: &ComparisonVector) -> (f64, f64) {
+            let mut log_m = 0.0;
+            let mut log_u = 0.0;
+            for (field, comparison) in vector.get_comparisons() {
+                if let Some(params) = self.parameters.get(field) {
+                    match comparison {
+                        FieldComparison::Agree    => {
+                            log_m += params.m.ln();
+                            log_u += params.u.ln();
+                        },
+                        FieldComparison::Disagree => {
+                            log_m += (1.0 - params.m).ln();
+                            log_u += (1.0 - params.u).ln();
+                        },
+                        FieldComparison::Missing  => {},
+                    }
+                }
+            }
+            (log_m, log_u)
+        }
+
+        /// Posterior probability that the pair behind `vector` is a match.
+        pub fn match_probability(&self, vector: &ComparisonVector) -> f64 {
+            let (log_m, log_u) = self.log_likelihoods(vector);
+            let log_match = self.match_proportion.ln() + log_m;
+            let log_non_match = (1.0 - self.match_proportion).ln() + log_u;
+            1.0 / (1.0 + (log_non_match - log_match).exp())
+        }
+
+        /// Fits `m`, `u` and the match proportion to unlabelled comparison vectors with expectation-maximisation,
+        /// starting from the model's current parameters. Start with `m > u` for every field, or the two classes may
+        /// come out swapped.
+        pub fn estimate_parameters(&mut self, vectors: &[ComparisonVector], max_iterations: usize, convergence_threshold: f64) -> EmEstimate {
+            let mut estimate = EmEstimate { iterations: 0, converged: false, log_likelihood: f64::NEG_INFINITY };
+            if vectors.is_empty() {
+                return estimate;
+            }
+            let fields: Vec<LinkageField> = self.parameters.keys().cloned().collect();
+            while estimate.iterations < max_iterations {
+                estimate.iterations += 1;
+                // E-step
+                let mut log_likelihood = 0.0;
+                let mut posteriors = Vec::with_capacity(vectors.len());
+                for v in vectors {
+                    let (log_m, log_u) = self.log_likelihoods(v);
+                    let log_match = self.match_proportion.ln() + log_m;
+                    let log_non_match = (1.0 - self.match_proportion).ln() + log_u;
+                    let max = log_match.max(log_non_match);
+                    let log_total = max + ((log_match - max).exp() + (log_non_match - max).exp()).ln();
+                    log_likelihood += log_total;
+                    posteriors.push((log_match - log_total).exp());
+                }
+                // M-step
+                let mut max_change: f64 = 0.0;
+                for field in &fields {
+                    let (mut m_agree, mut m_total, mut u_agree, mut u_total) = (0.0, 0.0, 0.0, 0.0);
+                    for (v, g) in vectors.iter().zip(&posteriors) {
+                        match v.get(*field) {
+                            FieldComparison::Agree    => {
+                                m_agree += g;
+                                m_total += g;
+                                u_agree += 1.0 - g;
+                                u_total += 1.0 - g;
+                            },
+                            FieldComparison::Disagree => {
+                                m_total += g;
+                                u_total += 1.0 - g;
+                            },
+                            FieldComparison::Missing  => {},
+                        }
+                    }
+                    let old = self.parameters[field];
+                    let m = if m_total > 0.0 { m_agree / m_total } else { old.m };
+                    let u = if u_total > 0.0 { u_agree / u_total } else { old.u };
+                    let new = FieldParameters::new(m, u);
+                    max_change = max_change.max((new.m - old.m).abs()).max((new.u - old.u).abs());
+                    self.parameters.insert(*field, new);
+                }
+                let p = clamp_probability(posteriors.iter().sum::<f64>() / vectors.len() as f64);
+                max_change = max_change.max((p - self.match_proportion).abs());
+                self.match_proportion = p;
+                estimate.log_likelihood = log_likelihood;
+                if max_change < convergence_threshold {
+                    estimate.converged = true;
+                    break;
+                }
+            }
+            estimate
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum LinkageClass {
+        Match,
+        PossibleMatch,
+        NonMatch,
+    }
+
+    /// Pairs weighing at least `upper` are matches, pairs weighing less than `lower` are non-matches, and
+    /// everything in between is left for clerical review.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct LinkageThresholds {
+        lower: f64,
+        upper: f64,
+    }
+
+    /// The lower threshold was above the upper one, or one of them was NaN.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct LinkageThresholdsErr;
+    pub type LinkageThresholdsResult = Result<LinkageThresholds, LinkageThresholdsErr>;
+
+    impl LinkageThresholds {
+        pub fn new(lower: f64, upper: f64) -> LinkageThresholdsResult {
+            if lower.is_nan() || upper.is_nan() || lower > upper {
+                return Err(LinkageThresholdsErr);
+            }
+            let ret_val = LinkageThresholds { lower: lower, upper: upper };
+            Ok(ret_val)
+        }
+
+        pub fn get_lower(&self) -> f64 {
+            self.lower
+        }
+
+        pub fn get_upper(&self) -> f64 {
+            self.upper
+        }
+
+        pub fn classify(&self, weight: f64) -> LinkageClass {
+            if weight >= self.upper {
+                LinkageClass::Match
+            } else if weight < self.lower {
+                LinkageClass::NonMatch
+            } else {
+                LinkageClass::PossibleMatch
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct LinkageResult {
+        pub comparison: ComparisonVector,
+        pub weight: MatchWeight,
+        pub class: LinkageClass,
+    }
+
+    /// Ties a `RecordComparator`, a `FellegiSunterModel` and `LinkageThresholds` together.
+    #[derive(Debug, Clone)]
+    pub struct RecordLinker {
+        comparator: RecordComparator,
+        model: FellegiSunterModel,
+        thresholds: LinkageThresholds,
+    }
+
+    impl RecordLinker {
+        pub fn new(comparator: RecordComparator, model: FellegiSunterModel, thresholds: LinkageThresholds) -> Self {
+            let ret_val = RecordLinker { comparator: comparator, model: model, thresholds: thresholds };
+            ret_val
+        }
+
+        pub fn get_comparator(&self) -> &RecordComparator {
+            &self.comparator
+        }
+
+        pub fn get_model(&self) -> &FellegiSunterModel {
+            &self.model
+        }
+
+        pub fn get_thresholds(&self) -> &LinkageThresholds {
+            &self.thresholds
+        }
+
+        pub fn set_thresholds(&mut self, thresholds: LinkageThresholds) {
+            self.thresholds = thresholds;
+        }
+
+        /// Re-estimates the model from unlabelled candidate pairs.
+        pub fn estimate_parameters(&mut self, pairs: &[(&Human, &Human)], max_iterations: usize, convergence_threshold: f64) -> EmEstimate {
+            let vectors: Vec<ComparisonVector> = pairs.iter().map(|(a, b)| self.comparator.compare(a, b)).collect();
+            self.model.estimate_parameters(&vectors, max_iterations, convergence_threshold)
+        }
+
+        pub fn link(&self, a: &Human, b: &Human) -> LinkageResult {
+            let comparison = self.comparator.compare(a, b);
+            let weight = self.model.match_weight(&comparison);
+            let class = self.thresholds.classify(weight.total);
+            LinkageResult { comparison: comparison, weight: weight, class: class }
+        }
+    }
+}
+
+pub mod phonetic {
+    use std::sync::Arc;
+    use super::fuzzy_matching::*;
+
+    fn ascii_letters(s: &str) -> Vec<char> {
+        s.chars().filter(|c| c.is_ascii_alphabetic()).map(|c| c.to_ascii_uppercase()).collect()
+    }
+
+    fn is_vowel(c: char) -> bool {
+        matches!(c, 'A' | 'E' | 'I' | 'O' | 'U')
+    }
+
+    fn soundex_digit(c: char) -> Option<char> {
+        match c {
+            'B' | 'F' | 'P' | 'V'                         => Some('1'),
+            'C' | 'G' | 'J' | 'K' | 'Q' | 'S' | 'X' | 'Z' => Some('2'),
+            'D' | 'T'                                     => Some('3'),
+            'L'                                           => Some('4'),
+            'M' | 'N'                                     => Some('5'),
+            'R'                                           => Some('6'),
+            _                                             => None,
+        }
+    }
+
+    /// American Soundex: the first letter followed by three digits, e.g. "Robert" -> "R163". Non-ASCII letters are
+    /// ignored; an input without any ASCII letters yields an empty code.
+    pub fn soundex(s: &str) -> String {
+        let letters = ascii_letters(s);
+        if letters.is_empty() {
+            return String::new();
+        }
+        let mut ret_val = String::with_capacity(4);
+        ret_val.push(letters[0]);
+        let mut last_digit = soundex_digit(letters[0]);
+        for &c in &letters[1..] {
+            if ret_val.len() == 4 {
+                break;
+            }
+            match soundex_digit(c) {
+                Some(d) => {
+                    if last_digit != Some(d) {
+                        ret_val.push(d);
+                    }
+                    last_digit = Some(d);
+                },
+                // H and W do not separate letters with the same code; vowels do
+                None if c == 'H' || c == 'W' => {},
+                None => last_digit = None,
+            }
+        }
+        while ret_val.len() < 4 {
+            ret_val.push('0');
+        }
+        ret_val
+    }
+
+    /// The New York State Identification and Intelligence System code, truncated to six characters as in the
+    /// original specification, e.g. "Knight" -> "NAGT".
+    pub fn nysiis(s: &str) -> String {
+        let mut chars = ascii_letters(s);
+        if chars.is_empty() {
+            return String::new();
+        }
+        // Translate the first characters of the name
+        let starts_with = |chars: &Vec<char>, prefix: &str| chars.iter().take(prefix.len()).copied().eq(prefix.chars());
+        if starts_with(&chars, "MAC") {
+            chars[1] = 'C';
+        } else if starts_with(&chars, "KN") {
+            chars[0] = 'N';
+        } else if starts_with(&chars, "K") {
+            chars[0] = 'C';
+        } else if starts_with(&chars, "PH") || starts_with(&chars, "PF") {
+            chars[0] = 'F';
+            chars[1] = 'F';
+        } else if starts_with(&chars, "SCH") {
+            chars[1] = 'S';
+            chars[2] = 'S';
+        }
+        // Translate the last characters of the name
+        let len = chars.len();
+        if len >= 2 {
+            let suffix: String = chars[len - 2..].iter().collect();
+            match suffix.as_str() {
+                "EE" | "IE"                      => {
+                    chars.truncate(len - 2);
+                    chars.push('Y');
+                },
+                "DT" | "RT" | "RD" | "NT" | "ND" => {
+                    chars.truncate(len - 2);
+                    chars.push('D');
+                },
+                _                                => {},
+            }
+        }
+        let mut key = vec![chars[0]];
+        let mut i = 1;
+        while i < chars.len() {
+            let next = chars.get(i + 1).copied();
+            let prev = chars[i - 1];
+            match chars[i] {
+                'E' if next == Some('V') => {
+                    chars[i] = 'A';
+                    chars[i + 1] = 'F';
+                },
+                c if is_vowel(c)         => chars[i] = 'A',
+                'Q'                      => chars[i] = 'G',
+                'Z'                      => chars[i] = 'S',
+                'M'                      => chars[i] = 'N',
+                'K'                      => chars[i] = if next == Some('N') { 'N' } else { 'C' },
+                'S' if next == Some('C') && chars.get(i + 2) == Some(&'H') => {
+                    chars[i + 1] = 'S';
+                    chars[i + 2] = 'S';
+                },
+                'P' if next == Some('H') => {
+                    chars[i] = 'F';
+                    chars[i + 1] = 'F';
+                },
+                'H' if !is_vowel(prev) || !next.map(is_vowel).unwrap_or(false) => chars[i] = prev,
+                'W' if is_vowel(prev)    => chars[i] = prev,
+                _                        => {},
+            }
+            if Some(&chars[i]) != key.last() {
+                key.push(chars[i]);
+            }
+            i += 1;
+        }
+        if key.len() > 1 && key.last() == Some(&'S') {
+            key.pop();
+        }
+        if key.len() > 2 && key[key.len() - 2..] == ['A', 'Y'] {
+            key.pop();
+            key.pop();
+            key.push('Y');
+        }
+        if key.len() > 1 && key.last() == Some(&'A') {
+            key.pop();
+        }
+        key.truncate(6);
+        key.into_iter().collect()
+    }
+
+    const REFINED_SOUNDEX_MAPPING: &[u8; 26] = b"01360240043788015936020505";
+
+    /// Refined Soundex: the first letter followed by a digit for every letter, vowels included, with runs of the
+    /// same digit collapsed, e.g. "testing" -> "T6036084". Unlike Soundex the code is not truncated.
+    pub fn refined_soundex(s: &str) -> String {
+        let letters = ascii_letters(s);
+        if letters.is_empty() {
+            return String::new();
+        }
+        let mut ret_val = String::with_capacity(letters.len() + 1);
+        ret_val.push(letters[0]);
+        let mut last = None;
+        for c in letters {
+            let code = REFINED_SOUNDEX_MAPPING[(c as u8 - b'A') as usize] as char;
+            if last != Some(code) {
+                ret_val.push(code);
+            }
+            last = Some(code);
+        }
+        ret_val
+    }
+
+    pub const DEFAULT_METAPHONE_LENGTH: usize = 4;
+
+    /// Lawrence Philips' original Metaphone, following the Apache Commons Codec rules, with codes of up to
+    /// `max_length` characters. `0` stands for "th".
+    pub fn metaphone(s: &str, max_length: usize) -> String {
+        let mut w = ascii_letters(s);
+        if w.is_empty() {
+            return String::new();
+        }
+        if w.len() == 1 {
+            return w[0].to_string();
+        }
+        // Silent or altered initial letters
+        match (w[0], w[1]) {
+            ('K', 'N') | ('G', 'N') | ('P', 'N') | ('A', 'E') | ('W', 'R') => {
+                w.remove(0);
+            },
+            ('W', 'H') => {
+                w.remove(1);
+            },
+            ('X', _) => w[0] = 'S',
+            _ => {},
+        }
+        let len = w.len();
+        let at = |i: usize| -> Option<char> { w.get(i).copied() };
+        let is_vowel_at = |i: usize| at(i).map(is_vowel).unwrap_or(false);
+        let is_front_vowel_at = |i: usize| matches!(at(i), Some('E') | Some('I') | Some('Y'));
+        let region_matches = |i: usize, pattern: &str| i + pattern.len() <= len && w[i..i + pattern.len()].iter().copied().eq(pattern.chars());
+        let is_last = |i: usize| i + 1 == len;
+        let mut code = String::new();
+        let mut n = 0;
+        while code.len() < max_length && n < len {
+            let symb = w[n];
+            let prev = if n > 0 { at(n - 1) } else { None };
+            if symb != 'C' && prev == Some(symb) {
+                n += 1;
+                continue;
+            }
+            match symb {
+                'A' | 'E' | 'I' | 'O' | 'U' if n == 0 => code.push(symb),
+                'B' if !(prev == Some('M') && is_last(n)) => code.push('B'),
+                'C' => {
+                    if prev == Some('S') && !is_last(n) && is_front_vowel_at(n + 1) {
+                        // SCI, SCE, SCY: silent
+                    } else if region_matches(n, "CIA") {
+                        code.push('X');
+                    } else if !is_last(n) && is_front_vowel_at(n + 1) {
+                        code.push('S');
+                    } else if prev == Some('S') && at(n + 1) == Some('H') {
+                        code.push('K');
+                    } else if at(n + 1) == Some('H') {
+                        code.push(if n == 0 && len >= 3 && is_vowel_at(2) { 'K' } else { 'X' });
+                    } else {
+                        code.push('K');
+                    }
+                },
+                'D' => {
+                    if !is_last(n + 1) && at(n + 1) == Some('G') && is_front_vowel_at(n + 2) {
+                        code.push('J');
+                        n += 2;
+                    } else {
+                        code.push('T');
+                    }
+                },
+                'G' => {
+                    let silent = (is_last(n + 1) && at(n + 1) == Some('H'))
+                        || (!is_last(n + 1) && at(n + 1) == Some('H') && !is_vowel_at(n + 2))
+                        || (n > 0 && (region_matches(n, "GN") || region_matches(n, "GNED")));
+                    if !silent {
+                        let hard = prev == Some('G');
+                        code.push(if !is_last(n) && is_front_vowel_at(n + 1) && !hard { 'J' } else { 'K' });
+                    }
+                },
+                'H' => {
+                    let after_varson = matches!(prev, Some('C') | Some('S') | Some('P') | Some('T') | Some('G'));
+                    if !is_last(n) && !after_varson && is_vowel_at(n + 1) {
+                        code.push('H');
+                    }
+                },
+                'F' | 'J' | 'L' | 'M' | 'N' | 'R' => code.push(symb),
+                'K' if prev != Some('C') => code.push('K'),
+                'P' => code.push(if at(n + 1) == Some('H') { 'F' } else { 'P' }),
+                'Q' => code.push('K'),
+                'S' => code.push(if region_matches(n, "SH") || region_matches(n, "SIO") || region_matches(n, "SIA") { 'X' } else { 'S' }),
+                'T' => {
+                    if region_matches(n, "TIA") || region_matches(n, "TIO") {
+                        code.push('X');
+                    } else if region_matches(n, "TCH") {
+                        // Silent if in "TCH"
+                    } else if region_matches(n, "TH") {
+                        code.push('0');
+                    } else {
+                        code.push('T');
+                    }
+                },
+                'V' => code.push('F'),
+                'W' | 'Y' if !is_last(n) && is_vowel_at(n + 1) => code.push(symb),
+                'X' => code.push_str("KS"),
+                'Z' => code.push('S'),
+                _ => {},
+            }
+            n += 1;
+        }
+        code.truncate(max_length);
+        code
+    }
+
+    struct DoubleMetaphoneResult {
+        primary: String,
+        alternate: String,
+        max_length: usize,
+    }
+
+    impl DoubleMetaphoneResult {
+        fn append_primary(&mut self, value: &str) {
+            for c in value.chars() {
+                if self.primary.len() < self.max_length {
+                    self.primary.push(c);
+                }
+            }
+        }
+
+        fn append_alternate(&mut self, value: &str) {
+            for c in value.chars() {
+                if self.alternate.len() < self.max_length {
+                    self.alternate.push(c);
+                }
+            }
+        }
+
+        fn append(&mut self, value: &str) {
+            self.append_primary(value);
+            self.append_alternate(value);
+        }
+
+        fn append_both(&mut self, primary: &str, alternate: &str) {
+            self.append_primary(primary);
+            self.append_alternate(alternate);
+        }
+
+        fn is_complete(&self) -> bool {
+            self.primary.len() >= self.max_length && self.alternate.len() >= self.max_length
+        }
+    }
+
+    /// The word being encoded, with the bounds-tolerant lookups the Double Metaphone rules are written in terms of.
+    struct DoubleMetaphoneWord {
+        chars: Vec<char>,
+        slavo_germanic: bool,
+    }
+
+    impl DoubleMetaphoneWord {
+        fn len(&self) -> isize {
+            self.chars.len() as isize
+        }
+
+        fn char_at(&self, index: isize) -> char {
+            if index < 0 || index >= self.len() { '\0' } else { self.chars[index as usize] }
+        }
+
+        /// Whether the `length` characters starting at `start` equal any of `criteria`.
+        fn contains(&self, start: isize, length: isize, criteria: &[&str]) -> bool {
+            if start < 0 || start + length > self.len() {
+                return false;
+            }
+            let region = &self.chars[start as usize..(start + length) as usize];
+            criteria.iter().any(|c| region.iter().copied().eq(c.chars()))
+        }
+
+        fn is_vowel_at(&self, index: isize) -> bool {
+            matches!(self.char_at(index), 'A' | 'E' | 'I' | 'O' | 'U' | 'Y')
+        }
+    }
+
+    const L_R_N_M_B_H_F_V_W_SPACE: &[&str] = &["L", "R", "N", "M", "B", "H", "F", "V", "W", " "];
+    const ES_EP_EB_EL_EY_IB_IL_IN_IE_EI_ER: &[&str] = &["ES", "EP", "EB", "EL", "EY", "IB", "IL", "IN", "IE", "EI", "ER"];
+    const L_T_K_S_N_M_B_Z: &[&str] = &["L", "T", "K", "S", "N", "M", "B", "Z"];
+
+    fn double_metaphone_c(w: &DoubleMetaphoneWord, r: &mut DoubleMetaphoneResult, mut index: isize) -> isize {
+        let condition_c0 = || {
+            if w.contains(index, 4, &["CHIA"]) {
+                true
+            } else if index <= 1 || w.is_vowel_at(index - 2) || !w.contains(index - 1, 3, &["ACH"]) {
+                false
+            } else {
+                let c = w.char_at(index + 2);
+                (c != 'I' && c != 'E') || w.contains(index - 2, 6, &["BACHER", "MACHER"])
+            }
+        };
+        if condition_c0() {
+            r.append("K");
+            index += 2;
+        } else if index == 0 && w.contains(index, 6, &["CAESAR"]) {
+            r.append("S");
+            index += 2;
+        } else if w.contains(index, 2, &["CH"]) {
+            index = double_metaphone_ch(w, r, index);
+        } else if w.contains(index, 2, &["CZ"]) && !w.contains(index - 2, 4, &["WICZ"]) {
+            r.append_both("S", "X");
+            index += 2;
+        } else if w.contains(index + 1, 3, &["CIA"]) {
+            r.append("X");
+            index += 3;
+        } else if w.contains(index, 2, &["CC"]) && !(index == 1 && w.char_at(0) == 'M') {
+            if w.contains(index + 2, 1, &["I", "E", "H"]) && !w.contains(index + 2, 2, &["HU"]) {
+                if (index == 1 && w.char_at(index - 1) == 'A') || w.contains(index - 1, 5, &["UCCEE", "UCCES"]) {
+                    r.append("KS");
+                } else {
+                    r.append("X");
+                }
+                index += 3;
+            } else {
+                r.append("K");
+                index += 2;
+            }
+        } else if w.contains(index, 2, &["CK", "CG", "CQ"]) {
+            r.append("K");
+            index += 2;
+        } else if w.contains(index, 2, &["CI", "CE", "CY"]) {
+            if w.contains(index, 3, &["CIO", "CIE", "CIA"]) {
+                r.append_both("S", "X");
+            } else {
+                r.append("S");
+            }
+            index += 2;
+        } else {
+            r.append("K");
+            if w.contains(index + 1, 2, &[" C", " Q", " G"]) {
+                index += 3;
+            } else if w.contains(index + 1, 1, &["C", "K", "Q"]) && !w.contains(index + 1, 2, &["CE", "CI"]) {
+                index += 2;
+            } else {
+                index += 1;
+            }
+        }
+        index
+    }
+
+    fn double_metaphone_ch(w: &DoubleMetaphoneWord, r: &mut DoubleMetaphoneResult, index: isize) -> isize {
+        let condition_ch0 = index == 0
+            && (w.contains(index + 1, 5, &["HARAC", "HARIS"]) || w.contains(index + 1, 3, &["HOR", "HYM", "HIA", "HEM"]))
+            && !w.contains(0, 5, &["CHORE"]);
+        let condition_ch1 = w.contains(0, 4, &["VAN ", "VON "]) || w.contains(0, 3, &["SCH"])
+            || w.contains(index - 2, 6, &["ORCHES", "ARCHIT", "ORCHID"])
+            || w.contains(index + 2, 1, &["T", "S"])
+            || ((w.contains(index - 1, 1, &["A", "O", "U", "E"]) || index == 0)
+                && (w.contains(index + 2, 1, L_R_N_M_B_H_F_V_W_SPACE) || index + 1 == w.len() - 1));
+        if index > 0 && w.contains(index, 4, &["CHAE"]) {
+            r.append_both("K", "X");
+        } else if condition_ch0 || condition_ch1 {
+            r.append("K");
+        } else if index > 0 {
+            if w.contains(0, 2, &["MC"]) {
+                r.append("K");
+            } else {
+                r.append_both("X", "K");
+            }
+        } else {
+            r.append("X");
+        }
+        index + 2
+    }
+
+    fn double_metaphone_g(w: &DoubleMetaphoneWord, r: &mut DoubleMetaphoneResult, mut index: isize) -> isize {
+        if w.char_at(index + 1) == 'H' {
+            if index > 0 && !w.is_vowel_at(index - 1) {
+                r.append("K");
+            } else if index == 0 {
+                r.append(if w.char_at(index + 2) == 'I' { "J" } else { "K" });
+            } else if (index > 1 && w.contains(index - 2, 1, &["B", "H", "D"]))
+                || (index > 2 && w.contains(index - 3, 1, &["B", "H", "D"]))
+                || (index > 3 && w.contains(index - 4, 1, &["B", "H"])) {
+                // Parker's rule: "hugh"
+            } else if index > 2 && w.char_at(index - 1) == 'U' && w.contains(index - 3, 1, &["C", "G", "L", "R", "T"]) {
+                // "laugh", "McLaughlin", "cough", "gough", "rough", "tough"
+                r.append("F");
+            } else if index > 0 && w.char_at(index - 1) != 'I' {
+                r.append("K");
+            }
+            index += 2;
+        } else if w.char_at(index + 1) == 'N' {
+            if index == 1 && w.is_vowel_at(0) && !w.slavo_germanic {
+                r.append_both("KN", "N");
+            } else if !w.contains(index + 2, 2, &["EY"]) && w.char_at(index + 1) != 'Y' && !w.slavo_germanic {
+                r.append_both("N", "KN");
+            } else {
+                r.append("KN");
+            }
+            index += 2;
+        } else if w.contains(index + 1, 2, &["LI"]) && !w.slavo_germanic {
+            r.append_both("KL", "L");
+            index += 2;
+        } else if (index == 0 && (w.char_at(index + 1) == 'Y' || w.contains(index + 1, 2, ES_EP_EB_EL_EY_IB_IL_IN_IE_EI_ER)))
+            || ((w.contains(index + 1, 2, &["ER"]) || w.char_at(index + 1) == 'Y')
+                && !w.contains(0, 6, &["DANGER", "RANGER", "MANGER"])
+                && !w.contains(index - 1, 1, &["E", "I"])
+                && !w.contains(index - 1, 3, &["RGY", "OGY"])) {
+            r.append_both("K", "J");
+            index += 2;
+        } else if w.contains(index + 1, 1, &["E", "I", "Y"]) || w.contains(index - 1, 4, &["AGGI", "OGGI"]) {
+            if w.contains(0, 4, &["VAN ", "VON "]) || w.contains(0, 3, &["SCH"]) || w.contains(index + 1, 2, &["ET"]) {
+                r.append("K");
+            } else if w.contains(index + 1, 3, &["IER"]) {
+                r.append("J");
+            } else {
+                r.append_both("J", "K");
+            }
+            index += 2;
+        } else if w.char_at(index + 1) == 'G' {
+            r.append("K");
+            index += 2;
+        } else {
+            r.append("K");
+            index += 1;
+        }
+        index
+    }
+
+    fn double_metaphone_j(w: &DoubleMetaphoneWord, r: &mut DoubleMetaphoneResult, mut index: isize) -> isize {
+        if w.contains(index, 4, &["JOSE"]) || w.contains(0, 4, &["SAN "]) {
+            if (index == 0 && w.char_at(index + 4) == ' ') || w.len() == 4 || w.contains(0, 4, &["SAN "]) {
+                r.append("H");
+            } else {
+                r.append_both("J", "H");
+            }
+            index += 1;
+        } else {
+            if index == 0 && !w.contains(index, 4, &["JOSE"]) {
+                r.append_both("J", "A");
+            } else if w.is_vowel_at(index - 1) && !w.slavo_germanic && (w.char_at(index + 1) == 'A' || w.char_at(index + 1) == 'O') {
+                r.append_both("J", "H");
+            } else if index == w.len() - 1 {
+                r.append_primary("J");
+            } else if !w.contains(index + 1, 1, L_T_K_S_N_M_B_Z) && !w.contains(index - 1, 1, &["S", "K", "L"]) {
+                r.append("J");
+            }
+            index += if w.char_at(index + 1) == 'J' { 2 } else { 1 };
+        }
+        index
+    }
+
+    fn double_metaphone_s(w: &DoubleMetaphoneWord, r: &mut DoubleMetaphoneResult, mut index: isize) -> isize {
+        if w.contains(index - 1, 3, &["ISL", "YSL"]) {
+            // "island", "isle", "carlisle", "carlysle"
+            index += 1;
+        } else if index == 0 && w.contains(index, 5, &["SUGAR"]) {
+            r.append_both("X", "S");
+            index += 1;
+        } else if w.contains(index, 2, &["SH"]) {
+            if w.contains(index + 1, 4, &["HEIM", "HOEK", "HOLM", "HOLZ"]) {
+                r.append("S");
+            } else {
+                r.append("X");
+            }
+            index += 2;
+        } else if w.contains(index, 3, &["SIO", "SIA"]) || w.contains(index, 4, &["SIAN"]) {
+            if w.slavo_germanic {
+                r.append("S");
+            } else {
+                r.append_both("S", "X");
+            }
+            index += 3;
+        } else if (index == 0 && w.contains(index + 1, 1, &["M", "N", "L", "W"])) || w.contains(index + 1, 1, &["Z"]) {
+            r.append_both("S", "X");
+            index += if w.contains(index + 1, 1, &["Z"]) { 2 } else { 1 };
+        } else if w.contains(index, 2, &["SC"]) {
+            if w.char_at(index + 2) == 'H' {
+                if w.contains(index + 3, 2, &["OO", "ER", "EN", "UY", "ED", "EM"]) {
+                    if w.contains(index + 3, 2, &["ER", "EN"]) {
+                        r.append_both("X", "SK");
+                    } else {
+                        r.append("SK");
+                    }
+                } else if index == 0 && !w.is_vowel_at(3) && w.char_at(3) != 'W' {
+                    r.append_both("X", "S");
+                } else {
+                    r.append("X");
+                }
+            } else if w.contains(index + 2, 1, &["I", "E", "Y"]) {
+                r.append("S");
+            } else {
+                r.append("SK");
+            }
+            index += 3;
+        } else {
+            if index == w.len() - 1 && w.contains(index - 2, 2, &["AI", "OI"]) {
+                r.append_alternate("S");
+            } else {
+                r.append("S");
+            }
+            index += if w.contains(index + 1, 1, &["S", "Z"]) { 2 } else { 1 };
+        }
+        index
+    }
+
+    fn double_metaphone_t(w: &DoubleMetaphoneWord, r: &mut DoubleMetaphoneResult, mut index: isize) -> isize {
+        if w.contains(index, 4, &["TION"]) || w.contains(index, 3, &["TIA", "TCH"]) {
+            r.append("X");
+            index += 3;
+        } else if w.contains(index, 2, &["TH"]) || w.contains(index, 3, &["TTH"]) {
+            if w.contains(index + 2, 2, &["OM", "AM"]) || w.contains(0, 4, &["VAN ", "VON "]) || w.contains(0, 3, &["SCH"]) {
+                r.append("T");
+            } else {
+                r.append_both("0", "T");
+            }
+            index += 2;
+        } else {
+            r.append("T");
+            index += if w.contains(index + 1, 1, &["T", "D"]) { 2 } else { 1 };
+        }
+        index
+    }
+
+    fn double_metaphone_w(w: &DoubleMetaphoneWord, r: &mut DoubleMetaphoneResult, mut index: isize) -> isize {
+        if w.contains(index, 2, &["WR"]) {
+            r.append("R");
+            index += 2;
+        } else if index == 0 && (w.is_vowel_at(index + 1) || w.contains(index, 2, &["WH"])) {
+            if w.is_vowel_at(index + 1) {
+                r.append_both("A", "F");
+            } else {
+                r.append("A");
+            }
+            index += 1;
+        } else if (index == w.len() - 1 && w.is_vowel_at(index - 1))
+            || w.contains(index - 1, 5, &["EWSKI", "EWSKY", "OWSKI", "OWSKY"])
+            || w.contains(0, 3, &["SCH"]) {
+            r.append_alternate("F");
+            index += 1;
+        } else if w.contains(index, 4, &["WICZ", "WITZ"]) {
+            r.append_both("TS", "FX");
+            index += 4;
+        } else {
+            index += 1;
+        }
+        index
+    }
+
+    pub const DEFAULT_DOUBLE_METAPHONE_LENGTH: usize = 4;
+
+    /// Lawrence Philips' Double Metaphone, following the Apache Commons Codec rules. Returns the primary and
+    /// alternate codes, each of up to `max_length` characters; they are equal when the name has only one likely
+    /// pronunciation. `0` stands for "th".
+    pub fn double_metaphone(s: &str, max_length: usize) -> (String, String) {
+        let chars: Vec<char> = s.trim().chars().flat_map(|c| c.to_uppercase()).collect();
+        let text: String = chars.iter().collect();
+        let slavo_germanic = text.contains('W') || text.contains('K') || text.contains("CZ") || text.contains("WITZ");
+        let w = DoubleMetaphoneWord { chars: chars, slavo_germanic: slavo_germanic };
+        let mut r = DoubleMetaphoneResult { primary: String::new(), alternate: String::new(), max_length: max_length };
+        if w.len() == 0 {
+            return (r.primary, r.alternate);
+        }
+        let mut index: isize = if w.contains(0, 2, &["GN", "KN", "PN", "WR", "PS"]) { 1 } else { 0 };
+        while !r.is_complete() && index < w.len() {
+            let next_is = |c: char| w.char_at(index + 1) == c;
+            index = match w.char_at(index) {
+                'A' | 'E' | 'I' | 'O' | 'U' | 'Y' => {
+                    if index == 0 {
+                        r.append("A");
+                    }
+                    index + 1
+                },
+                'B' => {
+                    r.append("P");
+                    if next_is('B') { index + 2 } else { index + 1 }
+                },
+                'Ç' => {
+                    r.append("S");
+                    index + 1
+                },
+                'C' => double_metaphone_c(&w, &mut r, index),
+                'D' => {
+                    if w.contains(index, 2, &["DG"]) {
+                        if w.contains(index + 2, 1, &["I", "E", "Y"]) {
+                            r.append("J");
+                            index + 3
+                        } else {
+                            r.append("TK");
+                            index + 2
+                        }
+                    } else if w.contains(index, 2, &["DT", "DD"]) {
+                        r.append("T");
+                        index + 2
+                    } else {
+                        r.append("T");
+                        index + 1
+                    }
+                },
+                'F' => {
+                    r.append("F");
+                    if next_is('F') { index + 2 } else { index + 1 }
+                },
+                'G' => double_metaphone_g(&w, &mut r, index),
+                'H' => {
+                    if (index == 0 || w.is_vowel_at(index - 1)) && w.is_vowel_at(index + 1) {
+                        r.append("H");
+                        index + 2
+                    } else {
+                        index + 1
+                    }
+                },
+                'J' => double_metaphone_j(&w, &mut r, index),
+                'K' => {
+                    r.append("K");
+                    if next_is('K') { index + 2 } else { index + 1 }
+                },
+                'L' => {
+                    if next_is('L') {
+                        let len = w.len();
+                        let spanish_ll = (index == len - 3 && w.contains(index - 1, 4, &["ILLO", "ILLA", "ALLE"]))
+                            || ((w.contains(len - 2, 2, &["AS", "OS"]) || w.contains(len - 1, 1, &["A", "O"])) && w.contains(index - 1, 4, &["ALLE"]));
+                        if spanish_ll {
+                            r.append_primary("L");
+                        } else {
+                            r.append("L");
+                        }
+                        index + 2
+                    } else {
+                        r.append("L");
+                        index + 1
+                    }
+                },
+                'M' => {
+                    r.append("M");
+                    let skip_next = next_is('M') || (w.contains(index - 1, 3, &["UMB"]) && (index + 1 == w.len() - 1 || w.contains(index + 2, 2, &["ER"])));
+                    if skip_next { index + 2 } else { index + 1 }
+                },
+                'N' => {
+                    r.append("N");
+                    if next_is('N') { index + 2 } else { index + 1 }
+                },
+                'Ñ' => {
+                    r.append("N");
+                    index + 1
+                },
+                'P' => {
+                    if next_is('H') {
+                        r.append("F");
+                        index + 2
+                    } else {
+                        r.append("P");
+                        if w.contains(index + 1, 1, &["P", "B"]) { index + 2 } else { index + 1 }
+                    }
+                },
+                'Q' => {
+                    r.append("K");
+                    if next_is('Q') { index + 2 } else { index + 1 }
+                },
+                'R' => {
+                    if index == w.len() - 1 && !w.slavo_germanic && w.contains(index - 2, 2, &["IE"]) && !w.contains(index - 4, 2, &["ME", "MA"]) {
+                        r.append_alternate("R");
+                    } else {
+                        r.append("R");
+                    }
+                    if next_is('R') { index + 2 } else { index + 1 }
+                },
+                'S' => double_metaphone_s(&w, &mut r, index),
+                'T' => double_metaphone_t(&w, &mut r, index),
+                'V' => {
+                    r.append("F");
+                    if next_is('V') { index + 2 } else { index + 1 }
+                },
+                'W' => double_metaphone_w(&w, &mut r, index),
+                'X' => {
+                    if index == 0 {
+                        r.append("S");
+                        index + 1
+                    } else {
+                        let silent = index == w.len() - 1 && (w.contains(index - 3, 3, &["IAU", "EAU"]) || w.contains(index - 2, 2, &["AU", "OU"]));
+                        if !silent {
+                            r.append("KS");
+                        }
+                        if w.contains(index + 1, 1, &["C", "X"]) { index + 2 } else { index + 1 }
+                    }
+                },
+                'Z' => {
+                    if next_is('H') {
+                        r.append("J");
+                        index + 2
+                    } else {
+                        if w.contains(index + 1, 2, &["ZO", "ZI", "ZA"]) || (w.slavo_germanic && index > 0 && w.char_at(index - 1) != 'T') {
+                            r.append_both("S", "TS");
+                        } else {
+                            r.append("S");
+                        }
+                        if next_is('Z') { index + 2 } else { index + 1 }
+                    }
+                },
+                _ => index + 1,
+            };
+        }
+        (r.primary, r.alternate)
+    }
+
+    /// Daitch–Mokotoff coding table: pattern, then the codes at the start of a name, before a vowel, and anywhere
+    /// else. `|` separates alternative codings and an empty code means the letters are not coded.
+    const DAITCH_MOKOTOFF_RULES: &[(&str, &str, &str, &str)] = &[
+        ("AI", "0", "1", ""), ("AJ", "0", "1", ""), ("AY", "0", "1", ""), ("AU", "0", "7", ""), ("A", "0", "", ""),
+        ("B", "7", "7", "7"),
+        ("CHS", "5", "54", "54"), ("CH", "5|4", "5|4", "5|4"), ("CK", "5|45", "5|45", "5|45"),
+        ("CSZ", "4", "4", "4"), ("CZS", "4", "4", "4"), ("CS", "4", "4", "4"), ("CZ", "4", "4", "4"), ("C", "5|4", "5|4", "5|4"),
+        ("DRZ", "4", "4", "4"), ("DRS", "4", "4", "4"), ("DSH", "4", "4", "4"), ("DSZ", "4", "4", "4"), ("DS", "4", "4", "4"),
+        ("DZH", "4", "4", "4"), ("DZS", "4", "4", "4"), ("DZ", "4", "4", "4"), ("DT", "3", "3", "3"), ("D", "3", "3", "3"),
+        ("EI", "0", "1", ""), ("EJ", "0", "1", ""), ("EY", "0", "1", ""), ("EU", "1", "1", ""), ("E", "0", "", ""),
+        ("FB", "7", "7", "7"), ("F", "7", "7", "7"),
+        ("G", "5", "5", "5"),
+        ("H", "5", "5", ""),
+        ("IA", "1", "", ""), ("IE", "1", "", ""), ("IO", "1", "", ""), ("IU", "1", "", ""), ("I", "0", "", ""),
+        ("J", "1|4", "|4", "|4"),
+        ("KS", "5", "54", "54"), ("KH", "5", "5", "5"), ("K", "5", "5", "5"),
+        ("L", "8", "8", "8"),
+        ("MN", "66", "66", "66"), ("M", "6", "6", "6"),
+        ("NM", "66", "66", "66"), ("N", "6", "6", "6"),
+        ("OI", "0", "1", ""), ("OJ", "0", "1", ""), ("OY", "0", "1", ""), ("O", "0", "", ""),
+        ("PF", "7", "7", "7"), ("PH", "7", "7", "7"), ("P", "7", "7", "7"),
+        ("Q", "5", "5", "5"),
+        ("RS", "94|4", "94|4", "94|4"), ("RZ", "94|4", "94|4", "94|4"), ("R", "9", "9", "9"),
+        ("SCHTSCH", "2", "4", "4"), ("SCHTSH", "2", "4", "4"), ("SCHTCH", "2", "4", "4"), ("SHTCH", "2", "4", "4"), ("SHTSH", "2", "4", "4"),
+        ("STSCH", "2", "4", "4"), ("SCHT", "2", "43", "43"), ("SCHD", "2", "43", "43"), ("SHCH", "2", "4", "4"), ("STCH", "2", "4", "4"),
+        ("STRZ", "2", "4", "4"), ("STRS", "2", "4", "4"), ("STSH", "2", "4", "4"), ("SZCZ", "2", "4", "4"), ("SZCS", "2", "4", "4"),
+        ("SCH", "4", "4", "4"), ("SHT", "2", "43", "43"), ("SZT", "2", "43", "43"), ("SHD", "2", "43", "43"), ("SZD", "2", "43", "43"),
+        ("SH", "4", "4", "4"), ("SC", "2", "4", "4"), ("ST", "2", "43", "43"), ("SD", "2", "43", "43"), ("SZ", "4", "4", "4"), ("S", "4", "4", "4"),
+        ("TTSCH", "4", "4", "4"), ("TTCH", "4", "4", "4"), ("TTSZ", "4", "4", "4"), ("TSCH", "4", "4", "4"), ("TCH", "4", "4", "4"),
+        ("TRZ", "4", "4", "4"), ("TRS", "4", "4", "4"), ("TSH", "4", "4", "4"), ("TTS", "4", "4", "4"), ("TTZ", "4", "4", "4"),
+        ("TZS", "4", "4", "4"), ("TSZ", "4", "4", "4"), ("TH", "3", "3", "3"), ("TS", "4", "4", "4"), ("TC", "4", "4", "4"),
+        ("TZ", "4", "4", "4"), ("T", "3", "3", "3"),
+        ("UI", "0", "1", ""), ("UJ", "0", "1", ""), ("UY", "0", "1", ""), ("UE", "0", "", ""), ("U", "0", "", ""),
+        ("V", "7", "7", "7"),
+        ("W", "7", "7", "7"),
+        ("X", "5", "54", "54"),
+        ("Y", "1", "", ""),
+        ("ZHDZH", "2", "4", "4"), ("ZDZH", "2", "4", "4"), ("ZSCH", "4", "4", "4"), ("ZDZ", "2", "4", "4"), ("ZHD", "2", "43", "43"),
+        ("ZSH", "4", "4", "4"), ("ZD", "2", "43", "43"), ("ZH", "4", "4", "4"), ("ZS", "4", "4", "4"), ("Z", "4", "4", "4"),
+    ];
+
+    const DAITCH_MOKOTOFF_LENGTH: usize = 6;
+
+    /// Daitch–Mokotoff Soundex, designed for Slavic and Yiddish surnames. Letter sequences with more than one
+    /// plausible pronunciation ("CH", "CK", "C", "J", "RS", "RZ") branch, so a name can have several six-digit codes,
+    /// e.g. "Peters" -> ["739400", "734000"].
+    pub fn daitch_mokotoff(s: &str) -> Vec<String> {
+        lazy_static! {
+            static ref RULES_BY_LENGTH: Vec<(Vec<char>, [Vec<&'static str>; 3])> = {
+                let mut rules: Vec<(Vec<char>, [Vec<&'static str>; 3])> = DAITCH_MOKOTOFF_RULES.iter()
+                    .map(|(p, start, vowel, other)| (p.chars().collect(), [start.split('|').collect(), vowel.split('|').collect(), other.split('|').collect()]))
+                    .collect();
+                rules.sort_by_key(|r| std::cmp::Reverse(r.0.len()));
+                rules
+            };
+        }
+        let letters = ascii_letters(s);
+        if letters.is_empty() {
+            return Vec::new();
+        }
+        // Each branch is a code so far, plus the last coding applied, for collapsing repeats
+        let mut branches: Vec<(String, &str)> = vec![(String::new(), "")];
+        let mut i = 0;
+        while i < letters.len() {
+            let rule = RULES_BY_LENGTH.iter().find(|(p, _)| letters[i..].starts_with(p));
+            let (pattern, codings) = match rule {
+                Some(r) => r,
+                None => {
+                    i += 1;
+                    continue;
+                },
+            };
+            let after = i + pattern.len();
+            let column = if i == 0 {
+                0
+            } else if after < letters.len() && is_vowel(letters[after]) {
+                1
+            } else {
+                2
+            };
+            let mut next_branches: Vec<(String, &str)> = Vec::new();
+            for (code, last) in &branches {
+                for coding in &codings[column] {
+                    let mut code = code.clone();
+                    if !coding.is_empty() && !last.ends_with(coding) {
+                        code.push_str(coding);
+                    }
+                    if !next_branches.iter().any(|(c, l)| *c == code && l == coding) {
+                        next_branches.push((code, coding));
+                    }
+                }
+            }
+            branches = next_branches;
+            i = after;
+        }
+        let mut ret_val: Vec<String> = Vec::new();
+        for (mut code, _) in branches {
+            code.truncate(DAITCH_MOKOTOFF_LENGTH);
+            while code.len() < DAITCH_MOKOTOFF_LENGTH {
+                code.push('0');
+            }
+            if !ret_val.contains(&code) {
+                ret_val.push(code);
+            }
+        }
+        ret_val
+    }
+
+    /// Grapheme-to-phoneme rules for `beider_morse`, longest patterns first within each starting letter. The
+    /// phoneme alphabet follows BMPM: `S` for "sh", `Z` for "zh", `x` for "kh", `tS` for "ch", `dZ` for "j".
+    /// A rule with a following-letter condition only applies before one of those letters.
+    const BEIDER_MORSE_RULES: &[(&str, &str, &str)] = &[
+        ("tsch", "", "tS"), ("sch", "", "S"), ("szcz", "", "StS"), ("sz", "", "S|s"), ("sh", "", "S"),
+        ("tch", "", "tS"), ("cz", "", "tS"), ("cs", "", "tS|S"), ("ch", "", "x|tS|S|k"), ("ck", "", "k"), ("c", "eiy", "s|ts|tS"), ("c", "", "k|ts"),
+        ("dzh", "", "dZ"), ("dz", "", "dz|dZ"), ("dt", "", "t"),
+        ("ph", "", "f"), ("pf", "", "f"),
+        ("th", "", "t"), ("tz", "", "ts"), ("ts", "", "ts"),
+        ("gh", "", "g"), ("g", "eiy", "g|dZ|Z"), ("kh", "", "x"), ("zh", "", "Z"),
+        ("qu", "", "kv|k"), ("q", "", "k"), ("x", "", "ks"),
+        ("j", "", "j|dZ|Z|x"), ("w", "", "v"), ("y", "", "i"), ("z", "", "z|ts"),
+        ("ou", "", "u"), ("oo", "", "u"), ("ee", "", "i"), ("ie", "", "i"), ("ei", "", "aj|i"), ("ey", "", "aj|i"),
+        ("ai", "", "aj|e"), ("ay", "", "aj|e"), ("au", "", "au|o"), ("eu", "", "oj|e"),
+    ];
+
+    const BEIDER_MORSE_MAX_ALTERNATIVES: usize = 32;
+
+    /// A compact take on Beider–Morse Phonetic Matching in its generic, approximate mode. Letters are rewritten
+    /// into a phonetic alphabet with every plausible reading kept as an alternative (so "Schwarz" and "Szwarc"
+    /// share a code), then vowels are reduced to three classes, `h` is dropped except at the start, and repeats are
+    /// collapsed. The language detection and the language-specific rule sets of full BMPM are not implemented, so
+    /// codes are not interchangeable with those of other BMPM implementations. At most 32 codes are returned.
+    pub fn beider_morse(s: &str) -> Vec<String> {
+        let letters: Vec<char> = ascii_letters(s).into_iter().map(|c| c.to_ascii_lowercase()).collect();
+        if letters.is_empty() {
+            return Vec::new();
+        }
+        let mut phonetic: Vec<String> = vec![String::new()];
+        let mut i = 0;
+        while i < letters.len() {
+            let next_after = |len: usize| letters.get(i + len).copied();
+            let rule = BEIDER_MORSE_RULES.iter().find(|(pattern, followed_by, _)| {
+                letters[i..].iter().copied().take(pattern.len()).eq(pattern.chars())
+                    && (followed_by.is_empty() || next_after(pattern.len()).map(|c| followed_by.contains(c)).unwrap_or(false))
+            });
+            let (consumed, alternatives): (usize, Vec<String>) = match rule {
+                Some((pattern, _, phonemes)) => (pattern.len(), phonemes.split('|').map(|p| p.to_string()).collect()),
+                None => (1, vec![letters[i].to_string()]),
+            };
+            let mut next: Vec<String> = Vec::new();
+            for prefix in &phonetic {
+                for a in &alternatives {
+                    let candidate = format!("{}{}", prefix, a);
+                    if !next.contains(&candidate) && next.len() < BEIDER_MORSE_MAX_ALTERNATIVES {
+                        next.push(candidate);
+                    }
+                }
+            }
+            phonetic = next;
+            i += consumed;
+        }
+        let mut ret_val: Vec<String> = Vec::new();
+        for p in phonetic {
+            let mut approx = String::new();
+            for (n, c) in p.chars().enumerate() {
+                let c = match c {
+                    'e' | 'i' => 'i',
+                    'o' | 'u' => 'u',
+                    'h' if n > 0 => continue,
+                    c => c,
+                };
+                if !approx.ends_with(c) {
+                    approx.push(c);
+                }
+            }
+            if !ret_val.contains(&approx) {
+                ret_val.push(approx);
+            }
+        }
+        ret_val.sort();
+        ret_val
+    }
+
+    pub trait PhoneticEncoder {
+        fn algorithm_name(&self) -> &'static str;
+
+        /// The name plus any settings that change the codes, as `DistanceAlgorithm::algorithm_identity`.
+        fn algorithm_identity(&self) -> String {
+            self.algorithm_name().to_string()
+        }
+
+        /// The codes for `s`. Encoders that model alternative pronunciations may return several; an input that
+        /// cannot be encoded (no usable letters) returns none.
+        fn encode(&self, s: &str) -> Vec<String>;
+    }
+
+    fn non_empty_code(code: String) -> Vec<String> {
+        if code.is_empty() { Vec::new() } else { vec![code] }
+    }
+
+    #[derive(Debug, Default)]
+    pub struct SoundexEncoder {}
+
+    impl SoundexEncoder {
+        pub fn new() -> Self {
+            let ret_val = SoundexEncoder {};
+            ret_val
+        }
+    }
+
+    impl PhoneticEncoder for SoundexEncoder {
+        fn algorithm_name(&self) -> &'static str {
+            return "Soundex";
+        }
+
+        fn encode(&self, s: &str) -> Vec<String> {
+            non_empty_code(soundex(s))
+        }
+    }
+
+    #[derive(Debug, Default)]
+    pub struct RefinedSoundexEncoder {}
+
+    impl RefinedSoundexEncoder {
+        pub fn new() -> Self {
+            let ret_val = RefinedSoundexEncoder {};
+            ret_val
+        }
+    }
+
+    impl PhoneticEncoder for RefinedSoundexEncoder {
+        fn algorithm_name(&self) -> &'static str {
+            return "Refined Soundex";
+        }
+
+        fn encode(&self, s: &str) -> Vec<String> {
+            non_empty_code(refined_soundex(s))
+        }
+    }
+
+    #[derive(Debug, Default)]
+    pub struct NysiisEncoder {}
+
+    impl NysiisEncoder {
+        pub fn new() -> Self {
+            let ret_val = NysiisEncoder {};
+            ret_val
+        }
+    }
+
+    impl PhoneticEncoder for NysiisEncoder {
+        fn algorithm_name(&self) -> &'static str {
+            return "NYSIIS";
+        }
+
+        fn encode(&self, s: &str) -> Vec<String> {
+            non_empty_code(nysiis(s))
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct MetaphoneEncoder {
+        max_length: usize,
+    }
+
+    impl MetaphoneEncoder {
+        pub fn new() -> Self {
+            MetaphoneEncoder::with_max_length(DEFAULT_METAPHONE_LENGTH)
+        }
+
+        pub fn with_max_length(max_length: usize) -> Self {
+            let ret_val = MetaphoneEncoder { max_length: max_length };
+            ret_val
+        }
+    }
+
+    impl Default for MetaphoneEncoder {
+        fn default() -> Self {
+            MetaphoneEncoder::new()
+        }
+    }
+
+    impl PhoneticEncoder for MetaphoneEncoder {
+        fn algorithm_name(&self) -> &'static str {
+            return "Metaphone";
+        }
+
+        fn algorithm_identity(&self) -> String {
+            format!("{} (max length {})", self.algorithm_name(), self.max_length)
+        }
+
+        fn encode(&self, s: &str) -> Vec<String> {
+            non_empty_code(metaphone(s, self.max_length))
+        }
+    }
+
+    /// Yields the primary code, followed by the alternate code when it differs.
+    #[derive(Debug)]
+    pub struct DoubleMetaphoneEncoder {
+        max_length: usize,
+    }
+
+    impl DoubleMetaphoneEncoder {
+        pub fn new() -> Self {
+            DoubleMetaphoneEncoder::with_max_length(DEFAULT_DOUBLE_METAPHONE_LENGTH)
+        }
+
+        pub fn with_max_length(max_length: usize) -> Self {
+            let ret_val = DoubleMetaphoneEncoder { max_length: max_length };
+            ret_val
+        }
+    }
+
+    impl Default for DoubleMetaphoneEncoder {
+        fn default() -> Self {
+            DoubleMetaphoneEncoder::new()
+        }
+    }
+
+    impl PhoneticEncoder for DoubleMetaphoneEncoder {
+        fn algorithm_name(&self) -> &'static str {
+            return "Double Metaphone";
+        }
+
+        fn algorithm_identity(&self) -> String {
+            format!("{} (max length {})", self.algorithm_name(), self.max_length)
+        }
+
+        fn encode(&self, s: &str) -> Vec<String> {
+            let (primary, alternate) = double_metaphone(s, self.max_length);
+            let mut ret_val = non_empty_code(primary);
+            if !alternate.is_empty() && !ret_val.contains(&alternate) {
+                ret_val.push(alternate);
+            }
+            ret_val
+        }
+    }
+
+    #[derive(Debug, Default)]
+    pub struct DaitchMokotoffEncoder {}
+
+    impl DaitchMokotoffEncoder {
+        pub fn new() -> Self {
+            let ret_val = DaitchMokotoffEncoder {};
+            ret_val
+        }
+    }
+
+    impl PhoneticEncoder for DaitchMokotoffEncoder {
+        fn algorithm_name(&self) -> &'static str {
+            return "Daitch-Mokotoff Soundex";
+        }
+
+        fn encode(&self, s: &str) -> Vec<String> {
+            daitch_mokotoff(s)
+        }
+    }
+
+    #[derive(Debug, Default)]
+    pub struct BeiderMorseEncoder {}
+
+    impl BeiderMorseEncoder {
+        pub fn new() -> Self {
+            let ret_val = BeiderMorseEncoder {};
+            ret_val
+        }
+    }
+
+    impl PhoneticEncoder for BeiderMorseEncoder {
+        fn algorithm_name(&self) -> &'static str {
+            return "Beider-Morse";
+        }
+
+        fn encode(&self, s: &str) -> Vec<String> {
+            beider_morse(s)
+        }
+    }
+
+    fn codes_or_empty<Encoder: PhoneticEncoder>(encoder: &Encoder, s: &str) -> Vec<String> {
+        let codes = encoder.encode(s);
+        if codes.is_empty() { vec![String::new()] } else { codes }
+    }
+
+    /// Compares the phonetic codes of two strings rather than the strings themselves. When an encoder yields
+    /// several codes, the closest pair of codes counts.
+    ///
+    /// With an encoder that yields one code per string (Soundex, Refined Soundex, NYSIIS, Metaphone) this is the
+    /// inner calculator's distance between codes: distinct strings can be at distance 0, but the triangle
+    /// inequality holds, so `BKTree` and `VPTree` can index it. With an encoder that may yield several codes
+    /// (Double Metaphone, Daitch-Mokotoff, Beider-Morse) the minimum over pairs is NOT a metric: `a` can be close
+    /// to `b` through one of `b`'s codes and `b` close to `c` through another while `a` and `c` are far apart.
+    /// The metric indexes would then prune away real matches, so use those encoders only for pairwise comparison
+    /// or a linear scan.
+    #[derive(Debug)]
+    pub struct PhoneticEditDistanceCalculator<Encoder: PhoneticEncoder, InnerCalc: EditDistanceCalculator<str>> {
+        encoder: Arc<Encoder>,
+        inner_calculator: Arc<InnerCalc>,
+    }
+
+    impl<Encoder: PhoneticEncoder, InnerCalc: EditDistanceCalculator<str>> PhoneticEditDistanceCalculator<Encoder, InnerCalc> {
+        pub fn new(encoder: Arc<Encoder>, inner_calculator: Arc<InnerCalc>) -> Self {
+            let ret_val = PhoneticEditDistanceCalculator { encoder: encoder, inner_calculator: inner_calculator };
+            ret_val
+        }
+    }
+
+    impl<Encoder: PhoneticEncoder, InnerCalc: EditDistanceCalculator<str>> DistanceAlgorithm for PhoneticEditDistanceCalculator<Encoder, InnerCalc> {
+        fn algorithm_name(&self) -> &'static str {
+            return "Phonetic Code Edit Distance";
+        }
+
+        fn algorithm_identity(&self) -> String {
+            format!("{} ({} of {} codes)", self.algorithm_name(), self.inner_calculator.algorithm_identity(), self.encoder.algorithm_identity())
+        }
+    }
+
+    impl<Encoder: PhoneticEncoder, InnerCalc: EditDistanceCalculator<str>> EditDistanceCalculator<str> for PhoneticEditDistanceCalculator<Encoder, InnerCalc> {
+        fn get_edit_distance(&self, item1: &str, item2: &str) -> EditDistance {
+            let codes1 = codes_or_empty(&*self.encoder, item1);
+            let codes2 = codes_or_empty(&*self.encoder, item2);
+            codes1.iter().flat_map(|c1| codes2.iter().map(move |c2| (c1, c2)))
+                .map(|(c1, c2)| self.inner_calculator.get_edit_distance(c1, c2))
+                .min()
+                .unwrap_or(0)
+        }
+
+        fn get_bounded_edit_distance(&self, item1: &str, item2: &str, max_distance: EditDistance) -> Option<EditDistance> {
+            let codes1 = codes_or_empty(&*self.encoder, item1);
+            let codes2 = codes_or_empty(&*self.encoder, item2);
+            codes1.iter().flat_map(|c1| codes2.iter().map(move |c2| (c1, c2)))
+                .filter_map(|(c1, c2)| self.inner_calculator.get_bounded_edit_distance(c1, c2, max_distance))
+                .min()
+        }
+    }
+
+    /// Similarity of the phonetic codes of two strings; the most similar pair of codes counts.
+    #[derive(Debug)]
+    pub struct PhoneticSimilarityCalculator<Encoder: PhoneticEncoder, InnerCalc: SimilarityCalculator> {
+        encoder: Arc<Encoder>,
+        inner_calculator: Arc<InnerCalc>,
+    }
+
+    impl<Encoder: PhoneticEncoder, InnerCalc: SimilarityCalculator> PhoneticSimilarityCalculator<Encoder, InnerCalc> {
+        pub fn new(encoder: Arc<Encoder>, inner_calculator: Arc<InnerCalc>) -> Self {
+            let ret_val = PhoneticSimilarityCalculator { encoder: encoder, inner_calculator: inner_calculator };
+            ret_val
+        }
+    }
+
+    impl<Encoder: PhoneticEncoder, InnerCalc: SimilarityCalculator> SimilarityCalculator for PhoneticSimilarityCalculator<Encoder, InnerCalc> {
+        fn algorithm_name(&self) -> &'static str {
+            return "Phonetic Code Similarity";
+        }
+
+        fn get_similarity(&self, item1: &str, item2: &str) -> Similarity {
+            let codes1 = codes_or_empty(&*self.encoder, item1);
+            let codes2 = codes_or_empty(&*self.encoder, item2);
+            codes1.iter().flat_map(|c1| codes2.iter().map(move |c2| (c1, c2)))
+                .map(|(c1, c2)| self.inner_calculator.get_similarity(c1, c2))
+                .fold(0.0, Similarity::max)
+        }
+    }
+}
+
+pub mod name_variants {
+    use std::collections::{HashMap, HashSet};
+    use std::fmt;
+    use std::fs::File;
+    use std::io::{BufRead, BufReader};
+    use std::path::Path;
+    use std::sync::Arc;
+    use super::fuzzy_matching::*;
+
+    /// Built-in given-name variant groups: diminutives, nicknames and cross-language equivalents. A name may appear
+    /// in more than one group ("Pat" is both Patrick and Patricia); two names are variants if any group holds both.
+    /// Accented and unaccented spellings are listed separately so they match without a normalization step.
+    pub const GIVEN_NAME_VARIANTS: &[&[&str]] = &[
+        &["Abigail", "Abby", "Abbie", "Gail"],
+        &["Albert", "Al", "Bert", "Bertie", "Alberto"],
+        &["Alexander", "Alex", "Al", "Alec", "Sandy", "Xander", "Alejandro", "Alessandro", "Aleksandr", "Alexandre", "Sasha"],
+        &["Alexandra", "Alex", "Alexa", "Lexi", "Sandra", "Sandy", "Alejandra", "Alessandra", "Sasha"],
+        &["Alfred", "Al", "Alf", "Alfie", "Fred", "Alfredo"],
+        &["Andrew", "Andy", "Drew", "Andrés", "Andres", "André", "Andre", "Andreas", "Andrea", "Andrei", "Andrzej"],
+        &["Ann", "Anne", "Anna", "Annie", "Nancy", "Nan", "Hannah", "Ana", "Anya"],
+        &["Anthony", "Tony", "Antonio", "Antoine", "Anton", "Antonius"],
+        &["Barbara", "Barb", "Barbie", "Babs"],
+        &["Bartholomew", "Bart", "Bartolomeo", "Bartolomé", "Bartolome"],
+        &["Benjamin", "Ben", "Benny", "Benji", "Benjamín"],
+        &["Catherine", "Katherine", "Kathryn", "Katharine", "Cathy", "Kathy", "Kate", "Katie", "Kitty", "Kay", "Catalina", "Caterina", "Katharina", "Katarzyna", "Kasia", "Ekaterina", "Katya"],
+        &["Charles", "Charlie", "Chuck", "Chas", "Chaz", "Carlos", "Carlo", "Carl", "Karl"],
+        &["Christine", "Christina", "Chris", "Chrissy", "Tina", "Kristina", "Cristina", "Kristin", "Kirsten"],
+        &["Christopher", "Chris", "Kit", "Topher", "Cristóbal", "Cristobal", "Christoph", "Krzysztof"],
+        &["Cynthia", "Cindy"],
+        &["Daniel", "Dan", "Danny", "Dani"],
+        &["David", "Dave", "Davey", "Davy", "Dawid"],
+        &["Deborah", "Debra", "Deb", "Debbie", "Debby"],
+        &["Donald", "Don", "Donnie", "Donny"],
+        &["Dorothy", "Dot", "Dottie", "Dolly", "Dorothea", "Dora"],
+        &["Edward", "Ed", "Eddie", "Eddy", "Ted", "Teddy", "Ned", "Eduardo", "Édouard", "Edouard", "Eduard"],
+        &["Eleanor", "Elinor", "Ellie", "Nell", "Nellie", "Nora", "Leonora", "Leonor"],
+        &["Elizabeth", "Elisabeth", "Liz", "Lizzie", "Beth", "Betty", "Betsy", "Bess", "Bessie", "Eliza", "Libby", "Lisa", "Liza", "Elsa", "Isabel", "Isabella", "Isabelle", "Elisabetta", "Elżbieta", "Elzbieta"],
+        &["Eugene", "Gene", "Eugenio", "Eugène", "Eugen"],
+        &["Francis", "Frank", "Frankie", "Fran", "Francisco", "Paco", "Pancho", "Francesco", "François", "Francois", "Franz", "Franciszek"],
+        &["Frederick", "Fred", "Freddie", "Freddy", "Fritz", "Federico", "Friedrich"],
+        &["Gerald", "Gerry", "Jerry"],
+        &["Gregory", "Greg", "Gregorio", "Grzegorz"],
+        &["Harold", "Hal", "Harry"],
+        &["Helen", "Helena", "Ellen", "Elena", "Hélène", "Helene", "Nell"],
+        &["Henry", "Hank", "Harry", "Hal", "Enrique", "Henri", "Heinrich", "Enrico", "Henryk"],
+        &["Jacob", "Jake", "Jakob", "Jacobo", "Jakub", "Yaakov"],
+        &["James", "Jim", "Jimmy", "Jamie", "Diego", "Jaime", "Santiago", "Jacques", "Giacomo"],
+        &["Jennifer", "Jen", "Jenny", "Jenn"],
+        &["Jessica", "Jess", "Jessie"],
+        &["John", "Jack", "Johnny", "Jon", "Juan", "Johann", "Johannes", "Hans", "Jan", "Jean", "Giovanni", "Gianni", "Ivan", "Sean", "Seán", "Ian", "Evan", "Ioannis"],
+        &["Jonathan", "Jon", "Jonny", "Johnathan"],
+        &["Joseph", "Joe", "Joey", "Jose", "José", "Josef", "Józef", "Jozef", "Giuseppe", "Pepe", "Beppe"],
+        &["Josephine", "Jo", "Josie", "Josefina", "Josefa", "Joséphine"],
+        &["Joshua", "Josh"],
+        &["Judith", "Judy", "Jude"],
+        &["Kenneth", "Ken", "Kenny"],
+        &["Lawrence", "Laurence", "Larry", "Laurie", "Lorenzo", "Laurent"],
+        &["Leonard", "Len", "Lenny", "Leo", "Leonardo", "Léonard"],
+        &["Louis", "Lou", "Louie", "Luis", "Luís", "Luigi", "Ludwig", "Ludovico"],
+        &["Louise", "Louisa", "Lou", "Lulu", "Luisa"],
+        &["Margaret", "Peggy", "Maggie", "Meg", "Megan", "Marge", "Margie", "Madge", "Greta", "Gretchen", "Rita", "Daisy", "Margarete", "Margarita", "Margherita", "Marguerite", "Małgorzata", "Malgorzata"],
+        &["Marjorie", "Margery", "Marge", "Margie"],
+        &["Martha", "Marty", "Mattie", "Patsy", "Marta"],
+        &["Martin", "Marty", "Martín", "Martino"],
+        &["Mary", "Molly", "Polly", "Mae", "May", "Mamie", "Maria", "María", "Marie", "Mariya", "Maryam", "Miriam"],
+        &["Matthew", "Matt", "Matty", "Mateo", "Matteo", "Mathieu", "Matthias", "Mateusz"],
+        &["Michael", "Mike", "Mikey", "Mick", "Mickey", "Mitch", "Miguel", "Michel", "Michele", "Michał", "Michal", "Mikhail", "Mihail"],
+        &["Nicholas", "Nicolas", "Nicolás", "Nick", "Nicky", "Nico", "Nikolai", "Nikolaus", "Nicola", "Niccolò", "Klaus", "Mikołaj", "Mikolaj"],
+        &["Patricia", "Pat", "Patty", "Patsy", "Trish", "Tricia", "Trisha"],
+        &["Patrick", "Pat", "Paddy", "Patricio", "Patrice"],
+        &["Paul", "Pablo", "Paolo", "Pavel", "Paweł", "Pawel"],
+        &["Peter", "Pete", "Pedro", "Pierre", "Pietro", "Piotr", "Pyotr", "Petr"],
+        &["Raymond", "Ray", "Ramón", "Ramon", "Raimondo"],
+        &["Rebecca", "Becky", "Becca", "Rebekah"],
+        &["Richard", "Dick", "Rick", "Ricky", "Rich", "Richie", "Ricardo", "Riccardo", "Ryszard"],
+        &["Robert", "Bob", "Bobby", "Rob", "Robbie", "Bert", "Roberto", "Robin"],
+        &["Ronald", "Ron", "Ronnie"],
+        &["Samantha", "Sam", "Sammy"],
+        &["Samuel", "Sam", "Sammy", "Samuele"],
+        &["Sarah", "Sara", "Sally", "Sadie"],
+        &["Stephen", "Steven", "Steve", "Stevie", "Esteban", "Stefan", "Stefano", "Étienne", "Etienne", "Stephan", "Stepan", "Szczepan"],
+        &["Susan", "Sue", "Susie", "Suzy", "Suzanne", "Susanna", "Susana"],
+        &["Teresa", "Theresa", "Terri", "Terry", "Tess", "Tessa", "Tracy", "Thérèse", "Therese"],
+        &["Theodore", "Ted", "Teddy", "Theo", "Teodoro"],
+        &["Thomas", "Tom", "Tommy", "Thom", "Tomás", "Tomas", "Tomasz", "Tommaso"],
+        &["Timothy", "Tim", "Timmy"],
+        &["Victoria", "Vicky", "Vickie", "Tori", "Vittoria"],
+        &["Vincent", "Vince", "Vinny", "Vicente", "Vincenzo", "Wincenty"],
+        &["Virginia", "Ginny", "Ginger"],
+        &["Walter", "Walt", "Wally"],
+        &["William", "Bill", "Billy", "Will", "Willy", "Willie", "Liam", "Wilhelm", "Guillaume", "Guillermo", "Guglielmo", "Wiliam"],
+        &["Zachary", "Zach", "Zack", "Zak"],
+    ];
+
+    #[derive(Debug)]
+    pub enum NameVariantErr {
+        IoError(std::io::Error),
+        /// 1-based line number of an alias line that does not name at least two variants.
+        MalformedLine(usize),
+    }
+    pub type NameVariantResult<T> = Result<T, NameVariantErr>;
+
+    impl From<std::io::Error> for NameVariantErr {
+        fn from(e: std::io::Error) -> Self {
+            NameVariantErr::IoError(e)
+        }
+    }
+
+    impl fmt::Display for NameVariantErr {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                NameVariantErr::IoError(e)          => write!(f, "I/O error: {}", e),
+                NameVariantErr::MalformedLine(line) => write!(f, "alias line {} must list at least two comma-separated names", line),
+            }
+        }
+    }
+
+    /// Lookup key for a name: trimmed and lowercased.
+    fn variant_key(name: &str) -> String {
+        name.trim().to_lowercase()
+    }
+
+    /// Groups of given names that should be treated as the same name when matching.
+    #[derive(Debug, Clone)]
+    pub struct NameVariantDictionary {
+        groups: Vec<Vec<Arc<String>>>,
+        group_ids_by_key: HashMap<String, Vec<usize>>,
+    }
+
+    impl NameVariantDictionary {
+        /// An empty dictionary, for callers that only want their own alias files.
+        pub fn new() -> Self {
+            let ret_val = NameVariantDictionary { groups: Vec::new(), group_ids_by_key: HashMap::new() };
+            ret_val
+        }
+
+        /// A dictionary pre-loaded with `GIVEN_NAME_VARIANTS`.
+        pub fn with_builtin_variants() -> Self {
+            let mut ret_val = NameVariantDictionary::new();
+            for group in GIVEN_NAME_VARIANTS {
+                ret_val.add_variant_group(group);
+            }
+            ret_val
+        }
+
+        pub fn get_group_count(&self) -> usize {
+            self.groups.len()
+        }
+
+        /// Adds one group of mutually equivalent names. Blank entries are ignored.
+        pub fn add_variant_group<S: AsRef<str>>(&mut self, names: &[S]) {
+            let group_id = self.groups.len();
+            let mut group = Vec::new();
+            let mut keys = HashSet::new();
+            for name in names {
+                let name = name.as_ref().trim();
+                if name.is_empty() || !keys.insert(variant_key(name)) {
+                    continue;
+                }
+                group.push(Arc::new(name.to_string()));
+                self.group_ids_by_key.entry(variant_key(name)).or_default().push(group_id);
+            }
+            self.groups.push(group);
+        }
+
+        /// Loads an organisation-specific alias list: one group per line, names separated by commas. Blank lines and
+        /// lines starting with `#` are skipped. Returns the number of groups added.
+        pub fn load_aliases<R: BufRead>(&mut self, reader: R) -> NameVariantResult<usize> {
+            let mut groups = Vec::new();
+            for (i, line) in reader.lines().enumerate() {
+                let line = line?;
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let names: Vec<&str> = line.split(',').map(str::trim).filter(|n| !n.is_empty()).collect();
+                if names.len() < 2 {
+                    return Err(NameVariantErr::MalformedLine(i + 1));
+                }
+                groups.push(names.iter().map(|n| n.to_string()).collect::<Vec<String>>());
+            }
+            // Only add anything once the whole file has been validated
+            for group in &groups {
+                self.add_variant_group(group);
+            }
+            Ok(groups.len())
+        }
+
+        pub fn load_alias_file<P: AsRef<Path>>(&mut self, path: P) -> NameVariantResult<usize> {
+            let file = File::open(path)?;
+            self.load_aliases(BufReader::new(file))
+        }
+
+        /// True if the names are equal ignoring case, or some group lists both.
+        pub fn are_variants(&self, name1: &str, name2: &str) -> bool {
+            let key1 = variant_key(name1);
+            let key2 = variant_key(name2);
+            if key1 == key2 {
+                return !key1.is_empty();
+            }
+            match (self.group_ids_by_key.get(&key1), self.group_ids_by_key.get(&key2)) {
+                (Some(ids1), Some(ids2)) => ids1.iter().any(|id| ids2.contains(id)),
+                _                        => false,
+            }
+        }
+
+        /// Every name sharing a group with `name`, excluding `name` itself.
+        pub fn get_variants(&self, name: &str) -> Vec<Arc<String>> {
+            let key = variant_key(name);
+            let mut seen = HashSet::new();
+            seen.insert(key.clone());
+            let mut ret_val = Vec::new();
+            if let Some(group_ids) = self.group_ids_by_key.get(&key) {
+                for &group_id in group_ids {
+                    for variant in &self.groups[group_id] {
+                        if seen.insert(variant_key(variant)) {
+                            ret_val.push(variant.clone());
+                        }
+                    }
+                }
+            }
+            ret_val
+        }
+
+        /// `name` followed by its variants: the queries to run against an index built with a plain metric
+        /// calculator when variants should match, in place of `NameVariantEditDistanceCalculator`.
+        pub fn expand_query(&self, name: &str) -> Vec<String> {
+            let mut ret_val = vec![name.to_string()];
+            ret_val.extend(self.get_variants(name).iter().map(|variant| variant.to_string()));
+            ret_val
+        }
+    }
+
+    impl Default for NameVariantDictionary {
+        fn default() -> Self {
+            NameVariantDictionary::with_builtin_variants()
+        }
+    }
+
+    /// Treats known variants of a given name as an exact match (similarity 1.0) and falls back to the inner
+    /// calculator otherwise, so "Bill"/"William" match while "Bill"/"Bull" are still scored as typos.
+    #[derive(Debug)]
+    pub struct NameVariantSimilarityCalculator<InnerCalc: SimilarityCalculator> {
+        dictionary: Arc<NameVariantDictionary>,
+        inner_calculator: Arc<InnerCalc>,
+    }
+
+    impl<InnerCalc: SimilarityCalculator> NameVariantSimilarityCalculator<InnerCalc> {
+        pub fn new(dictionary: Arc<NameVariantDictionary>, inner_calculator: Arc<InnerCalc>) -> Self {
+            let ret_val = NameVariantSimilarityCalculator { dictionary: dictionary, inner_calculator: inner_calculator };
+            ret_val
+        }
+
+        pub fn get_dictionary(&self) -> Arc<NameVariantDictionary> {
+            self.dictionary.clone()
+        }
+    }
+
+    impl<InnerCalc: SimilarityCalculator> SimilarityCalculator for NameVariantSimilarityCalculator<InnerCalc> {
+        fn algorithm_name(&self) -> &'static str {
+            return "Name Variant Similarity";
+        }
+
+        fn get_similarity(&self, item1: &str, item2: &str) -> Similarity {
+            if self.dictionary.are_variants(item1, item2) {
+                1.0
+            } else {
+                self.inner_calculator.get_similarity(item1, item2)
+            }
+        }
+    }
+
+    /// Edit-distance counterpart of `NameVariantSimilarityCalculator`: known variants are distance 0.
+    ///
+    /// This is NOT a metric and must not be used as the calculator of a `BKTree` or `VPTree`. "Bill" and
+    /// "William" are at distance 0 but "Bil" is 1 from "Bill" and 6 from "William", so the triangle inequality
+    /// the indexes prune with fails and they silently miss matches. Use it for pairwise comparison or a linear
+    /// scan only; to search a metric index for variants, index with the inner calculator and search for each
+    /// of `NameVariantDictionary::expand_query`.
+    #[derive(Debug)]
+    pub struct NameVariantEditDistanceCalculator<InnerCalc: EditDistanceCalculator<str>> {
+        dictionary: Arc<NameVariantDictionary>,
+        inner_calculator: Arc<InnerCalc>,
+    }
+
+    impl<InnerCalc: EditDistanceCalculator<str>> NameVariantEditDistanceCalculator<InnerCalc> {
+        pub fn new(dictionary: Arc<NameVariantDictionary>, inner_calculator: Arc<InnerCalc>) -> Self {
+            let ret_val = NameVariantEditDistanceCalculator { dictionary: dictionary, inner_calculator: inner_calculator };
+            ret_val
+        }
+
+        pub fn get_dictionary(&self) -> Arc<NameVariantDictionary> {
+            self.dictionary.clone()
+        }
+    }
+
+    impl<InnerCalc: EditDistanceCalculator<str>> DistanceAlgorithm for NameVariantEditDistanceCalculator<InnerCalc> {
+        fn algorithm_name(&self) -> &'static str {
+            return "Name Variant Edit Distance";
+        }
+
+        /// Only the number of variant groups is included, so two dictionaries of the same size are not told apart.
+        fn algorithm_identity(&self) -> String {
+            format!("{} ({} with {} variant groups)", self.algorithm_name(), self.inner_calculator.algorithm_identity(), self.dictionary.get_group_count())
+        }
+    }
+
+    impl<InnerCalc: EditDistanceCalculator<str>> EditDistanceCalculator<str> for NameVariantEditDistanceCalculator<InnerCalc> {
+        fn get_edit_distance(&self, item1: &str, item2: &str) -> EditDistance {
+            if self.dictionary.are_variants(item1, item2) {
+                0
+            } else {
+                self.inner_calculator.get_edit_distance(item1, item2)
+            }
+        }
+
+        fn get_bounded_edit_distance(&self, item1: &str, item2: &str, max_distance: EditDistance) -> Option<EditDistance> {
+            if self.dictionary.are_variants(item1, item2) {
+                Some(0)
+            } else {
+                self.inner_calculator.get_bounded_edit_distance(item1, item2, max_distance)
+            }
+        }
+    }
+}
+
+pub mod normalization {
+    use std::sync::Arc;
+    use unicode_normalization::char::{decompose_canonical, is_combining_mark};
+    use unicode_normalization::UnicodeNormalization;
+    use super::fuzzy_matching::*;
+
+    /// One transformation in a `NormalizationPipeline`. Steps run in the order they were added, so
+    /// transliterations that look at diacritics (such as `GermanUmlauts`) must come before `StripDiacritics`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum NormalizationStep {
+        Nfc,
+        Nfd,
+        Nfkc,
+        Nfkd,
+        /// Full case folding: lowercase, plus the multi-character folds such as "ß" to "ss".
+        CaseFold,
+        /// Removes combining marks, and maps letters with no decomposition ("ø", "ł", "æ") to plain Latin.
+        StripDiacritics,
+        /// "ä" to "ae", "ö" to "oe", "ü" to "ue" and "ß" to "ss", as German names are commonly re-spelled.
+        GermanUmlauts,
+        CyrillicToLatin,
+        GreekToLatin,
+        /// Trims, and replaces every run of whitespace with a single space.
+        CollapseWhitespace,
+    }
+
+    pub fn case_fold(s: &str) -> String {
+        let mut ret_val = String::with_capacity(s.len());
+        for c in s.chars() {
+            match c {
+                'ß' | 'ẞ' => ret_val.push_str("ss"),
+                'ς'       => ret_val.push('σ'),
+                'ſ'       => ret_val.push('s'),
+                _         => ret_val.extend(c.to_lowercase()),
+            }
+        }
+        ret_val
+    }
+
+    /// Latin letters that carry a diacritic or ligature but have no canonical decomposition.
+    fn undecomposable_latin(c: char) -> Option<&'static str> {
+        let replacement = match c {
+            'Ø' => "O",  'ø' => "o",
+            'Ł' => "L",  'ł' => "l",
+            'Đ' => "D",  'đ' => "d",
+            'Ð' => "D",  'ð' => "d",
+            'Ħ' => "H",  'ħ' => "h",
+            'ı' => "i",
+            'Æ' => "AE", 'æ' => "ae",
+            'Œ' => "OE", 'œ' => "oe",
+            'Þ' => "TH", 'þ' => "th",
+            'ß' => "ss", 'ẞ' => "SS",
+            _   => return None,
+        };
+        Some(replacement)
+    }
+
+    pub fn strip_diacritics(s: &str) -> String {
+        let mut ret_val = String::with_capacity(s.len());
+        for c in s.nfd() {
+            if is_combining_mark(c) {
+                continue;
+            }
+            match undecomposable_latin(c) {
+                Some(replacement) => ret_val.push_str(replacement),
+                None              => ret_val.push(c),
+            }
+        }
+        ret_val.nfc().collect()
+    }
+
+    pub fn expand_german_umlauts(s: &str) -> String {
+        let mut ret_val = String::with_capacity(s.len());
+        let chars: Vec<char> = s.nfc().collect();
+        for (i, &c) in chars.iter().enumerate() {
+            // A capital umlaut in an all-caps word expands to all caps: "MÜLLER" to "MUELLER", "Müller" to "Mueller"
+            let next_is_upper = chars.get(i + 1).map(|n| n.is_uppercase()).unwrap_or(false);
+            match c {
+                'ä' => ret_val.push_str("ae"),
+                'ö' => ret_val.push_str("oe"),
+                'ü' => ret_val.push_str("ue"),
+                'ß' => ret_val.push_str("ss"),
+                'Ä' => ret_val.push_str(if next_is_upper { "AE" } else { "Ae" }),
+                'Ö' => ret_val.push_str(if next_is_upper { "OE" } else { "Oe" }),
+                'Ü' => ret_val.push_str(if next_is_upper { "UE" } else { "Ue" }),
+                'ẞ' => ret_val.push_str("SS"),
+                _   => ret_val.push(c),
+            }
+        }
+        ret_val
+    }
+
+    fn cyrillic_lowercase_to_latin(c: char) -> Option<&'static str> {
+        let latin = match c {
+            'а' => "a",  'б' => "b",  'в' => "v",    'г' => "g",  'ґ' => "g",  'д' => "d",
+            'е' => "e",  'ё' => "e",  'є' => "ye",   'ж' => "zh", 'з' => "z",  'и' => "i",
+            'і' => "i",  'ї' => "yi", 'й' => "y",    'к' => "k",  'л' => "l",  'м' => "m",
+            'н' => "n",  'о' => "o",  'п' => "p",    'р' => "r",  'с' => "s",  'т' => "t",
+            'у' => "u",  'ў' => "u",  'ф' => "f",    'х' => "kh", 'ц' => "ts", 'ч' => "ch",
+            'ш' => "sh", 'щ' => "shch", 'ъ' => "",   'ы' => "y",  'ь' => "",   'э' => "e",
+            'ю' => "yu", 'я' => "ya",
+            _   => return None,
+        };
+        Some(latin)
+    }
+
+    fn greek_lowercase_to_latin(c: char) -> Option<&'static str> {
+        let latin = match c {
+            'α' => "a",  'β' => "v", 'γ' => "g",  'δ' => "d",  'ε' => "e",  'ζ' => "z",
+            'η' => "i",  'θ' => "th", 'ι' => "i", 'κ' => "k",  'λ' => "l",  'μ' => "m",
+            'ν' => "n",  'ξ' => "x", 'ο' => "o",  'π' => "p",  'ρ' => "r",  'σ' => "s",
+            'ς' => "s",  'τ' => "t", 'υ' => "y",  'φ' => "f",  'χ' => "ch", 'ψ' => "ps",
+            'ω' => "o",
+            _   => return None,
+        };
+        Some(latin)
+    }
+
+    /// Transliterates one script with a per-letter table keyed on lowercase letters. Uppercase letters keep their
+    /// case: the first output letter is capitalized, and the rest too when the next letter is also uppercase.
+    fn transliterate(s: &str, base_letter: fn(char) -> char, table: fn(char) -> Option<&'static str>) -> String {
+        let mut ret_val = String::with_capacity(s.len());
+        let chars: Vec<char> = s.nfc().collect();
+        for (i, &c) in chars.iter().enumerate() {
+            let base = base_letter(c);
+            let lower = base.to_lowercase().next().unwrap_or(base);
+            let latin = match table(lower) {
+                Some(latin) => latin,
+                None        => {
+                    ret_val.push(c);
+                    continue;
+                },
+            };
+            if base == lower {
+                ret_val.push_str(latin);
+            } else if chars.get(i + 1).map(|n| n.is_uppercase()).unwrap_or(false) {
+                ret_val.push_str(&latin.to_uppercase());
+            } else {
+                let mut latin_chars = latin.chars();
+                if let Some(first) = latin_chars.next() {
+                    ret_val.extend(first.to_uppercase());
+                    ret_val.extend(latin_chars);
+                }
+            }
+        }
+        ret_val
+    }
+
+    pub fn cyrillic_to_latin(s: &str) -> String {
+        // Cyrillic letters such as "й" and "ё" are distinct letters, so they are looked up without decomposing
+        transliterate(s, |c| c, cyrillic_lowercase_to_latin)
+    }
+
+    pub fn greek_to_latin(s: &str) -> String {
+        // Greek accents (tonos, dialytika) do not change the transliteration, so look up the undecorated letter
+        fn base_letter(c: char) -> char {
+            let mut base = c;
+            decompose_canonical(c, |d| if !is_combining_mark(d) { base = d; });
+            base
+        }
+        transliterate(s, base_letter, greek_lowercase_to_latin)
+    }
+
+    pub fn collapse_whitespace(s: &str) -> String {
+        s.split_whitespace().collect::<Vec<&str>>().join(" ")
+    }
+
+    /// Uppercase letters and digits only, with a ZIP+4 cut back to its 5-digit ZIP.
+    pub fn normalize_postal_code(postal_code: &str) -> String {
+        let ret_val: String = postal_code.chars().filter(|c| c.is_alphanumeric()).flat_map(|c| c.to_uppercase()).collect();
+        if ret_val.len() == 9 && ret_val.chars().all(|c| c.is_ascii_digit()) {
+            ret_val[..5].to_string()
+        } else {
+            ret_val
+        }
+    }
+
+    /// An ordered list of normalization steps applied to a value before it is compared or indexed.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct NormalizationPipeline {
+        steps: Vec<NormalizationStep>,
+    }
+
+    impl NormalizationPipeline {
+        /// A pipeline with no steps; `normalize` returns its input unchanged.
+        pub fn new() -> Self {
+            let ret_val = NormalizationPipeline { steps: Vec::new() };
+            ret_val
+        }
+
+        /// NFC, case folding, diacritic stripping and whitespace collapsing: suitable for most Latin-script names.
+        pub fn for_matching() -> Self {
+            NormalizationPipeline::new()
+                .with_step(NormalizationStep::Nfc)
+                .with_step(NormalizationStep::CaseFold)
+                .with_step(NormalizationStep::StripDiacritics)
+                .with_step(NormalizationStep::CollapseWhitespace)
+        }
+
+        /// `for_matching` with German umlaut expansion first, so "Müller" and "Mueller" normalize alike.
+        pub fn for_german_matching() -> Self {
+            NormalizationPipeline::new()
+                .with_step(NormalizationStep::Nfc)
+                .with_step(NormalizationStep::GermanUmlauts)
+                .with_step(NormalizationStep::CaseFold)
+                .with_step(NormalizationStep::StripDiacritics)
+                .with_step(NormalizationStep::CollapseWhitespace)
+        }
+
+        /// `for_matching` with Cyrillic and Greek transliterated to Latin first.
+        pub fn for_transliterated_matching() -> Self {
+            NormalizationPipeline::new()
+                .with_step(NormalizationStep::Nfc)
+                .with_step(NormalizationStep::CyrillicToLatin)
+                .with_step(NormalizationStep::GreekToLatin)
+                .with_step(NormalizationStep::CaseFold)
+                .with_step(NormalizationStep::StripDiacritics)
+                .with_step(NormalizationStep::CollapseWhitespace)
+        }
+
+        pub fn with_step(mut self, step: NormalizationStep) -> Self {
+            self.steps.push(step);
+            self
+        }
+
+        pub fn add_step(&mut self, step: NormalizationStep) {
+            self.steps.push(step);
+        }
+
+        pub fn get_steps(&self) -> &[NormalizationStep] {
+            &self.steps
+        }
+
+        pub fn normalize(&self, s: &str) -> String {
+            let mut ret_val = s.to_string();
+            for step in &self.steps {
+                ret_val = match step {
+                    NormalizationStep::Nfc                => ret_val.nfc().collect(),
+                    NormalizationStep::Nfd                => ret_val.nfd().collect(),
+                    NormalizationStep::Nfkc               => ret_val.nfkc().collect(),
+                    NormalizationStep::Nfkd               => ret_val.nfkd().collect(),
+                    NormalizationStep::CaseFold           => case_fold(&ret_val),
+                    NormalizationStep::StripDiacritics    => strip_diacritics(&ret_val),
+                    NormalizationStep::GermanUmlauts      => expand_german_umlauts(&ret_val),
+                    NormalizationStep::CyrillicToLatin    => cyrillic_to_latin(&ret_val),
+                    NormalizationStep::GreekToLatin       => greek_to_latin(&ret_val),
+                    NormalizationStep::CollapseWhitespace => collapse_whitespace(&ret_val),
+                };
+            }
+            ret_val
+        }
+    }
+
+    impl Default for NormalizationPipeline {
+        fn default() -> Self {
+            NormalizationPipeline::for_matching()
+        }
+    }
+
+    /// Normalizes both values with a pipeline before handing them to the inner calculator.
+    #[derive(Debug)]
+    pub struct NormalizingEditDistanceCalculator<InnerCalc: EditDistanceCalculator<str>> {
+        pipeline: Arc<NormalizationPipeline>,
+        inner_calculator: Arc<InnerCalc>,
+    }
+
+    impl<InnerCalc: EditDistanceCalculator<str>> NormalizingEditDistanceCalculator<InnerCalc> {
+        pub fn new(pipeline: Arc<NormalizationPipeline>, inner_calculator: Arc<InnerCalc>) -> Self {
+            let ret_val = NormalizingEditDistanceCalculator { pipeline: pipeline, inner_calculator: inner_calculator };
+            ret_val
+        }
+
+        pub fn get_pipeline(&self) -> Arc<NormalizationPipeline> {
+            self.pipeline.clone()
+        }
+    }
+
+    impl<InnerCalc: EditDistanceCalculator<str>> DistanceAlgorithm for NormalizingEditDistanceCalculator<InnerCalc> {
+        fn algorithm_name(&self) -> &'static str {
+            return "Normalized Edit Distance";
+        }
+
+        fn algorithm_identity(&self) -> String {
+            format!("{} ({} after {:?})", self.algorithm_name(), self.inner_calculator.algorithm_identity(), self.pipeline.get_steps())
+        }
+    }
+
+    impl<InnerCalc: EditDistanceCalculator<str>> EditDistanceCalculator<str> for NormalizingEditDistanceCalculator<InnerCalc> {
+        fn get_edit_distance(&self, item1: &str, item2: &str) -> EditDistance {
+            self.inner_calculator.get_edit_distance(&self.pipeline.normalize(item1), &self.pipeline.normalize(item2))
+        }
+
+        fn get_bounded_edit_distance(&self, item1: &str, item2: &str, max_distance: EditDistance) -> Option<EditDistance> {
+            self.inner_calculator.get_bounded_edit_distance(&self.pipeline.normalize(item1), &self.pipeline.normalize(item2), max_distance)
+        }
+    }
+
+    /// Similarity counterpart of `NormalizingEditDistanceCalculator`.
+    #[derive(Debug)]
+    pub struct NormalizingSimilarityCalculator<InnerCalc: SimilarityCalculator> {
+        pipeline: Arc<NormalizationPipeline>,
+        inner_calculator: Arc<InnerCalc>,
+    }
+
+    impl<InnerCalc: SimilarityCalculator> NormalizingSimilarityCalculator<InnerCalc> {
+        pub fn new(pipeline: Arc<NormalizationPipeline>, inner_calculator: Arc<InnerCalc>) -> Self {
+            let ret_val = NormalizingSimilarityCalculator { pipeline: pipeline, inner_calculator: inner_calculator };
+            ret_val
+        }
+
+        pub fn get_pipeline(&self) -> Arc<NormalizationPipeline> {
+            self.pipeline.clone()
+        }
+    }
+
+    impl<InnerCalc: SimilarityCalculator> SimilarityCalculator for NormalizingSimilarityCalculator<InnerCalc> {
+        fn algorithm_name(&self) -> &'static str {
+            return "Normalized Similarity";
+        }
+
+        fn get_similarity(&self, item1: &str, item2: &str) -> Similarity {
+            self.inner_calculator.get_similarity(&self.pipeline.normalize(item1), &self.pipeline.normalize(item2))
+        }
+    }
+}
+
+pub mod name_comparison {
+    use std::collections::BTreeMap;
+    use std::fmt;
+    use std::sync::Arc;
+    use strsim::jaro_winkler;
+    use super::fuzzy_matching::{Similarity, INITIAL_SIMILARITY};
+    use super::human::*;
+    use super::name_variants::NameVariantDictionary;
+    use super::normalization::NormalizationPipeline;
+
+    /// Roles that are compared with one another. Maiden and mother's maiden names are family names a person may also
+    /// be recorded under; father's and grandfather's names are patronymics.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    pub enum NameRoleGroup {
+        Given,
+        Middle,
+        Family,
+        Patronymic,
+    }
+
+    pub const ALL_NAME_ROLE_GROUPS: [NameRoleGroup; 4] = [
+        NameRoleGroup::Given,
+        NameRoleGroup::Middle,
+        NameRoleGroup::Family,
+        NameRoleGroup::Patronymic,
+    ];
+
+    impl fmt::Display for NameRoleGroup {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                NameRoleGroup::Given      => write!(f, "given"),
+                NameRoleGroup::Middle     => write!(f, "middle"),
+                NameRoleGroup::Family     => write!(f, "family"),
+                NameRoleGroup::Patronymic => write!(f, "patronymic"),
+            }
+        }
+    }
+
+    pub fn name_role_group(role: NameComponentRole) -> NameRoleGroup {
+        match role {
+            NameComponentRole::Given            => NameRoleGroup::Given,
+            NameComponentRole::Middle           => NameRoleGroup::Middle,
+            NameComponentRole::Family           => NameRoleGroup::Family,
+            NameComponentRole::Maiden           => NameRoleGroup::Family,
+            NameComponentRole::MothersMaiden    => NameRoleGroup::Family,
+            NameComponentRole::FathersName      => NameRoleGroup::Patronymic,
+            NameComponentRole::GrandfathersName => NameRoleGroup::Patronymic,
+        }
+    }
+
+    /// Relative weight of each role group in the overall score. Family names are the most telling, middle names and
+    /// patronymics the least, as they are so often left out or abbreviated.
+    pub fn default_group_weight(group: NameRoleGroup) -> f64 {
+        match group {
+            NameRoleGroup::Given      => 3.0,
+            NameRoleGroup::Middle     => 1.0,
+            NameRoleGroup::Family     => 4.0,
+            NameRoleGroup::Patronymic => 1.0,
+        }
+    }
+
+    /// Factor applied to the score of names that only match with given and family names swapped ("Doe Jane").
+    pub const SWAPPED_NAME_FACTOR: Similarity = 0.95;
+    /// Factor applied when only one part of a hyphenated or multi-word component matches ("Smith" in "Smith-Jones").
+    pub const PARTIAL_COMPONENT_FACTOR: Similarity = 0.9;
+
+    /// How the best-matching pair of components in a role group matched.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum ComponentMatchKind {
+        Exact,
+        /// Known variants in the comparator's dictionary, such as "Bill" and "William".
+        Variant,
+        /// A lone initial and a component starting with the same letter, such as "J." and "John".
+        Initial,
+        /// One part of a hyphenated or multi-word component, such as "Smith" and "Smith-Jones".
+        Partial,
+        Fuzzy,
+        /// Only one of the names has a component in the group.
+        Missing,
+    }
+
+    /// The comparison of one role group, with the components that matched best.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct ComponentComparison {
+        pub group: NameRoleGroup,
+        pub component1: Option<Arc<String>>,
+        pub component2: Option<Arc<String>>,
+        pub kind: ComponentMatchKind,
+        pub similarity: Similarity,
+        /// Weight of the group in the overall score; 0 when the group was left out of it.
+        pub weight: f64,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct HumanNameComparison {
+        pub similarity: Similarity,
+        /// True if the first name's given and family names matched the second's the other way round.
+        pub swapped: bool,
+        pub components: Vec<ComponentComparison>,
+    }
+
+    /// A name component normalized and split on hyphens and whitespace, with periods and apostrophes dropped.
+    #[derive(Debug, Clone)]
+    struct PreparedComponent {
+        original: Arc<String>,
+        parts: Vec<String>,
+    }
+
+    impl PreparedComponent {
+        fn joined(&self) -> String {
+            self.parts.join(" ")
+        }
+
+        fn is_initial(&self) -> bool {
+            self.parts.len() == 1 && self.parts[0].chars().count() == 1
+        }
+    }
+
+    type PreparedName = BTreeMap<NameRoleGroup, Vec<PreparedComponent>>;
+
+    /// Compares `HumanName`s component by component, aligning them by role instead of comparing display strings.
+    ///
+    /// Given names are compared with given names, family names (including maiden names) with family names, and so on.
+    /// A middle name or patronymic present in only one of the names is left out of the score, while a missing given or
+    /// family name counts as a complete mismatch. Where a name has no family name its patronymics stand in for it, and
+    /// where it has no patronymic its middle names do, so "Ivan Ivanovich Petrov" read in Western order still lines up
+    /// with a `Patronymic2` name.
+    #[derive(Debug, Clone)]
+    pub struct HumanNameComparator {
+        pipeline: NormalizationPipeline,
+        dictionary: Option<Arc<NameVariantDictionary>>,
+        group_weights: BTreeMap<NameRoleGroup, f64>,
+    }
+
+    impl HumanNameComparator {
+        pub fn new() -> Self {
+            let ret_val = HumanNameComparator {
+                pipeline: NormalizationPipeline::for_matching(),
+                dictionary: None,
+                group_weights: ALL_NAME_ROLE_GROUPS.iter().map(|g| (*g, default_group_weight(*g))).collect(),
+            };
+            ret_val
+        }
+
+        /// Normalization applied to each component before it is split into parts and compared.
+        pub fn with_pipeline(mut self, pipeline: NormalizationPipeline) -> Self {
+            self.pipeline = pipeline;
+            self
+        }
+
+        /// Given names the dictionary lists as variants of one another compare as exact matches.
+        pub fn with_variant_dictionary(mut self, dictionary: Arc<NameVariantDictionary>) -> Self {
+            self.dictionary = Some(dictionary);
+            self
+        }
+
+        pub fn set_group_weight(&mut self, group: NameRoleGroup, weight: f64) {
+            self.group_weights.insert(group, weight);
+        }
+
+        pub fn get_group_weight(&self, group: NameRoleGroup) -> f64 {
+            self.group_weights[&group]
+        }
+
+        pub fn get_similarity(&self, name1: &HumanName, name2: &HumanName) -> Similarity {
+            self.compare(name1, name2).similarity
+        }
+
+        /// Compares the names as given and with the first name's given and family names swapped, keeping whichever
+        /// scores better once the swap has been penalized by `SWAPPED_NAME_FACTOR`.
+        pub fn compare(&self, name1: &HumanName, name2: &HumanName) -> HumanNameComparison {
+            let prepared1 = self.prepare(name1);
+            let prepared2 = self.prepare(name2);
+            let mut ret_val = self.compare_prepared(&prepared1, &prepared2, false);
+            if prepared1.contains_key(&NameRoleGroup::Given) && prepared1.contains_key(&NameRoleGroup::Family) {
+                let mut swapped1 = prepared1.clone();
+                let given = swapped1.remove(&NameRoleGroup::Given).unwrap_or_default();
+                let family = swapped1.remove(&NameRoleGroup::Family).unwrap_or_default();
+                swapped1.insert(NameRoleGroup::Given, family);
+                swapped1.insert(NameRoleGroup::Family, given);
+                let swapped = self.compare_prepared(&swapped1, &prepared2, true);
+                if swapped.similarity > ret_val.similarity {
+                    ret_val = swapped;
+                }
+            }
+            ret_val
+        }
+
+        fn prepare(&self, name: &HumanName) -> PreparedName {
+            let mut ret_val = PreparedName::new();
+            for (role, component) in name.get_components() {
+                let normalized: String = self.pipeline.normalize(&component).chars().filter(|c| *c != '.' && *c != '\'').collect();
+                let parts: Vec<String> = normalized.split(|c: char| c == '-' || c.is_whitespace()).filter(|p| !p.is_empty()).map(|p| p.to_string()).collect();
+                if !parts.is_empty() {
+                    ret_val.entry(name_role_group(role)).or_default().push(PreparedComponent { original: component, parts: parts });
+                }
+            }
+            ret_val
+        }
+
+        fn compare_prepared(&self, name1: &PreparedName, name2: &PreparedName, swapped: bool) -> HumanNameComparison {
+            let mut components = Vec::new();
+            let (mut total, mut total_weight) = (0.0, 0.0);
+            for group in ALL_NAME_ROLE_GROUPS.iter() {
+                let (components1, components2) = match (name1.get(group), name2.get(group)) {
+                    (None, None)         => continue,
+                    (Some(c1), None)     => (Some(c1), stand_in(name2, *group)),
+                    (None, Some(c2))     => (stand_in(name1, *group), Some(c2)),
+                    (c1, c2)             => (c1, c2),
+                };
+                let (components1, components2) = match (components1, components2) {
+                    (Some(c1), Some(c2)) => (c1, c2),
+                    (c1, c2)             => {
+                        // A given or family name on one side only is a real disagreement; a middle name or patronymic
+                        // on one side only is usually just left out of the other record.
+                        let weight = match group {
+                            NameRoleGroup::Given | NameRoleGroup::Family => self.get_group_weight(*group),
+                            _                                            => 0.0,
+                        };
+                        total_weight += weight;
+                        components.push(ComponentComparison {
+                            group: *group,
+                            component1: c1.map(|c| Arc::clone(&c[0].original)),
+                            component2: c2.map(|c| Arc::clone(&c[0].original)),
+                            kind: ComponentMatchKind::Missing,
+                            similarity: 0.0,
+                            weight: weight,
+                        });
+                        continue;
+                    },
+                };
+                let mut best: Option<(&PreparedComponent, &PreparedComponent, ComponentMatchKind, Similarity)> = None;
+                for c1 in components1 {
+                    for c2 in components2 {
+                        let (kind, similarity) = self.component_similarity(*group, c1, c2);
+                        if best.is_none_or(|(_, _, _, s)| similarity > s) {
+                            best = Some((c1, c2, kind, similarity));
+                        }
+                    }
+                }
+                let (c1, c2, kind, similarity) = best.expect("both groups are non-empty");
+                let weight = self.get_group_weight(*group);
+                total += weight * similarity;
+                total_weight += weight;
+                components.push(ComponentComparison {
+                    group: *group,
+                    component1: Some(Arc::clone(&c1.original)),
+                    component2: Some(Arc::clone(&c2.original)),
+                    kind: kind,
+                    similarity: similarity,
+                    weight: weight,
+                });
+            }
+            let mut similarity = if total_weight > 0.0 { total / total_weight } else { 0.0 };
+            if swapped {
+                similarity *= SWAPPED_NAME_FACTOR;
+            }
+            let ret_val = HumanNameComparison { similarity: similarity, swapped: swapped, components: components };
+            ret_val
+        }
+
+        fn component_similarity(&self, group: NameRoleGroup, c1: &PreparedComponent, c2: &PreparedComponent) -> (ComponentMatchKind, Similarity) {
+            if c1.parts == c2.parts {
+                return (ComponentMatchKind::Exact, 1.0);
+            }
+            if c1.is_initial() || c2.is_initial() {
+                let same_initial = c1.parts[0].chars().next() == c2.parts[0].chars().next();
+                return (ComponentMatchKind::Initial, if same_initial { INITIAL_SIMILARITY } else { 0.0 });
+            }
+            let (joined1, joined2) = (c1.joined(), c2.joined());
+            if group != NameRoleGroup::Family && group != NameRoleGroup::Patronymic {
+                if let Some(dictionary) = &self.dictionary {
+                    if dictionary.are_variants(&joined1, &joined2) {
+                        return (ComponentMatchKind::Variant, 1.0);
+                    }
+                }
+            }
+            let fuzzy = jaro_winkler(&joined1, &joined2);
+            if c1.parts.len() > 1 || c2.parts.len() > 1 {
+                let partial = c1.parts.iter()
+                    .flat_map(|p1| c2.parts.iter().map(move |p2| jaro_winkler(p1, p2)))
+                    .fold(0.0, Similarity::max) * PARTIAL_COMPONENT_FACTOR;
+                if partial > fuzzy {
+                    return (ComponentMatchKind::Partial, partial);
+                }
+            }
+            (ComponentMatchKind::Fuzzy, fuzzy)
+        }
+    }
+
+    impl Default for HumanNameComparator {
+        fn default() -> Self {
+            HumanNameComparator::new()
+        }
+    }
+
+    /// The components standing in for `group` in a name that has none of its own, when the other name does:
+    /// patronymics for a family name, middle names for a patronymic.
+    fn stand_in(name: &PreparedName, group: NameRoleGroup) -> Option<&Vec<PreparedComponent>> {
+        match group {
+            NameRoleGroup::Family     => name.get(&NameRoleGroup::Patronymic),
+            NameRoleGroup::Patronymic => name.get(&NameRoleGroup::Middle),
+            _                         => None,
+        }
+    }
+}
+
+pub mod date_comparison {
+    use std::collections::BTreeMap;
+    use super::fuzzy_matching::*;
+    use super::option_date_time::{CompleteDate, OptionDate};
+
+    /// The error pattern, if any, that explains the difference between two dates. Only components known in both
+    /// dates are compared; a component missing from either is neither agreement nor disagreement.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    pub enum DateMatchKind {
+        Exact,
+        /// Every component known in both dates agrees, but at least one is unknown in one of them.
+        PartialAgreement,
+        /// Day and month swapped, as happens between US and European date orders.
+        DayMonthTransposed,
+        /// One component differs by a single digit, or by two adjacent digits swapped ("1973" and "1937").
+        DigitTypo,
+        /// Only the year differs, and by exactly 10 or 100 years: a misread decade or a two-digit year expanded into
+        /// the wrong century.
+        YearOffByDecadeOrCentury,
+        /// No recognised pattern, but the dates are within the comparator's maximum number of days.
+        Close,
+        Different,
+        /// No component is known in both dates.
+        Missing,
+    }
+
+    pub const ALL_DATE_MATCH_KINDS: [DateMatchKind; 8] = [
+        DateMatchKind::Exact,
+        DateMatchKind::PartialAgreement,
+        DateMatchKind::DayMonthTransposed,
+        DateMatchKind::DigitTypo,
+        DateMatchKind::YearOffByDecadeOrCentury,
+        DateMatchKind::Close,
+        DateMatchKind::Different,
+        DateMatchKind::Missing,
+    ];
+
+    /// Similarity of full dates that differ by each pattern. `Close` falls off linearly from this value with the
+    /// number of days apart; `PartialAgreement` uses the share of the date that could be compared instead.
+    pub fn default_match_kind_similarity(kind: DateMatchKind) -> Similarity {
+        match kind {
+            DateMatchKind::Exact                    => 1.0,
+            DateMatchKind::PartialAgreement         => 1.0,
+            DateMatchKind::DayMonthTransposed       => 0.85,
+            DateMatchKind::DigitTypo                => 0.8,
+            DateMatchKind::YearOffByDecadeOrCentury => 0.7,
+            DateMatchKind::Close                    => 0.6,
+            DateMatchKind::Different                => 0.0,
+            DateMatchKind::Missing                  => 0.0,
+        }
+    }
+
+    /// Share of a full date's identifying power carried by each component: a year narrows things down far more than
+    /// a day of the month does.
+    pub const YEAR_SHARE: Similarity = 0.5;
+    pub const MONTH_SHARE: Similarity = 0.3;
+    pub const DAY_SHARE: Similarity = 0.2;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct DateComparison {
+        pub similarity: Similarity,
+        pub kind: DateMatchKind,
+        /// Sum of the shares of the components known in both dates; 1 when both dates are complete.
+        pub compared_share: Similarity,
+    }
+
+    /// Scores two `OptionDate`s, recognising the ways birth dates are commonly mistyped. The similarity is the
+    /// pattern's similarity scaled by the share of the date that both sides know, so a partial date agreeing with a
+    /// full one scores lower than two agreeing full dates.
+    #[derive(Debug, Clone)]
+    pub struct BirthDateComparator {
+        kind_similarities: BTreeMap<DateMatchKind, Similarity>,
+        max_days: EditDistance,
+    }
+
+    impl BirthDateComparator {
+        pub fn new() -> Self {
+            let ret_val = BirthDateComparator {
+                kind_similarities: ALL_DATE_MATCH_KINDS.iter().map(|k| (*k, default_match_kind_similarity(*k))).collect(),
+                max_days: DEFAULT_BIRTH_DATE_MAX_DAYS,
+            };
+            ret_val
+        }
+
+        pub fn set_kind_similarity(&mut self, kind: DateMatchKind, similarity: Similarity) {
+            self.kind_similarities.insert(kind, similarity);
+        }
+
+        pub fn get_kind_similarity(&self, kind: DateMatchKind) -> Similarity {
+            self.kind_similarities[&kind]
+        }
+
+        /// Largest number of days apart at which complete dates with no recognised error pattern still count as `Close`.
+        pub fn set_max_days(&mut self, max_days: EditDistance) {
+            self.max_days = max_days;
+        }
+
+        pub fn get_max_days(&self) -> EditDistance {
+            self.max_days
+        }
+
+        pub fn get_similarity(&self, date1: &OptionDate, date2: &OptionDate) -> Similarity {
+            self.compare(date1, date2).similarity
+        }
+
+        pub fn compare(&self, date1: &OptionDate, date2: &OptionDate) -> DateComparison {
+            let years = both(date1.get_year(), date2.get_year());
+            let months = both(date1.get_month().map(i64::from), date2.get_month().map(i64::from));
+            let days = both(date1.get_day().map(i64::from), date2.get_day().map(i64::from));
+            let compared_share = [(years, YEAR_SHARE), (months, MONTH_SHARE), (days, DAY_SHARE)].iter()
+                .filter(|(pair, _)| pair.is_some())
+                .map(|(_, share)| share)
+                .sum::<Similarity>();
+            if compared_share == 0.0 {
+                return self.comparison(DateMatchKind::Missing, 0.0, compared_share);
+            }
+            let differs = |pair: Option<(i64, i64)>| pair.is_some_and(|(a, b)| a != b);
+            let differing: Vec<(Option<(i64, i64)>, usize)> = [(years, 4), (months, 2), (days, 2)].iter()
+                .filter(|(pair, _)| differs(*pair))
+                .cloned()
+                .collect();
+            if differing.is_empty() {
+                let complete = years.is_some() && months.is_some() && days.is_some();
+                let kind = if complete { DateMatchKind::Exact } else { DateMatchKind::PartialAgreement };
+                return self.comparison(kind, self.get_kind_similarity(kind) * compared_share, compared_share);
+            }
+
+            let mut kinds = Vec::new();
+            if let (Some((month1, month2)), Some((day1, day2))) = (months, days) {
+                if month1 == day2 && day1 == month2 && !differs(years) {
+                    kinds.push(DateMatchKind::DayMonthTransposed);
+                }
+            }
+            if differing.len() == 1 {
+                let (pair, width) = differing[0];
+                let (a, b) = pair.expect("differing components are known in both dates");
+                if is_digit_typo(a, b, width) {
+                    kinds.push(DateMatchKind::DigitTypo);
+                }
+                if differs(years) && ((a - b).abs() == 10 || (a - b).abs() == 100) {
+                    kinds.push(DateMatchKind::YearOffByDecadeOrCentury);
+                }
+            }
+            let mut ret_val = self.comparison(DateMatchKind::Different, self.get_kind_similarity(DateMatchKind::Different) * compared_share, compared_share);
+            for kind in kinds {
+                let similarity = self.get_kind_similarity(kind) * compared_share;
+                if similarity > ret_val.similarity {
+                    ret_val = self.comparison(kind, similarity, compared_share);
+                }
+            }
+            if let (Some(complete1), Some(complete2)) = (CompleteDate::from_option_date(date1), CompleteDate::from_option_date(date2)) {
+                if let Some(days_apart) = DateDistanceCalculator::new().get_bounded_edit_distance(&complete1, &complete2, self.max_days) {
+                    let similarity = self.get_kind_similarity(DateMatchKind::Close) * (1.0 - days_apart as Similarity / (self.max_days + 1) as Similarity);
+                    if similarity > ret_val.similarity {
+                        ret_val = self.comparison(DateMatchKind::Close, similarity, compared_share);
+                    }
+                }
+            }
+            ret_val
+        }
+
+        fn comparison(&self, kind: DateMatchKind, similarity: Similarity, compared_share: Similarity) -> DateComparison {
+            let ret_val = DateComparison { similarity: similarity, kind: kind, compared_share: compared_share };
+            ret_val
+        }
+    }
+
+    impl Default for BirthDateComparator {
+        fn default() -> Self {
+            BirthDateComparator::new()
+        }
+    }
+
+    fn both(a: Option<i64>, b: Option<i64>) -> Option<(i64, i64)> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some((a, b)),
+            _                  => None,
+        }
+    }
+
+    /// True if the values, zero-padded to `width` digits, differ in exactly one digit or by one swap of adjacent digits.
+    fn is_digit_typo(a: i64, b: i64, width: usize) -> bool {
+        if a < 0 || b < 0 {
+            return false;
+        }
+        let digits1: Vec<char> = format!("{:0width$}", a, width = width).chars().collect();
+        let digits2: Vec<char> = format!("{:0width$}", b, width = width).chars().collect();
+        if digits1.len() != digits2.len() {
+            return false;
+        }
+        let positions: Vec<usize> = (0..digits1.len()).filter(|i| digits1[*i] != digits2[*i]).collect();
+        match positions.len() {
+            1 => true,
+            2 => positions[1] == positions[0] + 1 && digits1[positions[0]] == digits2[positions[1]] && digits1[positions[1]] == digits2[positions[0]],
+            _ => false,
+        }
+    }
+}
+
+pub mod address_comparison {
+    use std::collections::BTreeMap;
+    use std::fmt;
+    use strsim::jaro_winkler;
+    use super::entity::Address;
+    use super::fuzzy_matching::Similarity;
+    use super::normalization::{normalize_postal_code, NormalizationPipeline};
+
+    /// Street suffixes and their USPS standard abbreviations.
+    pub const STREET_SUFFIXES: &[(&str, &str)] = &[
+        ("alley", "aly"), ("avenue", "ave"), ("av", "ave"), ("boulevard", "blvd"), ("circle", "cir"), ("court", "ct"),
+        ("crescent", "cres"), ("drive", "dr"), ("expressway", "expy"), ("freeway", "fwy"), ("highway", "hwy"),
+        ("lane", "ln"), ("parkway", "pkwy"), ("place", "pl"), ("plaza", "plz"), ("road", "rd"), ("square", "sq"),
+        ("street", "st"), ("str", "st"), ("terrace", "ter"), ("trail", "trl"), ("way", "way"),
+    ];
+
+    pub const DIRECTIONALS: &[(&str, &str)] = &[
+        ("north", "n"), ("south", "s"), ("east", "e"), ("west", "w"),
+        ("northeast", "ne"), ("northwest", "nw"), ("southeast", "se"), ("southwest", "sw"),
+    ];
+
+    /// Words introducing a unit within a building. "#" is split off as a token of its own before matching.
+    pub const UNIT_DESIGNATORS: &[&str] = &["apt", "apartment", "unit", "suite", "ste", "#", "fl", "floor", "rm", "room", "bldg", "building"];
+
+    pub const COUNTRY_ALIASES: &[(&str, &str)] = &[
+        ("united states", "us"), ("united states of america", "us"), ("usa", "us"),
+        ("united kingdom", "gb"), ("uk", "gb"), ("great britain", "gb"),
+        ("canada", "ca"), ("mexico", "mx"),
+    ];
+
+    /// An address split into the components that are compared separately, each normalized and abbreviated the
+    /// standard way. Components that could not be found are `None`.
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct StandardizedAddress {
+        pub house_number: Option<String>,
+        /// Street name with directionals abbreviated, without its suffix.
+        pub street_name: Option<String>,
+        pub street_suffix: Option<String>,
+        pub unit: Option<String>,
+        pub city: Option<String>,
+        pub state_or_province: Option<String>,
+        pub postal_code: Option<String>,
+        pub country: Option<String>,
+    }
+
+    impl StandardizedAddress {
+        pub fn new(address: &Address, pipeline: &NormalizationPipeline) -> Self {
+            let mut tokens = tokenize(&address.get_line_1(), pipeline);
+            let house_number = if tokens.first().is_some_and(|t| t.starts_with(|c: char| c.is_ascii_digit())) {
+                Some(tokens.remove(0))
+            } else {
+                None
+            };
+            let mut unit = None;
+            if let Some(at) = tokens.iter().position(|t| UNIT_DESIGNATORS.contains(&t.as_str())) {
+                unit = non_empty(tokens[at + 1..].join(" "));
+                tokens.truncate(at);
+            }
+            for line in &[address.get_line_2(), address.get_line_3()] {
+                let line_tokens: Vec<String> = tokenize(line, pipeline).into_iter().filter(|t| !UNIT_DESIGNATORS.contains(&t.as_str())).collect();
+                if unit.is_none() && !line_tokens.is_empty() {
+                    unit = Some(line_tokens.join(" "));
+                }
+            }
+            let street_suffix = match tokens.last().and_then(|t| standard_abbreviation(t, STREET_SUFFIXES)) {
+                Some(suffix) if tokens.len() > 1 => {
+                    tokens.pop();
+                    Some(suffix)
+                },
+                _                                => None,
+            };
+            let street_name: Vec<String> = tokens.iter().map(|t| standard_abbreviation(t, DIRECTIONALS).unwrap_or_else(|| t.clone())).collect();
+            let country = non_empty(tokenize(&address.get_country(), pipeline).join(" "));
+            let ret_val = StandardizedAddress {
+                house_number: house_number,
+                street_name: non_empty(street_name.join(" ")),
+                street_suffix: street_suffix,
+                unit: unit,
+                city: non_empty(tokenize(&address.get_city(), pipeline).join(" ")),
+                state_or_province: non_empty(tokenize(&address.get_state_or_province(), pipeline).join(" ")),
+                postal_code: non_empty(normalize_postal_code(&address.get_zip_or_postal_code())),
+                country: country.map(|c| standard_abbreviation(&c, COUNTRY_ALIASES).unwrap_or(c)),
+            };
+            ret_val
+        }
+    }
+
+    /// Normalizes, splits "#" from what follows it, and breaks on whitespace and punctuation other than "#" and "-".
+    fn tokenize(s: &str, pipeline: &NormalizationPipeline) -> Vec<String> {
+        pipeline.normalize(s).replace('#', " # ")
+            .split(|c: char| !(c.is_alphanumeric() || c == '#' || c == '-'))
+            .filter(|t| !t.is_empty())
+            .map(|t| t.to_string())
+            .collect()
+    }
+
+    fn standard_abbreviation(token: &str, table: &[(&str, &str)]) -> Option<String> {
+        table.iter()
+            .find(|(long, short)| *long == token || *short == token)
+            .map(|(_, short)| short.to_string())
+    }
+
+    fn non_empty(s: String) -> Option<String> {
+        if s.is_empty() { None } else { Some(s) }
+    }
+
+    /// Parts of an address scored separately. `Locality` covers city, state and postal code together, since one
+    /// can stand in for another.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    pub enum AddressComponent {
+        HouseNumber,
+        StreetName,
+        Unit,
+        Locality,
+        Country,
+    }
+
+    pub const ALL_ADDRESS_COMPONENTS: [AddressComponent; 5] = [
+        AddressComponent::HouseNumber,
+        AddressComponent::StreetName,
+        AddressComponent::Unit,
+        AddressComponent::Locality,
+        AddressComponent::Country,
+    ];
+
+    impl fmt::Display for AddressComponent {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                AddressComponent::HouseNumber => write!(f, "house_number"),
+                AddressComponent::StreetName  => write!(f, "street_name"),
+                AddressComponent::Unit        => write!(f, "unit"),
+                AddressComponent::Locality    => write!(f, "locality"),
+                AddressComponent::Country     => write!(f, "country"),
+            }
+        }
+    }
+
+    pub fn default_component_weight(component: AddressComponent) -> f64 {
+        match component {
+            AddressComponent::HouseNumber => 2.0,
+            AddressComponent::StreetName  => 3.0,
+            AddressComponent::Unit        => 1.0,
+            AddressComponent::Locality    => 3.0,
+            AddressComponent::Country     => 1.0,
+        }
+    }
+
+    /// The most specific level at which the localities of two addresses agree.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    pub enum LocalityMatchLevel {
+        PostalCode,
+        City,
+        /// Same first three characters of the postal code: the same US sectional centre or UK postcode area.
+        PostalCodePrefix,
+        StateOrProvince,
+        None,
+    }
+
+    /// Factor applied when only the street suffixes differ ("Main St" and "Main Ave").
+    pub const STREET_SUFFIX_MISMATCH_FACTOR: Similarity = 0.9;
+    /// Factor applied to a city match when both postal codes are known and differ: large cities span many codes.
+    pub const CITY_WITH_DIFFERENT_POSTAL_CODE_FACTOR: Similarity = 0.9;
+    /// Jaro-Winkler similarity at or above which two city names are taken to be the same city.
+    pub const CITY_SIMILARITY_THRESHOLD: Similarity = 0.9;
+    pub const POSTAL_CODE_PREFIX_SIMILARITY: Similarity = 0.7;
+    pub const STATE_OR_PROVINCE_SIMILARITY: Similarity = 0.3;
+    const POSTAL_CODE_PREFIX_LEN: usize = 3;
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct AddressComponentComparison {
+        pub component: AddressComponent,
+        pub value1: Option<String>,
+        pub value2: Option<String>,
+        pub similarity: Similarity,
+        /// Weight of the component in the overall score; 0 when it is missing from either address.
+        pub weight: f64,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct AddressComparison {
+        pub similarity: Similarity,
+        pub locality_match_level: LocalityMatchLevel,
+        pub components: Vec<AddressComponentComparison>,
+    }
+
+    /// Scores two `Address`es component by component: house number and unit exactly, street name fuzzily, and the
+    /// locality by postal code, falling back to city, postal code prefix and state. Components missing from either
+    /// address are left out of the weighted score.
+    #[derive(Debug, Clone)]
+    pub struct AddressComparator {
+        pipeline: NormalizationPipeline,
+        component_weights: BTreeMap<AddressComponent, f64>,
+    }
+
+    impl AddressComparator {
+        pub fn new() -> Self {
+            let ret_val = AddressComparator {
+                pipeline: NormalizationPipeline::for_matching(),
+                component_weights: ALL_ADDRESS_COMPONENTS.iter().map(|c| (*c, default_component_weight(*c))).collect(),
+            };
+            ret_val
+        }
+
+        /// Normalization applied to every address line before it is split into components.
+        pub fn with_pipeline(mut self, pipeline: NormalizationPipeline) -> Self {
+            self.pipeline = pipeline;
+            self
+        }
+
+        pub fn set_component_weight(&mut self, component: AddressComponent, weight: f64) {
+            self.component_weights.insert(component, weight);
+        }
+
+        pub fn get_component_weight(&self, component: AddressComponent) -> f64 {
+            self.component_weights[&component]
+        }
+
+        pub fn standardize(&self, address: &Address) -> StandardizedAddress {
+            StandardizedAddress::new(address, &self.pipeline)
+        }
+
+        pub fn get_similarity(&self, address1: &Address, address2: &Address) -> Similarity {
+            self.compare(address1, address2).similarity
+        }
+
+        pub fn compare(&self, address1: &Address, address2: &Address) -> AddressComparison {
+            self.compare_standardized(&self.standardize(address1), &self.standardize(address2))
+        }
+
+        pub fn compare_standardized(&self, address1: &StandardizedAddress, address2: &StandardizedAddress) -> AddressComparison {
+            let (locality_similarity, locality_match_level) = locality_similarity(address1, address2);
+            let mut components = Vec::new();
+            let (mut total, mut total_weight) = (0.0, 0.0);
+            for component in ALL_ADDRESS_COMPONENTS.iter() {
+                let (value1, value2, similarity) = match component {
+                    AddressComponent::HouseNumber => (address1.house_number.clone(), address2.house_number.clone(), exact_similarity(&address1.house_number, &address2.house_number)),
+                    AddressComponent::StreetName  => (address1.street_name.clone(), address2.street_name.clone(), street_similarity(address1, address2)),
+                    AddressComponent::Unit        => (address1.unit.clone(), address2.unit.clone(), exact_similarity(&address1.unit, &address2.unit)),
+                    AddressComponent::Locality    => (locality(address1), locality(address2), locality_similarity),
+                    AddressComponent::Country     => (address1.country.clone(), address2.country.clone(), exact_similarity(&address1.country, &address2.country)),
+                };
+                let weight = match similarity {
+                    Some(_) => self.get_component_weight(*component),
+                    None    => 0.0,
+                };
+                let similarity = similarity.unwrap_or(0.0);
+                total += weight * similarity;
+                total_weight += weight;
+                components.push(AddressComponentComparison { component: *component, value1: value1, value2: value2, similarity: similarity, weight: weight });
+            }
+            let similarity = if total_weight > 0.0 { total / total_weight } else { 0.0 };
+            let ret_val = AddressComparison { similarity: similarity, locality_match_level: locality_match_level, components: components };
+            ret_val
+        }
+    }
+
+    impl Default for AddressComparator {
+        fn default() -> Self {
+            AddressComparator::new()
+        }
+    }
+
+    /// 1 or 0 for equal or unequal values; `None` if either is missing.
+    fn exact_similarity(value1: &Option<String>, value2: &Option<String>) -> Option<Similarity> {
+        match (value1, value2) {
+            (Some(v1), Some(v2)) => Some(if v1 == v2 { 1.0 } else { 0.0 }),
+            _                    => None,
+        }
+    }
+
+    fn street_similarity(address1: &StandardizedAddress, address2: &StandardizedAddress) -> Option<Similarity> {
+        let (street1, street2) = match (&address1.street_name, &address2.street_name) {
+            (Some(s1), Some(s2)) => (s1, s2),
+            _                    => return None,
+        };
+        let mut ret_val = jaro_winkler(street1, street2);
+        if let (Some(suffix1), Some(suffix2)) = (&address1.street_suffix, &address2.street_suffix) {
+            if suffix1 != suffix2 {
+                ret_val *= STREET_SUFFIX_MISMATCH_FACTOR;
+            }
+        }
+        Some(ret_val)
+    }
+
+    fn locality(address: &StandardizedAddress) -> Option<String> {
+        let parts: Vec<&str> = [&address.city, &address.state_or_province, &address.postal_code].iter().filter_map(|p| p.as_deref()).collect();
+        non_empty(parts.join(" "))
+    }
+
+    /// The best similarity over the hierarchy postal code, city, postal code prefix, state; `None` if no level
+    /// has values in both addresses.
+    fn locality_similarity(address1: &StandardizedAddress, address2: &StandardizedAddress) -> (Option<Similarity>, LocalityMatchLevel) {
+        let postal_codes = address1.postal_code.as_ref().zip(address2.postal_code.as_ref());
+        let cities = address1.city.as_ref().zip(address2.city.as_ref());
+        let states = address1.state_or_province.as_ref().zip(address2.state_or_province.as_ref());
+        if postal_codes.is_none() && cities.is_none() && states.is_none() {
+            return (None, LocalityMatchLevel::None);
+        }
+        if postal_codes.is_some_and(|(p1, p2)| p1 == p2) {
+            return (Some(1.0), LocalityMatchLevel::PostalCode);
+        }
+        let mut ret_val = (Some(0.0), LocalityMatchLevel::None);
+        let same_state = states.is_none_or(|(s1, s2)| s1 == s2);
+        if let Some((city1, city2)) = cities {
+            let city_similarity = jaro_winkler(city1, city2);
+            if same_state && city_similarity >= CITY_SIMILARITY_THRESHOLD {
+                let factor = if postal_codes.is_some() { CITY_WITH_DIFFERENT_POSTAL_CODE_FACTOR } else { 1.0 };
+                ret_val = (Some(city_similarity * factor), LocalityMatchLevel::City);
+            }
+        }
+        if ret_val.1 == LocalityMatchLevel::None && postal_codes.is_some_and(|(p1, p2)| p1.len() >= POSTAL_CODE_PREFIX_LEN && p1.get(..POSTAL_CODE_PREFIX_LEN) == p2.get(..POSTAL_CODE_PREFIX_LEN)) {
+            ret_val = (Some(POSTAL_CODE_PREFIX_SIMILARITY), LocalityMatchLevel::PostalCodePrefix);
+        }
+        if ret_val.1 == LocalityMatchLevel::None && states.is_some_and(|(s1, s2)| s1 == s2) {
+            ret_val = (Some(STATE_OR_PROVINCE_SIMILARITY), LocalityMatchLevel::StateOrProvince);
+        }
+        ret_val
+    }
+}
+
+pub mod blocking {
+    use std::collections::{HashSet, HashMap};
+    use std::sync::Arc;
+    use super::fuzzy_matching::*;
+    use super::human::*;
+    use super::phonetic::{nysiis, soundex};
+
+    /// A pair of indexes into the record slice being blocked, always with the smaller index first.
+    pub type CandidatePair = (usize, usize);
+
+    pub fn candidate_pair(i: usize, j: usize) -> CandidatePair {
+        if i <= j { (i, j) } else { (j, i) }
+    }
+
+    /// Derives the values a record is blocked on. A record may yield several keys (one per family name component,
+    /// one per address, ...) and is placed in every block it has a key for.
+    pub trait BlockingKey {
+        fn key_name(&self) -> &'static str;
+        fn blocking_keys(&self, human: &Human) -> Vec<String>;
+    }
+
+    fn family_names(human: &Human) -> Vec<Arc<String>> {
+        human.get_name().get_components_with_role(NameComponentRole::Family)
+    }
+
+    #[derive(Debug, Default)]
+    pub struct LastNameKey {}
+
+    impl LastNameKey {
+        pub fn new() -> Self {
+            let ret_val = LastNameKey {};
+            ret_val
+        }
+    }
+
+    impl BlockingKey for LastNameKey {
+        fn key_name(&self) -> &'static str {
+            "Last Name"
+        }
+
+        fn blocking_keys(&self, human: &Human) -> Vec<String> {
+            family_names(human).iter().map(|n| n.to_lowercase()).collect()
+        }
+    }
+
+    #[derive(Debug, Default)]
+    pub struct FullNameKey {}
+
+    impl FullNameKey {
+        pub fn new() -> Self {
+            let ret_val = FullNameKey {};
+            ret_val
+        }
+    }
+
+    impl BlockingKey for FullNameKey {
+        fn key_name(&self) -> &'static str {
+            "Full Name"
+        }
+
+        fn blocking_keys(&self, human: &Human) -> Vec<String> {
+            vec![human.get_name().to_string().to_lowercase()]
+        }
+    }
+
+    #[derive(Debug, Default)]
+    pub struct LastNameSoundexKey {}
+
+    impl LastNameSoundexKey {
+        pub fn new() -> Self {
+            let ret_val = LastNameSoundexKey {};
+            ret_val
+        }
+    }
+
+    impl BlockingKey for LastNameSoundexKey {
+        fn key_name(&self) -> &'static str {
+            "Last Name Soundex"
+        }
+
+        fn blocking_keys(&self, human: &Human) -> Vec<String> {
+            family_names(human).iter().map(|n| soundex(n)).filter(|k| !k.is_empty()).collect()
+        }
+    }
+
+    #[derive(Debug, Default)]
+    pub struct LastNameNysiisKey {}
+
+    impl LastNameNysiisKey {
+        pub fn new() -> Self {
+            let ret_val = LastNameNysiisKey {};
+            ret_val
+        }
+    }
+
+    impl BlockingKey for LastNameNysiisKey {
+        fn key_name(&self) -> &'static str {
+            "Last Name NYSIIS"
+        }
+
+        fn blocking_keys(&self, human: &Human) -> Vec<String> {
+            family_names(human).iter().map(|n| nysiis(n)).filter(|k| !k.is_empty()).collect()
+        }
+    }
+
+    #[derive(Debug, Default)]
+    pub struct BirthYearKey {}
+
+    impl BirthYearKey {
+        pub fn new() -> Self {
+            let ret_val = BirthYearKey {};
+            ret_val
+        }
+    }
+
+    impl BlockingKey for BirthYearKey {
+        fn key_name(&self) -> &'static str {
+            "Birth Year"
+        }
+
+        fn blocking_keys(&self, human: &Human) -> Vec<String> {
+            human.get_birth_date().get_year().map(|y| y.to_string()).into_iter().collect()
+        }
+    }
+
+    /// The first `prefix_len` characters of each ZIP or postal code on the record.
+    #[derive(Debug)]
+    pub struct ZipPrefixKey {
+        prefix_len: usize,
+    }
+
+    impl ZipPrefixKey {
+        pub fn new(prefix_len: usize) -> Self {
+            let ret_val = ZipPrefixKey { prefix_len: prefix_len };
+            ret_val
+        }
+    }
+
+    impl BlockingKey for ZipPrefixKey {
+        fn key_name(&self) -> &'static str {
+            "ZIP Prefix"
+        }
+
+        fn blocking_keys(&self, human: &Human) -> Vec<String> {
+            let mut ret_val: Vec<String> = human.get_addresses().values()
+                .map(|a| a.get_zip_or_postal_code())
+                .filter(|z| z.chars().count() >= self.prefix_len)
+                .map(|z| z.chars().take(self.prefix_len).collect())
+                .collect();
+            ret_val.sort();
+            ret_val.dedup();
+            ret_val
+        }
+    }
+
+    /// Combines several keys into one by concatenation, so records must agree on all of them to share a block.
+    pub struct CompositeKey {
+        keys: Vec<Box<dyn BlockingKey>>,
+    }
+
+    impl CompositeKey {
+        pub fn new(keys: Vec<Box<dyn BlockingKey>>) -> Self {
+            let ret_val = CompositeKey { keys: keys };
+            ret_val
+        }
+    }
+
+    impl BlockingKey for CompositeKey {
+        fn key_name(&self) -> &'static str {
+            "Composite"
+        }
+
+        fn blocking_keys(&self, human: &Human) -> Vec<String> {
+            let mut ret_val = vec![String::new()];
+            for (i, k) in self.keys.iter().enumerate() {
+                let parts = k.blocking_keys(human);
+                let mut combined = Vec::with_capacity(ret_val.len() * parts.len());
+                for prefix in &ret_val {
+                    for p in &parts {
+                        combined.push(if i == 0 { p.clone() } else { format!("{}|{}", prefix, p) });
+                    }
+                }
+                ret_val = combined;
+            }
+            ret_val
+        }
+    }
+
+    pub trait Blocker {
+        fn candidate_pairs(&self, records: &[Arc<Human>]) -> HashSet<CandidatePair>;
+    }
+
+    fn add_all_pairs(members: &[usize], pairs: &mut HashSet<CandidatePair>) {
+        for (n, i) in members.iter().enumerate() {
+            for j in &members[n + 1..] {
+                if i != j {
+                    pairs.insert(candidate_pair(*i, *j));
+                }
+            }
+        }
+    }
+
+    /// Classic blocking: records sharing a key value are compared. With several keys, each is a separate pass and
+    /// the candidate pairs of all passes are combined.
+    pub struct StandardBlocker {
+        keys: Vec<Box<dyn BlockingKey>>,
+    }
+
+    impl StandardBlocker {
+        pub fn new(keys: Vec<Box<dyn BlockingKey>>) -> Self {
+            let ret_val = StandardBlocker { keys: keys };
+            ret_val
+        }
+    }
+
+    impl Blocker for StandardBlocker {
+        fn candidate_pairs(&self, records: &[Arc<Human>]) -> HashSet<CandidatePair> {
+            let mut pairs = HashSet::new();
+            for key in &self.keys {
+                let mut blocks = HashMap::<String, Vec<usize>>::new();
+                for (i, r) in records.iter().enumerate() {
+                    for k in key.blocking_keys(r) {
+                        blocks.entry(k).or_default().push(i);
+                    }
+                }
+                for members in blocks.values() {
+                    add_all_pairs(members, &mut pairs);
+                }
+            }
+            pairs
+        }
+    }
+
+    /// Sorts records by key and compares each with the `window_size - 1` records that follow it, so near-miss keys
+    /// ("Smith"/"Smyth") still meet.
+    pub struct SortedNeighbourhoodBlocker {
+        key: Box<dyn BlockingKey>,
+        window_size: usize,
+    }
+
+    impl SortedNeighbourhoodBlocker {
+        pub fn new(key: Box<dyn BlockingKey>, window_size: usize) -> Self {
+            let ret_val = SortedNeighbourhoodBlocker { key: key, window_size: window_size };
+            ret_val
+        }
+    }
+
+    impl Blocker for SortedNeighbourhoodBlocker {
+        fn candidate_pairs(&self, records: &[Arc<Human>]) -> HashSet<CandidatePair> {
+            let mut sorted = Vec::new();
+            for (i, r) in records.iter().enumerate() {
+                for k in self.key.blocking_keys(r) {
+                    sorted.push((k, i));
+                }
+            }
+            sorted.sort();
+            let mut pairs = HashSet::new();
+            for (n, (_, i)) in sorted.iter().enumerate() {
+                for (_, j) in sorted.iter().skip(n + 1).take(self.window_size.saturating_sub(1)) {
+                    if i != j {
+                        pairs.insert(candidate_pair(*i, *j));
+                    }
+                }
+            }
+            pairs
+        }
+    }
+
+    /// Canopy thresholds must satisfy 0 <= loose <= tight <= 1; NaN satisfies nothing.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct CanopyThresholdsErr;
+    pub type CanopyBlockerResult<T> = Result<T, CanopyThresholdsErr>;
+
+    /// Canopy clustering. Repeatedly takes the first record left in the pool as a centre; every record at least
+    /// `loose_threshold` similar to it joins the canopy, and those at least `tight_threshold` similar leave the
+    /// pool. Records sharing a canopy are compared. Similarity between two records is the best similarity between
+    /// any of their key values.
+    pub struct CanopyBlocker<SimilarityCalc: SimilarityCalculator> {
+        key: Box<dyn BlockingKey>,
+        similarity_calculator: Arc<SimilarityCalc>,
+        loose_threshold: Similarity,
+        tight_threshold: Similarity,
+    }
+
+    impl<SimilarityCalc: SimilarityCalculator> CanopyBlocker<SimilarityCalc> {
+        pub fn new(key: Box<dyn BlockingKey>, similarity_calculator: Arc<SimilarityCalc>, loose_threshold: Similarity, tight_threshold: Similarity) -> CanopyBlockerResult<Self> {
+            if !(0.0 <= loose_threshold && loose_threshold <= tight_threshold && tight_threshold <= 1.0) {
+                return Err(CanopyThresholdsErr);
+            }
+            let ret_val = CanopyBlocker { key: key, similarity_calculator: similarity_calculator, loose_threshold: loose_threshold, tight_threshold: tight_threshold };
+            Ok(ret_val)
+        }
+
+        fn similarity(&self, a: &[String], b: &[String]) -> Similarity {
+            let mut best = 0.0;
+            for x in a {
+                for y in b {
+                    let s = self.similarity_calculator.get_similarity(x, y);
+                    if s > best {
+                        best = s;
+                    }
+                }
+            }
+            best
+        }
+    }
+
+    impl<SimilarityCalc: SimilarityCalculator> Blocker for CanopyBlocker<SimilarityCalc> {
+        fn candidate_pairs(&self, records: &[Arc<Human>]) -> HashSet<CandidatePair> {
+            let keys: Vec<Vec<String>> = records.iter().map(|r| self.key.blocking_keys(r)).collect();
+            let mut in_pool: Vec<bool> = keys.iter().map(|k| !k.is_empty()).collect();
+            let mut pairs = HashSet::new();
+            for centre in 0..records.len() {
+                if !in_pool[centre] {
+                    continue;
+                }
+                in_pool[centre] = false;
+                let mut canopy = vec![centre];
+                for other in 0..records.len() {
+                    if !in_pool[other] {
+                        continue;
+                    }
+                    let s = self.similarity(&keys[centre], &keys[other]);
+                    if s >= self.loose_threshold {
+                        canopy.push(other);
+                        if s >= self.tight_threshold {
+                            in_pool[other] = false;
+                        }
+                    }
+                }
+                add_all_pairs(&canopy, &mut pairs);
+            }
+            pairs
+        }
+    }
+
+    /// How well a set of candidate pairs trades comparison effort against missed matches.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct BlockingEvaluation {
+        pub candidate_pairs: usize,
+        pub total_pairs: usize,
+        pub true_matches: usize,
+        pub true_matches_found: usize,
+        /// Share of all possible pairs that blocking avoided comparing.
+        pub reduction_ratio: f64,
+        /// Share of the labelled true matches that survived blocking.
+        pub pair_completeness: f64,
+    }
+
+    pub fn evaluate_blocking(candidates: &HashSet<CandidatePair>, record_count: usize, true_matches: &HashSet<CandidatePair>) -> BlockingEvaluation {
+        let total_pairs = record_count * record_count.saturating_sub(1) / 2;
+        let true_matches_found = true_matches.iter().filter(|(i, j)| candidates.contains(&candidate_pair(*i, *j))).count();
+        let reduction_ratio = if total_pairs == 0 { 0.0 } else { 1.0 - candidates.len() as f64 / total_pairs as f64 };
+        let pair_completeness = if true_matches.is_empty() { 1.0 } else { true_matches_found as f64 / true_matches.len() as f64 };
+        BlockingEvaluation { candidate_pairs: candidates.len(), total_pairs: total_pairs, true_matches: true_matches.len(), true_matches_found: true_matches_found, reduction_ratio: reduction_ratio, pair_completeness: pair_completeness }
+    }
+}
+
+pub mod deduplication {
+    use std::collections::{HashSet, HashMap};
+    use std::sync::Arc;
+    use super::blocking::{candidate_pair, CandidatePair};
+    use super::entity::*;
+    use super::human::*;
+    use super::option_date_time::OptionDate;
+    use super::organization::Organization;
+
+    fn find(parents: &mut [usize], i: usize) -> usize {
+        let mut root = i;
+        while parents[root] != root {
+            root = parents[root];
+        }
+        let mut cur = i;
+        while parents[cur] != root {
+            let next = parents[cur];
+            parents[cur] = root;
+            cur = next;
+        }
+        root
+    }
+
+    fn group_by_label(labels: &[usize]) -> Vec<Vec<usize>> {
+        let mut clusters = HashMap::<usize, Vec<usize>>::new();
+        for (i, label) in labels.iter().enumerate() {
+            clusters.entry(*label).or_default().push(i);
+        }
+        let mut ret_val: Vec<Vec<usize>> = clusters.into_values().collect();
+        ret_val.sort();
+        ret_val
+    }
+
+    /// Groups records `0..record_count` into entities by transitively following matched pairs. Unmatched records
+    /// come back as singleton clusters. Clusters are sorted by their smallest member. Pairs naming a record outside
+    /// `0..record_count` are ignored.
+    pub fn connected_components(record_count: usize, matched_pairs: &HashSet<CandidatePair>) -> Vec<Vec<usize>> {
+        let mut parents: Vec<usize> = (0..record_count).collect();
+        for (i, j) in matched_pairs.iter().filter(|(i, j)| *i < record_count && *j < record_count) {
+            let root_i = find(&mut parents, *i);
+            let root_j = find(&mut parents, *j);
+            if root_i != root_j {
+                parents[root_i.max(root_j)] = root_i.min(root_j);
+            }
+        }
+        let labels: Vec<usize> = (0..record_count).map(|i| find(&mut parents, i)).collect();
+        group_by_label(&labels)
+    }
+
+    const MAX_LOCAL_SEARCH_PASSES: usize = 50;
+
+    /// Correlation clustering, for breaking up the long chains transitive closure produces ("A matches B, B matches
+    /// C" although A and C clearly differ). Positive pair weights pull records together, negative weights push them
+    /// apart, and unscored pairs are neutral; a natural choice is a pair's match weight minus the match threshold.
+    /// Clusters are seeded with the pivot algorithm, strongest records first, then refined by moving single records
+    /// between clusters while that increases the total within-cluster weight. As with `connected_components`,
+    /// pairs naming a record outside `0..record_count` are ignored.
+    pub fn correlation_clustering(record_count: usize, weighted_pairs: &HashMap<CandidatePair, f64>) -> Vec<Vec<usize>> {
+        let mut neighbours: Vec<Vec<(usize, f64)>> = vec![Vec::new(); record_count];
+        for ((i, j), w) in weighted_pairs {
+            if i != j && *i < record_count && *j < record_count {
+                neighbours[*i].push((*j, *w));
+                neighbours[*j].push((*i, *w));
+            }
+        }
+        for n in neighbours.iter_mut() {
+            n.sort_by_key(|a| a.0);
+        }
+        let strength = |i: usize| neighbours[i].iter().filter(|(_, w)| *w > 0.0).map(|(_, w)| w).sum::<f64>();
+        let mut order: Vec<usize> = (0..record_count).collect();
+        order.sort_by(|a, b| strength(*b).partial_cmp(&strength(*a)).unwrap_or(std::cmp::Ordering::Equal).then(a.cmp(b)));
+
+        const UNASSIGNED: usize = usize::MAX;
+        let mut labels = vec![UNASSIGNED; record_count];
+        for pivot in order {
+            if labels[pivot] != UNASSIGNED {
+                continue;
+            }
+            labels[pivot] = pivot;
+            for (other, w) in &neighbours[pivot] {
+                if *w > 0.0 && labels[*other] == UNASSIGNED {
+                    labels[*other] = pivot;
+                }
+            }
+        }
+
+        for _ in 0..MAX_LOCAL_SEARCH_PASSES {
+            let mut improved = false;
+            for v in 0..record_count {
+                let mut weight_to_cluster = HashMap::<usize, f64>::new();
+                for (other, w) in &neighbours[v] {
+                    *weight_to_cluster.entry(labels[*other]).or_insert(0.0) += w;
+                }
+                let current = weight_to_cluster.get(&labels[v]).cloned().unwrap_or(0.0);
+                // Moving into a cluster of its own is always an option, worth 0
+                let mut best_label = labels[v];
+                let mut best_gain = 0.0 - current;
+                let mut candidates: Vec<(&usize, &f64)> = weight_to_cluster.iter().collect();
+                candidates.sort_by_key(|a| *a.0);
+                for (label, w) in candidates {
+                    if *label != labels[v] && w - current > best_gain {
+                        best_gain = w - current;
+                        best_label = *label;
+                    }
+                }
+                if best_gain > 1e-12 {
+                    let alone = best_label == labels[v];
+                    labels[v] = if alone { record_count + v } else { best_label };
+                    improved = true;
+                }
+            }
+            if !improved {
+                break;
+            }
+        }
+        group_by_label(&labels)
+    }
+
+    /// One input record to survivorship, together with where it came from and when it was last updated.
+    #[derive(Debug, Clone)]
+    pub struct SourceRecord {
+        pub source_id: Arc<String>,
+        pub last_updated: OptionDate,
+        pub human: Arc<Human>,
+    }
+
+    impl SourceRecord {
+        pub fn new(source_id: Arc<String>, last_updated: OptionDate, human: Arc<Human>) -> Self {
+            let ret_val = SourceRecord { source_id: source_id, last_updated: last_updated, human: human };
+            ret_val
+        }
+    }
+
+    /// How to pick the surviving value of a field from the records in a cluster. Records without a value for the
+    /// field are never picked.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum SurvivorshipRule {
+        /// The value from the most recently updated record.
+        MostRecent,
+        /// The value with the most information (most name components, most known date parts, most filled-in
+        /// address lines, longest text), with ties going to the most recently updated record.
+        MostComplete,
+        /// The value from the first record in cluster order.
+        FirstNonNull,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct SurvivorshipRules {
+        pub name: SurvivorshipRule,
+        pub ssn: SurvivorshipRule,
+        pub birth_date: SurvivorshipRule,
+        pub addresses: SurvivorshipRule,
+        pub phone_numbers: SurvivorshipRule,
+        pub email_addresses: SurvivorshipRule,
+    }
+
+    impl SurvivorshipRules {
+        /// Most complete name, most complete birth date, and the most recent TIN and contact details.
+        pub fn new() -> Self {
+            let ret_val = SurvivorshipRules {
+                name: SurvivorshipRule::MostComplete,
+                ssn: SurvivorshipRule::MostRecent,
+                birth_date: SurvivorshipRule::MostComplete,
+                addresses: SurvivorshipRule::MostRecent,
+                phone_numbers: SurvivorshipRule::MostRecent,
+                email_addresses: SurvivorshipRule::MostRecent,
+            };
+            ret_val
+        }
+    }
+
+    impl Default for SurvivorshipRules {
+        fn default() -> Self {
+            SurvivorshipRules::new()
+        }
+    }
+
+    /// A field of the golden record, for provenance. Contact details are tracked per purpose and employers per
+    /// organization, so two employers sharing a name keep separate provenance.
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub enum GoldenField {
+        Name,
+        Ssn,
+        BirthDate,
+        Address(AddressType),
+        PhoneNumber(PhoneNumberType),
+        EmailAddress(EmailAddressType),
+        Employer(Arc<Organization>),
+    }
+
+    /// The surviving `Human` built from a cluster, and the `source_id` each of its fields was taken from.
+    #[derive(Debug)]
+    pub struct GoldenRecord {
+        pub human: Human,
+        pub provenance: HashMap<GoldenField, Arc<String>>,
+        pub source_ids: Vec<Arc<String>>,
+    }
+
+    fn name_completeness(name: &HumanName) -> (usize, usize) {
+        let components = name.get_components();
+        (components.iter().filter(|(_, c)| c.chars().count() > 1).count(), components.iter().map(|(_, c)| c.chars().count()).sum())
+    }
+
+    fn date_completeness(date: &OptionDate) -> (usize, usize) {
+        ([date.get_year().is_some(), date.get_month().is_some(), date.get_day().is_some()].iter().filter(|b| **b).count(), 0)
+    }
+
+    fn address_completeness(address: &Address) -> (usize, usize) {
+        let parts = [address.get_line_1(), address.get_line_2(), address.get_line_3(), address.get_city(), address.get_state_or_province(), address.get_zip_or_postal_code(), address.get_country()];
+        (parts.iter().filter(|p| !p.is_empty()).count(), parts.iter().map(|p| p.chars().count()).sum())
+    }
+
+    fn text_completeness(s: &str) -> (usize, usize) {
+        (s.chars().count(), 0)
+    }
+
+    /// Applies `rule` to the candidate values, each paired with the index of the record it came from.
+    fn select<'a, T, F>(rule: SurvivorshipRule, sources: &[&SourceRecord], candidates: Vec<(usize, &'a T)>, completeness: F) -> Option<(usize, &'a T)>
+        where F: Fn(&T) -> (usize, usize) {
+        match rule {
+            SurvivorshipRule::FirstNonNull => candidates.into_iter().next(),
+            SurvivorshipRule::MostRecent   => {
+                // max_by_key keeps the last maximum; reverse so earlier records win ties
+                candidates.into_iter().rev().max_by_key(|(i, _)| sources[*i].last_updated.clone())
+            },
+            SurvivorshipRule::MostComplete => {
+                candidates.into_iter().rev().max_by_key(|(i, v)| (completeness(v), sources[*i].last_updated.clone()))
+            },
+        }
+    }
+
+    /// Builds golden records out of clusters of source records, according to a set of survivorship rules.
+    #[derive(Debug, Clone)]
+    pub struct Survivorship {
+        rules: SurvivorshipRules,
+    }
+
+    impl Survivorship {
+        pub fn new(rules: SurvivorshipRules) -> Self {
+            let ret_val = Survivorship { rules: rules };
+            ret_val
+        }
+
+        pub fn get_rules(&self) -> &SurvivorshipRules {
+            &self.rules
+        }
+
+        /// One golden record per cluster; clusters hold indexes into `sources`, e.g. as returned by
+        /// `connected_components` or `correlation_clustering`. Empty clusters are skipped.
+        pub fn build_golden_records(&self, sources: &[SourceRecord], clusters: &[Vec<usize>]) -> Vec<GoldenRecord> {
+            clusters.iter()
+                .filter(|c| !c.is_empty())
+                .map(|c| self.build_golden_record(&c.iter().map(|i| &sources[*i]).collect::<Vec<&SourceRecord>>()))
+                .collect()
+        }
+
+        /// Panics if `cluster` is empty.
+        pub fn build_golden_record(&self, cluster: &[&SourceRecord]) -> GoldenRecord {
+            assert!(!cluster.is_empty(), "cannot build a golden record from an empty cluster");
+            let mut provenance = HashMap::new();
+
+            let names = cluster.iter().enumerate().map(|(i, s)| (i, s.human.get_name())).filter(|(_, n)| !n.get_components().is_empty()).collect();
+            let (name_source, name) = select(self.rules.name, cluster, names, name_completeness).unwrap_or((0, cluster[0].human.get_name()));
+            provenance.insert(GoldenField::Name, Arc::clone(&cluster[name_source].source_id));
+
+            let ssns = cluster.iter().enumerate().map(|(i, s)| (i, s.human.get_ssn())).filter(|(_, t)| !t.as_unencrypted_string().trim().is_empty()).collect();
+            let (ssn_source, ssn) = select(self.rules.ssn, cluster, ssns, |t: &TIN| text_completeness(&t.as_unencrypted_string())).unwrap_or((0, cluster[0].human.get_ssn()));
+            provenance.insert(GoldenField::Ssn, Arc::clone(&cluster[ssn_source].source_id));
+
+            let birth_dates = cluster.iter().enumerate().map(|(i, s)| (i, s.human.get_birth_date())).filter(|(_, d)| d.get_year().is_some()).collect();
+            let (birth_date_source, birth_date) = select(self.rules.birth_date, cluster, birth_dates, date_completeness).unwrap_or((0, cluster[0].human.get_birth_date()));
+            provenance.insert(GoldenField::BirthDate, Arc::clone(&cluster[birth_date_source].source_id));
+
+            let mut addresses = HashMap::<AddressType, Address>::new();
+            let address_types: HashSet<&AddressType> = cluster.iter().flat_map(|s| s.human.get_addresses().keys()).collect();
+            for t in address_types {
+                let candidates = cluster.iter().enumerate().filter_map(|(i, s)| s.human.get_addresses().get(t).map(|a| (i, a))).collect();
+                if let Some((i, a)) = select(self.rules.addresses, cluster, candidates, address_completeness) {
+                    addresses.insert(t.clone(), a.clone());
+                    provenance.insert(GoldenField::Address(t.clone()), Arc::clone(&cluster[i].source_id));
+                }
+            }
+
+            let mut phone_numbers = HashMap::<PhoneNumberType, PhoneNumber>::new();
+            let phone_types: HashSet<&PhoneNumberType> = cluster.iter().flat_map(|s| s.human.get_phone_numbers().keys()).collect();
+            for t in phone_types {
+                let candidates = cluster.iter().enumerate().filter_map(|(i, s)| s.human.get_phone_numbers().get(t).filter(|p| !p.trim().is_empty()).map(|p| (i, p))).collect();
+                if let Some((i, p)) = select(self.rules.phone_numbers, cluster, candidates, |p: &PhoneNumber| text_completeness(p)) {
+                    phone_numbers.insert(t.clone(), p.clone());
+                    provenance.insert(GoldenField::PhoneNumber(t.clone()), Arc::clone(&cluster[i].source_id));
+                }
+            }
+
+            let mut email_addresses = HashMap::<EmailAddressType, EmailAddress>::new();
+            let email_types: HashSet<&EmailAddressType> = cluster.iter().flat_map(|s| s.human.get_email_addresses().keys()).collect();
+            for t in email_types {
+                let candidates = cluster.iter().enumerate().filter_map(|(i, s)| s.human.get_email_addresses().get(t).filter(|e| !e.trim().is_empty()).map(|e| (i, e))).collect();
+                if let Some((i, e)) = select(self.rules.email_addresses, cluster, candidates, |e: &EmailAddress| text_completeness(e)) {
+                    email_addresses.insert(t.clone(), e.clone());
+                    provenance.insert(GoldenField::EmailAddress(t.clone()), Arc::clone(&cluster[i].source_id));
+                }
+            }
+
+            // Employment is not exclusive, so every employer any source knows about survives
+            let mut employers = HashSet::<Arc<Organization>>::new();
+            for s in cluster {
+                for e in s.human.get_employers() {
+                    if employers.insert(Arc::clone(e)) {
+                        provenance.insert(GoldenField::Employer(Arc::clone(e)), Arc::clone(&s.source_id));
+                    }
+                }
+            }
+
+            let human = Human::new(name.clone(), ssn.clone(), birth_date.clone(), addresses, phone_numbers, email_addresses, employers);
+            let source_ids = cluster.iter().map(|s| Arc::clone(&s.source_id)).collect();
+            GoldenRecord { human: human, provenance: provenance, source_ids: source_ids }
+        }
+    }
+
+    /// Convenience for the common case: all pairs in `matched_pairs` are taken as matches, records are grouped by
+    /// transitive closure, and each group is merged with `rules`.
+    pub fn deduplicate(sources: &[SourceRecord], matched_pairs: &HashSet<CandidatePair>, rules: SurvivorshipRules) -> Vec<GoldenRecord> {
+        let normalized: HashSet<CandidatePair> = matched_pairs.iter().map(|(i, j)| candidate_pair(*i, *j)).collect();
+        let clusters = connected_components(sources.len(), &normalized);
+        Survivorship::new(rules).build_golden_records(sources, &clusters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashSet, HashMap};
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use super::address_comparison::*;
+    use super::blocking::*;
+    use super::bktree_storage::*;
+    use super::csv_import::*;
+    use super::date_comparison::*;
+    use super::deduplication::*;
+    use super::entity::*;
+    use super::export::*;
+    use super::fuzzy_index::*;
+    use super::fuzzy_matching::*;
+    use super::human::*;
+    use super::human_index::*;
+    use super::name_comparison::*;
+    use super::name_variants::*;
+    use super::normalization::*;
     use super::option_date_time::*;
     use super::organization::*;
+    use super::phonetic::*;
+    use super::record_linkage::*;
+
+    #[test]
+    fn test_osa_algorithm_name() {
+        let osa = OsaEditDistanceCalculator::new();
+        assert_eq!(osa.algorithm_name(), "Optimal String Alignment");
+    }
+
+    #[test]
+    fn test_osa_get_edit_distance_1() {
+        let osa = OsaEditDistanceCalculator::new();
+        assert_eq!(osa.get_edit_distance(&String::from("blah"), &String::from("bleh")), 1);
+    }
+
+    #[test]
+    fn test_osa_equal_strings() {
+        let osa = OsaEditDistanceCalculator::new();
+        let s = String::from("The quick brown fox jumps over the lazy dog");
+        assert_eq!(osa.get_edit_distance(&s, &s), 0);
+    }
+
+    #[test]
+    fn test_levenshtein_algorithm_name() {
+        let levenshtein = LevenshteinEditDistanceCalculator::new();
+        assert_eq!(levenshtein.algorithm_name(), "Levenshtein");
+    }
+
+    #[test]
+    fn test_levenshtein_get_edit_distance_1() {
+        let levenshtein = LevenshteinEditDistanceCalculator::new();
+        assert_eq!(levenshtein.get_edit_distance(&String::from("blah"), &String::from("bleh")), 1);
+    }
+
+    #[test]
+    fn test_levenshtein_equal_strings() {
+        let levenshtein = LevenshteinEditDistanceCalculator::new();
+        let s = String::from("The quick brown fox jumps over the lazy dog");
+        assert_eq!(levenshtein.get_edit_distance(&s, &s), 0);
+    }
+
+    #[test]
+    fn test_creating_human_name() {
+        let name = HumanName::from_str("Jane Doe").unwrap();
+        println!("Name: {}", name);
+    }
+
+    #[test]
+    fn test_creating_ssn() {
+        let ssn = SSN::from_str("123-45-6789").unwrap();
+        println!("SSN: {}", ssn);
+    }
+
+    #[test]
+    fn test_creating_option_date() {
+        let option_date = OptionDate::from_str("1970-01-01").unwrap();
+        println!("Date: {}", option_date);
+    }
+
+    #[test]
+    fn test_creating_address() {
+        let address = Address::from_str("123 Main St, Anytown, NJ 01234, United States").unwrap();
+        println!("Address: {}", address);
+    }
+
+    #[test]
+    fn test_creating_phone_number() {
+        let phone_number = "+1 (888) 555-1212".to_string();
+        println!("Phone number: {}", phone_number);
+    }
+
+    #[test]
+    fn test_creating_email_address() {
+        let email_address = "jane.doe@example.com".to_string();
+        println!("Email address: {}", email_address);
+    }
+
+    #[test]
+    fn test_creating_organization() {
+        let name = Arc::new("ACME Widgets Inc.".to_string());
+        let tin_number = TIN::from_str("987-65-4321").unwrap();
+        let mut addresses = HashMap::<AddressType, Address>::new();
+        let work_address = Address::from_str("567 Main St, Anytown, NJ 01234, United States").unwrap();
+        addresses.insert("Main".to_string(), work_address);
+        let mut phone_numbers = HashMap::<PhoneNumberType, PhoneNumber>::new();
+        let work_phone = "555-6767".to_string();
+        phone_numbers.insert("Main".to_string(), work_phone);
+        let mut email_addresses = HashMap::<EmailAddressType, EmailAddress>::new();
+        let work_email = "hr@acmewidgets.com".to_string();
+        email_addresses.insert("Main".to_string(), work_email);
+        let organization = Organization::new(name, tin_number, addresses, phone_numbers, email_addresses);
+        println!("Organization: {:?}", organization);
+    }
+
+    #[test]
+    fn test_creating_human_record() {
+        let name = HumanName::from_str("John Smith").unwrap();
+        let ssn = SSN::from_str("578-90-1234").unwrap();
+        let birth_date = OptionDate::from_str("1980-01-01").unwrap();
+        let mut addresses = HashMap::<AddressType, Address>::new();
+        let home_address = Address::from_str("123 Main St, Anytown, NJ 01234, United States").unwrap();
+        addresses.insert("Home".to_string(), home_address);
+        let work_address = Address::from_str("567 Main St, Anytown, NJ 01234, United States").unwrap();
+        addresses.insert("Work".to_string(), work_address);
+        let mut phone_numbers = HashMap::<PhoneNumberType, PhoneNumber>::new();
+        let home_phone = "555-1212".to_string();
+        phone_numbers.insert("Home".to_string(), home_phone);
+        let work_phone = "555-6767".to_string();
+        phone_numbers.insert("Work".to_string(), work_phone);
+        let mut email_addresses = HashMap::<EmailAddressType, EmailAddress>::new();
+        let work_email = "john.smith@acmewidgets.com".to_string();
+        email_addresses.insert("Work".to_string(), work_email);
+        let employers = HashSet::<Arc<Organization>>::new();
+        let human = Human::new(name, ssn, birth_date, addresses, phone_numbers, email_addresses, employers);
+        println!("Human: {:?}", human);
+    }
+
+    fn str_str_tuple(s: &str) -> (Arc<String>, Arc<String>) {
+        let value_found = Arc::new(s.to_string());
+        let record_found_in = Arc::clone(&value_found);
+        (value_found, record_found_in)
+    }
+
+    #[test]
+    fn test_creating_bktree_of_string_and_levenshtein() {
+        let ( first_str, first_record ) = str_str_tuple("bla");
+        let additional_strs = [ "blah", "bleh", "blih", "bloh", "bluh", "bloop", ];
+        let calc = Arc::new(LevenshteinEditDistanceCalculator::new());
+        let mut bktree = BKTree::<String, LevenshteinEditDistanceCalculator>::new(first_str, first_record, Arc::clone(&calc), 1);
+        for s in &additional_strs {
+            let ( this_str, this_record ) = str_str_tuple(s);
+            bktree.insert(this_str, this_record);
+        }
+        println!("{:?}", bktree);
+    }
+
+    fn get_value_and_record_for_human_name(name: HumanName) -> (Arc<String>, Arc<HumanName>) {
+        let value_found = Arc::new(name.to_string());
+        let record_found_in = Arc::new(name);
+        (value_found, record_found_in)
+    }
+
+    #[test]
+    fn test_creating_bktree_of_human_name_and_osa() {
+        let (first_value, first_record) = get_value_and_record_for_human_name(HumanName::from_str("Jane Doe").unwrap());
+        let addl_names = [ "John Doe", "Jon Doe", "Jane Fonda", "Joe Bloe", "Adam Smith" ];
+        let calc = Arc::new(OsaEditDistanceCalculator::new());
+        let mut bktree = BKTree::new(first_value, first_record, Arc::clone(&calc), 2);
+        for n in &addl_names {
+            let (this_value, this_record) = get_value_and_record_for_human_name(HumanName::from_str(n).unwrap());
+            bktree.insert(this_value, this_record);
+        }
+        println!("{:?}", bktree);
+    }
+
+    fn get_value_and_record_for_human_by_name(human: Arc<Human>) -> (Arc<String>, Arc<Human>) {
+        let value_found = Arc::new(human.get_name().to_string());
+        let record_found_in = Arc::clone(&human);
+        (value_found, record_found_in)
+    }
+
+    #[test]
+    fn test_bktree_of_human_by_name_and_levenshtein() {
+        let persons_name = HumanName::from_str("John Smith").unwrap();
+        let ssn = SSN::from_str("578-90-1234").unwrap();
+        let birth_date = OptionDate::from_str("1980-01-01").unwrap();
+        let mut persons_addresses = HashMap::<AddressType, Address>::new();
+        let home_address = Address::from_str("123 Main St, Anytown, NJ 01234, United States").unwrap();
+        persons_addresses.insert("Home".to_string(), home_address);
+        let work_address = Address::from_str("567 Main St, Anytown, NJ 01234, United States").unwrap();
+        persons_addresses.insert("Work".to_string(), work_address);
+        let mut persons_phone_numbers = HashMap::<PhoneNumberType, PhoneNumber>::new();
+        let home_phone = "555-1212".to_string();
+        persons_phone_numbers.insert("Home".to_string(), home_phone);
+        let work_phone = "555-6767".to_string();
+        persons_phone_numbers.insert("Work".to_string(), work_phone);
+        let mut persons_email_addresses = HashMap::<EmailAddressType, EmailAddress>::new();
+        let work_email = "john.smith@acmewidgets.com".to_string();
+        persons_email_addresses.insert("Work".to_string(), work_email);
+        let mut employers = HashSet::<Arc<Organization>>::new();
+
+        let employers_name = Arc::new("ACME Widgets Inc.".to_string());
+        let tin_number = TIN::from_str("987-65-4321").unwrap();
+        let mut employers_addresses = HashMap::<AddressType, Address>::new();
+        let employers_main_address = Address::from_str("567 Main St, Anytown, NJ 01234, United States").unwrap();
+        employers_addresses.insert("Main".to_string(), employers_main_address);
+        let mut employers_phone_numbers = HashMap::<PhoneNumberType, PhoneNumber>::new();
+        let employers_main_phone = "555-6767".to_string();
+        employers_phone_numbers.insert("Main".to_string(), employers_main_phone);
+        let mut employers_email_addresses = HashMap::<EmailAddressType, EmailAddress>::new();
+        let employers_hr_email = "hr@acmewidgets.com".to_string();
+        employers_email_addresses.insert("HR".to_string(), employers_hr_email);
+        let organization = Organization::new(employers_name, tin_number, employers_addresses, employers_phone_numbers, employers_email_addresses);
+        employers.insert(Arc::new(organization));
+
+        let human = Human::new(persons_name, ssn, birth_date, persons_addresses, persons_phone_numbers, persons_email_addresses, employers);
+        let (first_value, first_record) = get_value_and_record_for_human_by_name(Arc::new(human));
+        let calc = Arc::new(LevenshteinEditDistanceCalculator::new());
+        let mut bktree = BKTree::new(first_value, first_record, Arc::clone(&calc), 2);
+
+        let second_persons_name = HumanName::from_str("Jane Doe").unwrap();
+        let second_persons_ssn = SSN::from_str("678-90-1234").unwrap();
+        let second_persons_birth_date = OptionDate::from_str("1980-01-02").unwrap();
+        let second_persons_addresses = HashMap::<AddressType, Address>::new();
+        let second_persons_phones = HashMap::<PhoneNumberType, PhoneNumber>::new();
+        let second_persons_emails = HashMap::<EmailAddressType, EmailAddress>::new();
+        let second_persons_employers = HashSet::<Arc<Organization>>::new();
+
+        let human2 = Human::new(second_persons_name, second_persons_ssn, second_persons_birth_date, second_persons_addresses, second_persons_phones, second_persons_emails, second_persons_employers);
+        let (second_value, second_record) = get_value_and_record_for_human_by_name(Arc::new(human2));
+        bktree.insert(second_value, second_record);
+    }
+
+    fn human_csv_mapping() -> CsvColumnMapping<HumanField> {
+        let mut mapping = CsvColumnMapping::<HumanField>::new();
+        mapping.map_column("Patient Name", HumanField::Name);
+        mapping.map_column("SSN", HumanField::Ssn);
+        mapping.map_column("DOB", HumanField::BirthDate);
+        mapping.map_column("Home Address", HumanField::Address("Home".to_string()));
+        mapping.map_column("Cell", HumanField::PhoneNumber("Mobile".to_string()));
+        mapping
+    }
+
+    #[test]
+    fn test_csv_import_humans() {
+        let data = "patient name,ssn,dob,home address,cell,ignored\n\
+                    Jane Doe,123-45-6789,1970-01-01,\"123 Main St, Anytown, NJ 01234, United States\",555-1212,x\n\
+                    John Smith,578-90-1234,1980-01-01,,,y\n";
+        let importer = HumanCsvImporter::new(human_csv_mapping());
+        let mut rejects = Vec::<u8>::new();
+        let mut humans = Vec::new();
+        let summary = importer.import(data.as_bytes(), &mut rejects, |h| humans.push(h)).unwrap();
+        assert_eq!(summary, CsvImportSummary { rows_read: 2, rows_imported: 2, rows_rejected: 0 });
+        assert_eq!(humans[0].get_name().to_string(), "Jane Doe");
+        assert_eq!(humans[0].get_addresses().len(), 1);
+        assert_eq!(humans[0].get_phone_numbers()["Mobile"], "555-1212");
+        assert!(humans[1].get_addresses().is_empty());
+        assert_eq!(String::from_utf8(rejects).unwrap(), "row,column,field,error\n");
+    }
+
+    #[test]
+    fn test_csv_import_reject_file() {
+        let data = "Patient Name,SSN,DOB,Home Address,Cell\n\
+                    Jane Doe,123-45-6789,01/01/1970,Nowhere,555-1212\n\
+                    ,578-90-1234,1980-01-01,,\n\
+                    John Smith,578-90-1234,1980-01-01,,\n";
+        let importer = HumanCsvImporter::new(human_csv_mapping());
+        let mut rejects = Vec::<u8>::new();
+        let mut humans = Vec::new();
+        let summary = importer.import(data.as_bytes(), &mut rejects, |h| humans.push(h)).unwrap();
+        assert_eq!(summary, CsvImportSummary { rows_read: 3, rows_imported: 1, rows_rejected: 2 });
+        assert_eq!(humans.len(), 1);
+        assert_eq!(String::from_utf8(rejects).unwrap(), "row,column,field,error\n\
+                                                         2,DOB,birth_date,invalid date\n\
+                                                         2,Home Address,address[Home],invalid address\n\
+                                                         3,Patient Name,name,missing value\n");
+    }
+
+    #[test]
+    fn test_csv_import_malformed_rows() {
+        let mut data = b"Patient Name,SSN,DOB,Home Address,Cell\n\
+                         Jane Doe,123-45-6789,1970-01-01,,555-1212,extra\n\
+                         John Smith,578-90-1234\n".to_vec();
+        data.extend_from_slice(b"\xff\xfe,578-90-1234,1980-01-01,,\n");
+        data.extend_from_slice(b"John Smith,578-90-1234,1980-01-01,,\n");
+        let importer = HumanCsvImporter::new(human_csv_mapping());
+        let mut rejects = Vec::<u8>::new();
+        let mut humans = Vec::new();
+        let summary = importer.import(data.as_slice(), &mut rejects, |h| humans.push(h)).unwrap();
+        assert_eq!(summary, CsvImportSummary { rows_read: 4, rows_imported: 1, rows_rejected: 3 });
+        assert_eq!(humans[0].get_name().to_string(), "John Smith");
+        assert_eq!(String::from_utf8(rejects).unwrap(), "row,column,field,error\n\
+                                                         2,,,\"expected 5 fields, found 6\"\n\
+                                                         3,,,\"expected 5 fields, found 2\"\n\
+                                                         4,Patient Name,name,invalid UTF-8\n");
+    }
+
+    #[test]
+    fn test_csv_import_missing_column() {
+        let data = "Patient Name,SSN\nJane Doe,123-45-6789\n";
+        let importer = HumanCsvImporter::new(human_csv_mapping());
+        match importer.records(data.as_bytes()) {
+            Err(CsvImportErr::MissingColumn(header)) => assert_eq!(header, "DOB"),
+            _ => panic!("expected a missing column error"),
+        }
+        let mut mapping = CsvColumnMapping::<HumanField>::new();
+        mapping.map_column("Patient Name", HumanField::Name);
+        let importer = HumanCsvImporter::new(mapping);
+        assert!(matches!(importer.records(data.as_bytes()), Err(CsvImportErr::UnmappedField(_))));
+    }
+
+    #[test]
+    fn test_csv_import_organizations() {
+        let data = "Company;EIN;Main Phone\nACME Widgets Inc.;987-65-4321;555-6767\n;12-3456789;\n";
+        let mut mapping = CsvColumnMapping::<OrganizationField>::new();
+        mapping.map_column("Company", OrganizationField::Name);
+        mapping.map_column("EIN", OrganizationField::TinNumber);
+        mapping.map_column("Main Phone", OrganizationField::PhoneNumber("Main".to_string()));
+        let importer = OrganizationCsvImporter::with_delimiter(mapping, b';');
+        let outcomes: Vec<_> = importer.records(data.as_bytes()).unwrap().map(|r| r.unwrap()).collect();
+        match &outcomes[0] {
+            CsvRowOutcome::Imported(org) => assert_eq!(org.get_name().as_str(), "ACME Widgets Inc."),
+            _ => panic!("expected the first row to import"),
+        }
+        match &outcomes[1] {
+            CsvRowOutcome::Rejected(rejection) => assert_eq!(rejection.errors[0].field, OrganizationField::Name),
+            _ => panic!("expected the second row to be rejected"),
+        }
+    }
+
+    fn human_for_export(name: &str, ssn: &str, birth_date: &str, home_address: &str, employer: Option<&str>) -> Human {
+        let mut addresses = HashMap::<AddressType, Address>::new();
+        addresses.insert("Home".to_string(), Address::from_str(home_address).unwrap());
+        let mut phone_numbers = HashMap::<PhoneNumberType, PhoneNumber>::new();
+        phone_numbers.insert("Home".to_string(), "555-1212".to_string());
+        let mut employers = HashSet::<Arc<Organization>>::new();
+        if let Some(e) = employer {
+            employers.insert(Arc::new(Organization::new(Arc::new(e.to_string()), TIN::from_str("987-65-4321").unwrap(), HashMap::new(), HashMap::new(), HashMap::new())));
+        }
+        Human::new(HumanName::from_str(name).unwrap(), SSN::from_str(ssn).unwrap(), OptionDate::from_str(birth_date).unwrap(), addresses, phone_numbers, HashMap::new(), employers)
+    }
+
+    #[test]
+    fn test_human_name_components() {
+        let name = HumanName::from_str("Mary Anne Jane Smith").unwrap();
+        assert_eq!(name.get_components()[0], (NameComponentRole::Given, Arc::new("Mary".to_string())));
+        assert_eq!(name.get_components_with_role(NameComponentRole::Middle), vec![Arc::new("Anne".to_string()), Arc::new("Jane".to_string())]);
+        assert_eq!(name.get_components_with_role(NameComponentRole::Family), vec![Arc::new("Smith".to_string())]);
+    }
+
+    #[test]
+    fn test_csv_export_redaction_profiles() {
+        let human = human_for_export("Jane Q Doe", "123-45-6789", "1970-01-31", "123 Main St, Anytown, NJ 01234, United States", Some("ACME Widgets Inc."));
+
+        let full_layout = HumanExportLayout::from_records(RedactionProfile::Full, vec![&human]);
+        let mut exporter = HumanCsvExporter::new(full_layout, Vec::<u8>::new()).unwrap();
+        exporter.write_human(&human).unwrap();
+        let output = String::from_utf8(exporter.finish().unwrap()).unwrap();
+        assert_eq!(output, "name_given,name_middle,name_family,name_maiden,name_mothers_maiden,name_fathers,name_grandfathers,ssn,birth_date,address_home,phone_home,employer_names\n\
+                            Jane,Q,Doe,,,,,123-45-6789,1970-01-31,\"123 Main St, Anytown, NJ, 01234, United States\",555-1212,ACME Widgets Inc.\n");
+
+        let masked_layout = HumanExportLayout::from_records(RedactionProfile::MaskedTin, vec![&human]);
+        assert!(masked_layout.flatten(&human).contains(&Some("XXX-XX-6789".to_string())));
+
+        let deidentified_layout = HumanExportLayout::from_records(RedactionProfile::DeIdentified, vec![&human]);
+        let mut exporter = HumanCsvExporter::new(deidentified_layout, Vec::<u8>::new()).unwrap();
+        exporter.write_human(&human).unwrap();
+        let output = String::from_utf8(exporter.finish().unwrap()).unwrap();
+        assert_eq!(output, "birth_year,address_home_state,address_home_zip3\n1970,NJ,012\n");
+    }
+
+    #[test]
+    fn test_export_masks_short_tins_and_applies_safe_harbor() {
+        let short_tin = human_for_export("Jane Doe", "5678", "1970-01-31", "123 Main St, Anytown, NJ 01234, United States", None);
+        assert_eq!(short_tin.get_ssn().to_string(), "XXX-XX-XXXX");
+        let masked_layout = HumanExportLayout::from_records(RedactionProfile::MaskedTin, vec![&short_tin]);
+        let row = masked_layout.flatten(&short_tin);
+        assert!(row.contains(&Some("XXX-XX-XXXX".to_string())));
+        assert!(!row.iter().flatten().any(|v| v.contains("5678")));
+
+        let humans = vec![
+            human_for_export("Jane Doe", "123-45-6789", "1936-12-31", "1 Elm St, Smallville, NH 03601, United States", None),
+            human_for_export("John Doe", "123-45-6789", "1937-01-01", "1 Elm St, Montreal, QC 12345, Canada", None),
+        ];
+        let layout = HumanExportLayout::from_records(RedactionProfile::DeIdentified, &humans).with_reference_year(2026);
+        assert_eq!(layout.get_reference_year(), 2026);
+        assert_eq!(layout.flatten(&humans[0]), vec![Some("1936 or earlier".to_string()), Some("NH".to_string()), Some("000".to_string())]);
+        assert_eq!(layout.flatten(&humans[1]), vec![Some("1937".to_string()), Some("QC".to_string()), Some("123".to_string())]);
+        assert!(current_year() >= 2024);
+    }
+
+    #[test]
+    fn test_parquet_export() {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+        use parquet::record::RowAccessor;
+
+        let humans = vec![
+            human_for_export("Jane Doe", "123-45-6789", "1970-01-31", "123 Main St, Anytown, NJ 01234, United States", None),
+            human_for_export("John Smith", "578-90-1234", "1980-01-01", "567 Main St, Anytown, NJ 01234, United States", Some("ACME Widgets Inc.")),
+            human_for_export("Adam Smith", "678-90-1234", "1723-06-16", "1 High St, Kirkcaldy, FI 12345, Scotland", None),
+        ];
+        let layout = HumanExportLayout::from_records(RedactionProfile::MaskedTin, &humans);
+        let column_names = layout.get_column_names();
+        let mut exporter = HumanParquetExporter::with_row_group_size(layout, Vec::<u8>::new(), 2).unwrap();
+        for h in &humans {
+            exporter.write_human(h).unwrap();
+        }
+        let bytes = exporter.finish().unwrap();
+
+        let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+        let metadata = reader.metadata();
+        assert_eq!(metadata.num_row_groups(), 2);
+        assert_eq!(metadata.file_metadata().num_rows(), 3);
+        let schema_columns: Vec<String> = metadata.file_metadata().schema_descr().columns().iter().map(|c| c.name().to_string()).collect();
+        assert_eq!(schema_columns, column_names);
+        let ssn_index = column_names.iter().position(|c| c == "ssn").unwrap();
+        let ssns: Vec<String> = reader.get_row_iter(None).unwrap().map(|r| r.unwrap().get_string(ssn_index).unwrap().clone()).collect();
+        assert_eq!(ssns, vec!["XXX-XX-6789", "XXX-XX-1234", "XXX-XX-1234"]);
+    }
+
+    fn next_pseudo_random(state: &mut u64) -> f64 {
+        *state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (*state >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    #[test]
+    fn test_fellegi_sunter_em_recovers_parameters() {
+        let fields = [LinkageField::Name, LinkageField::BirthDate, LinkageField::Tin, LinkageField::Address];
+        let true_m = [0.95, 0.9, 0.98, 0.8];
+        let true_u = [0.05, 0.02, 0.001, 0.1];
+        let mut state = 42u64;
+        let mut vectors = Vec::new();
+        for i in 0..5000 {
+            let is_match = i % 5 == 0;
+            let comparisons = fields.iter().enumerate().map(|(k, f)| {
+                let p = if is_match { true_m[k] } else { true_u[k] };
+                (*f, if next_pseudo_random(&mut state) < p { FieldComparison::Agree } else { FieldComparison::Disagree })
+            }).collect();
+            vectors.push(ComparisonVector::new(comparisons));
+        }
+        let mut model = FellegiSunterModel::new(&fields);
+        let estimate = model.estimate_parameters(&vectors, 500, 1e-8);
+        assert!(estimate.converged);
+        assert!((model.get_match_proportion() - 0.2).abs() < 0.02);
+        for (k, f) in fields.iter().enumerate() {
+            let params = model.get_parameters(*f).unwrap();
+            assert!((params.m - true_m[k]).abs() < 0.03, "m for {} was {}", f, params.m);
+            assert!((params.u - true_u[k]).abs() < 0.03, "u for {} was {}", f, params.u);
+        }
+    }
+
+    #[test]
+    fn test_fellegi_sunter_match_weights_and_classes() {
+        let mut parameters = HashMap::new();
+        parameters.insert(LinkageField::Name, FieldParameters::new(0.9, 0.1));
+        parameters.insert(LinkageField::Tin, FieldParameters::new(0.99, 0.01));
+        let model = FellegiSunterModel::with_parameters(parameters, 0.1);
+        let thresholds = LinkageThresholds::new(0.0, 8.0).unwrap();
+        assert_eq!((thresholds.get_lower(), thresholds.get_upper()), (0.0, 8.0));
+        assert_eq!(LinkageThresholds::new(8.0, 0.0), Err(LinkageThresholdsErr));
+        assert_eq!(LinkageThresholds::new(f64::NAN, 8.0), Err(LinkageThresholdsErr));
+
+        let all_agree = ComparisonVector::new(vec![(LinkageField::Name, FieldComparison::Agree), (LinkageField::Tin, FieldComparison::Agree)]);
+        let weight = model.match_weight(&all_agree);
+        assert!((weight.total - (9f64.log2() + 99f64.log2())).abs() < 1e-9);
+        assert_eq!(thresholds.classify(weight.total), LinkageClass::Match);
+
+        let name_only = ComparisonVector::new(vec![(LinkageField::Name, FieldComparison::Agree), (LinkageField::Tin, FieldComparison::Missing)]);
+        assert_eq!(thresholds.classify(model.match_weight(&name_only).total), LinkageClass::PossibleMatch);
+
+        let all_disagree = ComparisonVector::new(vec![(LinkageField::Name, FieldComparison::Disagree), (LinkageField::Tin, FieldComparison::Disagree)]);
+        assert_eq!(thresholds.classify(model.match_weight(&all_disagree).total), LinkageClass::NonMatch);
+        assert!((model.match_probability(&all_agree) - 0.99).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_record_comparator() {
+        let jane = human_for_export("Jane Doe", "123-45-6789", "1970-01-31", "123 Main St, Anytown, NJ 01234, United States", None);
+        let jayne = human_for_export("Jayne Doe", "123456789", "1970-01-31", "123 Main Street, Anytown, NJ 01234, United States", None);
+        let john = human_for_export("John Smith", "578-90-1234", "1980-01-01", "567 Elm St, Othertown, NY 12345, United States", None);
+        let comparator = RecordComparator::new();
+        let v = comparator.compare(&jane, &jayne);
+        assert_eq!(v.get(LinkageField::Name), FieldComparison::Agree);
+        assert_eq!(v.get(LinkageField::Tin), FieldComparison::Agree);
+        assert_eq!(v.get(LinkageField::BirthDate), FieldComparison::Agree);
+        assert_eq!(v.get(LinkageField::Address), FieldComparison::Agree);
+        assert_eq!(v.get(LinkageField::PhoneNumber), FieldComparison::Agree);
+        assert_eq!(v.get(LinkageField::EmailAddress), FieldComparison::Missing);
+        let v = comparator.compare(&jane, &john);
+        assert_eq!(v.get(LinkageField::Name), FieldComparison::Disagree);
+        assert_eq!(v.get(LinkageField::Tin), FieldComparison::Disagree);
+
+        let linker = RecordLinker::new(comparator, FellegiSunterModel::new(&ALL_LINKAGE_FIELDS), LinkageThresholds::new(0.0, 10.0).unwrap());
+        assert_eq!(linker.link(&jane, &jayne).class, LinkageClass::Match);
+        assert_eq!(linker.link(&jane, &john).class, LinkageClass::NonMatch);
+    }
+
+    #[test]
+    fn test_record_comparator_partial_birth_dates() {
+        let with_birth_date = |human: &Human, birth_date: OptionDate| {
+            Human::new(human.get_name().clone(), human.get_ssn().clone(), birth_date, human.get_addresses().clone(), human.get_phone_numbers().clone(), human.get_email_addresses().clone(), HashSet::new())
+        };
+        let jane = human_for_export("Jane Doe", "123-45-6789", "1980-01-03", "123 Main St, Anytown, NJ 01234, United States", None);
+        let jane_1980 = with_birth_date(&jane, OptionDate::new(Some(1980), None, None).unwrap());
+        let jane_1980_01 = with_birth_date(&jane, OptionDate::new(Some(1980), Some(1), None).unwrap());
+        let jane_1981 = with_birth_date(&jane, OptionDate::new(Some(1981), None, None).unwrap());
+        let comparator = RecordComparator::new();
+        assert_eq!(comparator.compare_field(LinkageField::BirthDate, &jane, &jane_1980), FieldComparison::Missing);
+        assert_eq!(comparator.compare_field(LinkageField::BirthDate, &jane, &jane_1980_01), FieldComparison::Agree);
+        assert_eq!(comparator.compare_field(LinkageField::BirthDate, &jane, &jane_1981), FieldComparison::Disagree);
+        assert_eq!(comparator.compare_field(LinkageField::BirthDate, &jane_1980, &jane_1981), FieldComparison::Disagree);
+
+        // A year-only date that agrees leaves the match to the other fields instead of counting against it
+        let model = FellegiSunterModel::new(&ALL_LINKAGE_FIELDS);
+        let full = comparator.compare(&jane, &jane);
+        let partial = comparator.compare(&jane, &jane_1980);
+        let birth_date_agreement = model.get_parameters(LinkageField::BirthDate).unwrap().agreement_weight();
+        assert!((model.match_weight(&partial).total - (model.match_weight(&full).total - birth_date_agreement)).abs() < 1e-9);
+        let linker = RecordLinker::new(comparator, model, LinkageThresholds::new(0.0, 10.0).unwrap());
+        assert_eq!(linker.link(&jane, &jane_1980).class, LinkageClass::Match);
+    }
+
+    #[test]
+    fn test_soundex() {
+        assert_eq!(soundex("Robert"), "R163");
+        assert_eq!(soundex("Rupert"), "R163");
+        assert_eq!(soundex("Ashcraft"), "A261");
+        assert_eq!(soundex("Tymczak"), "T522");
+        assert_eq!(soundex("Pfister"), "P236");
+        assert_eq!(soundex("Lee"), "L000");
+        assert_eq!(soundex("Smith"), soundex("Smyth"));
+        assert_eq!(soundex(""), "");
+    }
+
+    #[test]
+    fn test_nysiis() {
+        assert_eq!(nysiis("Knight"), "NAGT");
+        assert_eq!(nysiis("Smith"), "SNAT");
+        assert_eq!(nysiis("MacIntosh"), "MCANT");
+        assert_eq!(nysiis("Schmidt"), "SNAD");
+        assert_eq!(nysiis("Catherine"), nysiis("Katherine"));
+        assert_eq!(nysiis("123"), "");
+    }
+
+    fn humans_for_blocking() -> Vec<Arc<Human>> {
+        vec![
+            Arc::new(human_for_export("Jane Smith", "111-11-1111", "1970-01-31", "123 Main St, Anytown, NJ 01234, United States", None)),
+            Arc::new(human_for_export("Jane Smyth", "111-11-1111", "1970-01-31", "123 Main St, Anytown, NJ 01234, United States", None)),
+            Arc::new(human_for_export("John Doe", "222-22-2222", "1980-05-05", "9 Elm St, Othertown, NY 12345, United States", None)),
+            Arc::new(human_for_export("Jon Doe", "222-22-2222", "1980-05-05", "9 Elm St, Othertown, NY 12399, United States", None)),
+            Arc::new(human_for_export("Adam Jones", "333-33-3333", "1990-07-07", "1 High St, Kirkcaldy, FI 99999, Scotland", None)),
+            Arc::new(human_for_export("Mary Johnson", "444-44-4444", "1970-02-02", "5 Oak St, Anytown, NJ 01299, United States", None)),
+        ]
+    }
 
     #[test]
-    fn test_osa_algorithm_name() {
-        let osa = OsaEditDistanceCalculator::new();
-        assert_eq!(osa.algorithm_name(), "Optimal String Alignment");
+    fn test_standard_blocking() {
+        let records = humans_for_blocking();
+        let true_matches: HashSet<CandidatePair> = [(0, 1), (2, 3)].iter().cloned().collect();
+
+        let by_soundex = StandardBlocker::new(vec![Box::new(LastNameSoundexKey::new())]);
+        let pairs = by_soundex.candidate_pairs(&records);
+        assert_eq!(pairs, true_matches);
+        let evaluation = evaluate_blocking(&pairs, records.len(), &true_matches);
+        assert_eq!(evaluation.total_pairs, 15);
+        assert!((evaluation.reduction_ratio - 13.0 / 15.0).abs() < 1e-9);
+        assert_eq!(evaluation.pair_completeness, 1.0);
+
+        let by_birth_year = StandardBlocker::new(vec![Box::new(BirthYearKey::new())]);
+        let pairs = by_birth_year.candidate_pairs(&records);
+        assert!(pairs.contains(&(0, 5)));
+        assert_eq!(evaluate_blocking(&pairs, records.len(), &true_matches).pair_completeness, 1.0);
+
+        let by_zip_and_nysiis = StandardBlocker::new(vec![Box::new(CompositeKey::new(vec![Box::new(ZipPrefixKey::new(3)), Box::new(LastNameNysiisKey::new())]))]);
+        let pairs = by_zip_and_nysiis.candidate_pairs(&records);
+        assert_eq!(pairs, [(2, 3)].iter().cloned().collect());
+        assert_eq!(evaluate_blocking(&pairs, records.len(), &true_matches).pair_completeness, 0.5);
     }
 
     #[test]
-    fn test_osa_get_edit_distance_1() {
-        let osa = OsaEditDistanceCalculator::new();
-        assert_eq!(osa.get_edit_distance(&String::from("blah"), &String::from("bleh")), 1);
+    fn test_sorted_neighbourhood_blocking() {
+        let records = humans_for_blocking();
+        let blocker = SortedNeighbourhoodBlocker::new(Box::new(LastNameKey::new()), 2);
+        let pairs = blocker.candidate_pairs(&records);
+        // Sorted: doe, doe, johnson, jones, smith, smyth
+        assert_eq!(pairs, [(2, 3), (3, 5), (4, 5), (0, 4), (0, 1)].iter().cloned().collect());
     }
 
     #[test]
-    fn test_osa_equal_strings() {
-        let osa = OsaEditDistanceCalculator::new();
-        let s = String::from("The quick brown fox jumps over the lazy dog");
-        assert_eq!(osa.get_edit_distance(&s, &s), 0);
+    fn test_canopy_blocking() {
+        let records = humans_for_blocking();
+        let blocker = CanopyBlocker::new(Box::new(FullNameKey::new()), Arc::new(JaroWinklerSimilarityCalculator::new()), 0.85, 0.95).unwrap();
+        assert!(CanopyBlocker::new(Box::new(FullNameKey::new()), Arc::new(JaroWinklerSimilarityCalculator::new()), 0.95, 0.85).is_err());
+        let pairs = blocker.candidate_pairs(&records);
+        assert!(pairs.contains(&(0, 1)));
+        assert!(pairs.contains(&(2, 3)));
+        assert!(!pairs.contains(&(0, 4)));
     }
 
     #[test]
-    fn test_levenshtein_algorithm_name() {
-        let levenshtein = LevenshteinEditDistanceCalculator::new();
-        assert_eq!(levenshtein.algorithm_name(), "Levenshtein");
+    fn test_connected_components() {
+        let pairs: HashSet<CandidatePair> = [(0, 1), (1, 2), (4, 5)].iter().cloned().collect();
+        assert_eq!(connected_components(7, &pairs), vec![vec![0, 1, 2], vec![3], vec![4, 5], vec![6]]);
+        let mut out_of_range = pairs.clone();
+        out_of_range.insert((6, 9));
+        assert_eq!(connected_components(7, &out_of_range), vec![vec![0, 1, 2], vec![3], vec![4, 5], vec![6]]);
     }
 
     #[test]
-    fn test_levenshtein_get_edit_distance_1() {
-        let levenshtein = LevenshteinEditDistanceCalculator::new();
-        assert_eq!(levenshtein.get_edit_distance(&String::from("blah"), &String::from("bleh")), 1);
+    fn test_correlation_clustering_breaks_chains() {
+        let mut weights = HashMap::<CandidatePair, f64>::new();
+        // 0-1-2 and 3-4-5 are tight groups, joined by a weak link 2-3 that transitive closure would follow
+        weights.insert((0, 1), 10.0);
+        weights.insert((0, 2), 8.0);
+        weights.insert((1, 2), 9.0);
+        weights.insert((3, 4), 10.0);
+        weights.insert((3, 5), 8.0);
+        weights.insert((4, 5), 9.0);
+        weights.insert((2, 3), 1.0);
+        weights.insert((0, 4), -12.0);
+        weights.insert((1, 3), -12.0);
+        let matched: HashSet<CandidatePair> = weights.iter().filter(|(_, w)| **w > 0.0).map(|(p, _)| *p).collect();
+        assert_eq!(connected_components(6, &matched), vec![vec![0, 1, 2, 3, 4, 5]]);
+        assert_eq!(correlation_clustering(6, &weights), vec![vec![0, 1, 2], vec![3, 4, 5]]);
     }
 
     #[test]
-    fn test_levenshtein_equal_strings() {
-        let levenshtein = LevenshteinEditDistanceCalculator::new();
+    fn test_golden_record_survivorship() {
+        let mut old_addresses = HashMap::<AddressType, Address>::new();
+        old_addresses.insert("Home".to_string(), Address::from_str("1 Old Rd, Anytown, NJ 01234, United States").unwrap());
+        let mut new_addresses = HashMap::<AddressType, Address>::new();
+        new_addresses.insert("Home".to_string(), Address::from_str("2 New Rd, Anytown, NJ 01234, United States").unwrap());
+        let mut emails = HashMap::<EmailAddressType, EmailAddress>::new();
+        emails.insert("Work".to_string(), "jane.doe@example.com".to_string());
+        let sources = vec![
+            SourceRecord::new(Arc::new("clinic".to_string()), OptionDate::from_str("2019-03-01").unwrap(),
+                Arc::new(Human::new(HumanName::from_str("Jane Q Doe").unwrap(), SSN::from_str("").unwrap(), OptionDate::from_str("1970-01-31").unwrap(), old_addresses, HashMap::new(), emails, HashSet::new()))),
+            SourceRecord::new(Arc::new("billing".to_string()), OptionDate::from_str("2021-06-15").unwrap(),
+                Arc::new(Human::new(HumanName::from_str("Jane Doe").unwrap(), SSN::from_str("123-45-6789").unwrap(), OptionDate::from_str("1970-01-31").unwrap(), new_addresses, HashMap::new(), HashMap::new(), HashSet::new()))),
+            SourceRecord::new(Arc::new("lab".to_string()), OptionDate::from_str("2020-01-01").unwrap(),
+                Arc::new(human_for_export("John Smith", "578-90-1234", "1980-01-01", "567 Main St, Anytown, NJ 01234, United States", Some("ACME Widgets Inc.")))),
+        ];
+        let matched: HashSet<CandidatePair> = [(1, 0)].iter().cloned().collect();
+        let golden = deduplicate(&sources, &matched, SurvivorshipRules::new());
+        assert_eq!(golden.len(), 2);
+
+        let jane = &golden[0];
+        assert_eq!(jane.human.get_name().to_string(), "Jane Q Doe");
+        assert_eq!(jane.human.get_ssn().as_unencrypted_string().as_str(), "123-45-6789");
+        assert_eq!(jane.human.get_addresses()["Home"].get_line_1().as_str(), "2 New Rd");
+        assert_eq!(jane.human.get_email_addresses()["Work"], "jane.doe@example.com");
+        assert_eq!(jane.provenance[&GoldenField::Name].as_str(), "clinic");
+        assert_eq!(jane.provenance[&GoldenField::Ssn].as_str(), "billing");
+        assert_eq!(jane.provenance[&GoldenField::Address("Home".to_string())].as_str(), "billing");
+        assert_eq!(jane.provenance[&GoldenField::EmailAddress("Work".to_string())].as_str(), "clinic");
+        assert_eq!(jane.source_ids.len(), 2);
+
+        let john = &golden[1];
+        assert_eq!(john.human.get_employers().len(), 1);
+        let acme = john.human.get_employers().iter().next().unwrap();
+        assert_eq!(john.provenance[&GoldenField::Employer(Arc::clone(acme))].as_str(), "lab");
+
+        // Two different organizations that share a name each keep their own provenance
+        let mut other_acme_employers = HashSet::<Arc<Organization>>::new();
+        other_acme_employers.insert(Arc::new(Organization::new(Arc::new("ACME Widgets Inc.".to_string()), TIN::from_str("12-3456789").unwrap(), HashMap::new(), HashMap::new(), HashMap::new())));
+        let other_acme = SourceRecord::new(Arc::new("payroll".to_string()), OptionDate::from_str("2022-01-01").unwrap(),
+            Arc::new(Human::new(HumanName::from_str("John Smith").unwrap(), SSN::from_str("578-90-1234").unwrap(), OptionDate::from_str("1980-01-01").unwrap(), HashMap::new(), HashMap::new(), HashMap::new(), other_acme_employers)));
+        let john = Survivorship::new(SurvivorshipRules::new()).build_golden_record(&[&sources[2], &other_acme]);
+        assert_eq!(john.human.get_employers().len(), 2);
+        let mut employer_sources: Vec<&str> = john.human.get_employers().iter().map(|e| john.provenance[&GoldenField::Employer(Arc::clone(e))].as_str()).collect();
+        employer_sources.sort();
+        assert_eq!(employer_sources, vec!["lab", "payroll"]);
+
+        let mut rules = SurvivorshipRules::new();
+        rules.addresses = SurvivorshipRule::FirstNonNull;
+        let jane = Survivorship::new(rules).build_golden_record(&[&sources[0], &sources[1]]);
+        assert_eq!(jane.human.get_addresses()["Home"].get_line_1().as_str(), "1 Old Rd");
+    }
+
+    #[test]
+    fn test_string_similarity_calculators() {
+        let jw = JaroWinklerSimilarityCalculator::new();
+        assert_eq!(jw.algorithm_name(), "Jaro-Winkler");
+        assert!((jw.get_similarity(&String::from("martha"), &String::from("marhta")) - 0.9611).abs() < 1e-4);
+        let dice = SorensenDiceSimilarityCalculator::new();
+        assert_eq!(dice.get_similarity(&String::from("french"), &String::from("quebec")), 0.0);
+        assert!((dice.get_similarity(&String::from("night"), &String::from("nacht")) - 0.25).abs() < 1e-9);
+        let lev = NormalizedLevenshteinSimilarityCalculator::new();
+        assert!((lev.get_similarity(&String::from("blah"), &String::from("bleh")) - 0.75).abs() < 1e-9);
         let s = String::from("The quick brown fox jumps over the lazy dog");
-        assert_eq!(levenshtein.get_edit_distance(&s, &s), 0);
+        assert_eq!(jw.get_similarity(&s, &s), 1.0);
+        assert_eq!(dice.get_similarity(&s, &s), 1.0);
+        assert_eq!(lev.get_similarity(&s, &s), 1.0);
     }
 
     #[test]
-    fn test_creating_human_name() {
-        let name = HumanName::from_str("Jane Doe").unwrap();
-        println!("Name: {}", name);
+    fn test_token_similarity_calculators() {
+        let jane_doe = HumanName::from_str("Jane Doe").unwrap().to_string();
+        let doe_jane = String::from("DOE, Jane");
+        let jaccard = TokenJaccardSimilarityCalculator::new();
+        assert_eq!(jaccard.get_similarity(&jane_doe, &doe_jane), 1.0);
+        assert!((jaccard.get_similarity(&jane_doe, &String::from("Jane Q Doe")) - 2.0 / 3.0).abs() < 1e-9);
+        let token_sort = TokenSortRatioSimilarityCalculator::new();
+        assert_eq!(token_sort.get_similarity(&jane_doe, &doe_jane), 1.0);
+        let plain = NormalizedLevenshteinSimilarityCalculator::new();
+        assert!(plain.get_similarity(&jane_doe, &doe_jane) < 0.5);
+
+        let monge_elkan = MongeElkanSimilarityCalculator::new(Arc::new(JaroWinklerSimilarityCalculator::new()));
+        assert_eq!(monge_elkan.get_similarity(&String::from("Jane Doe"), &String::from("Doe Jane Quincy")), 1.0);
+        assert!(monge_elkan.get_similarity(&String::from("Doe Jayne"), &String::from("Jane Doe")) > 0.95);
+        let symmetric = MongeElkanSimilarityCalculator::new_symmetric(Arc::new(JaroWinklerSimilarityCalculator::new()));
+        assert!(symmetric.get_similarity(&String::from("Jane Doe"), &String::from("Doe Jane Quincy")) < 1.0);
+        assert_eq!(symmetric.get_similarity("", ""), 1.0);
+        assert_eq!(symmetric.get_similarity("", &jane_doe), 0.0);
     }
 
     #[test]
-    fn test_creating_ssn() {
-        let ssn = SSN::from_str("123-45-6789").unwrap();
-        println!("SSN: {}", ssn);
+    fn test_refined_soundex_and_metaphone() {
+        assert_eq!(refined_soundex("testing"), "T6036084");
+        assert_eq!(refined_soundex("jumped"), "J408106");
+        assert_eq!(refined_soundex("Smith"), refined_soundex("Smyth"));
+        assert_eq!(metaphone("Smith", DEFAULT_METAPHONE_LENGTH), "SM0");
+        assert_eq!(metaphone("Thompson", DEFAULT_METAPHONE_LENGTH), "0MPS");
+        assert_eq!(metaphone("Knight", DEFAULT_METAPHONE_LENGTH), "NT");
+        assert_eq!(metaphone("Wright", DEFAULT_METAPHONE_LENGTH), "RT");
+        assert_eq!(metaphone("Catherine", DEFAULT_METAPHONE_LENGTH), metaphone("Kathryn", DEFAULT_METAPHONE_LENGTH));
     }
 
     #[test]
-    fn test_creating_option_date() {
-        let option_date = OptionDate::from_str("1970-01-01").unwrap();
-        println!("Date: {}", option_date);
+    fn test_double_metaphone() {
+        let dm = |s: &str| double_metaphone(s, DEFAULT_DOUBLE_METAPHONE_LENGTH);
+        assert_eq!(dm("Smith"), ("SM0".to_string(), "XMT".to_string()));
+        assert_eq!(dm("Schmidt"), ("XMT".to_string(), "SMT".to_string()));
+        assert_eq!(dm("Thompson"), ("TMPS".to_string(), "TMPS".to_string()));
+        assert_eq!(dm("Jose"), ("HS".to_string(), "HS".to_string()));
+        assert_eq!(dm("Catherine"), ("K0RN".to_string(), "KTRN".to_string()));
+        assert_eq!(dm("Kathryn"), ("K0RN".to_string(), "KTRN".to_string()));
+        assert_eq!(dm(""), (String::new(), String::new()));
+        let encoder = DoubleMetaphoneEncoder::new();
+        assert_eq!(encoder.encode("Smith"), vec!["SM0".to_string(), "XMT".to_string()]);
+        assert_eq!(encoder.encode("Thompson"), vec!["TMPS".to_string()]);
     }
 
     #[test]
-    fn test_creating_address() {
-        let address = Address::from_str("123 Main St, Anytown, NJ 01234, United States").unwrap();
-        println!("Address: {}", address);
+    fn test_daitch_mokotoff_and_beider_morse() {
+        assert_eq!(daitch_mokotoff("Moskowitz"), vec!["645740".to_string()]);
+        assert_eq!(daitch_mokotoff("Auerbach"), vec!["097500".to_string(), "097400".to_string()]);
+        assert_eq!(daitch_mokotoff("Peters"), vec!["739400".to_string(), "734000".to_string()]);
+        assert!(daitch_mokotoff("").is_empty());
+        let schwarz = beider_morse("Schwarz");
+        let szwarc = beider_morse("Szwarc");
+        assert!(schwarz.iter().any(|c| szwarc.contains(c)));
+        assert!(!beider_morse("Schwarz").iter().any(|c| beider_morse("Weiss").contains(c)));
     }
 
     #[test]
-    fn test_creating_phone_number() {
-        let phone_number = "+1 (888) 555-1212".to_string();
-        println!("Phone number: {}", phone_number);
+    fn test_phonetic_calculators_and_name_keys() {
+        let soundex_distance = PhoneticEditDistanceCalculator::new(Arc::new(SoundexEncoder::new()), Arc::new(LevenshteinEditDistanceCalculator::new()));
+        assert_eq!(soundex_distance.get_edit_distance(&String::from("Smith"), &String::from("Smyth")), 0);
+        assert_eq!(soundex_distance.get_edit_distance(&String::from("Smith"), &String::from("Jones")), 2);
+        let dm_similarity = PhoneticSimilarityCalculator::new(Arc::new(DoubleMetaphoneEncoder::new()), Arc::new(NormalizedLevenshteinSimilarityCalculator::new()));
+        assert_eq!(dm_similarity.get_similarity(&String::from("Catherine"), &String::from("Kathryn")), 1.0);
+        assert_eq!(dm_similarity.get_similarity(&String::from("Smith"), &String::from("Schmidt")), 1.0);
+
+        let name = HumanName::from_str("Catherine Smyth").unwrap();
+        let keys = name.get_phonetic_keys(&NysiisEncoder::new());
+        assert_eq!(keys, vec![(NameComponentRole::Given, vec![nysiis("Katherine")]), (NameComponentRole::Family, vec![nysiis("Smyth")])]);
     }
 
     #[test]
-    fn test_creating_email_address() {
-        let email_address = "jane.doe@example.com".to_string();
-        println!("Email address: {}", email_address);
+    fn test_name_variant_dictionary() {
+        let dictionary = NameVariantDictionary::with_builtin_variants();
+        assert!(dictionary.are_variants("Bill", "William"));
+        assert!(dictionary.are_variants("peggy", "MARGARET"));
+        assert!(dictionary.are_variants("José", "Jose"));
+        assert!(dictionary.are_variants("Pat", "Patrick"));
+        assert!(dictionary.are_variants("Pat", "Patricia"));
+        assert!(!dictionary.are_variants("Patrick", "Patricia"));
+        assert!(!dictionary.are_variants("Bill", "Robert"));
+        assert!(dictionary.are_variants("Zelda", "zelda"));
+        assert!(dictionary.get_variants("Peggy").contains(&Arc::new(String::from("Margaret"))));
+        assert!(dictionary.get_variants("Xyzzy").is_empty());
+
+        let mut custom = NameVariantDictionary::new();
+        let aliases = "# Site-specific aliases\n\nBongani, Bonga\n  Xolani ,Xoli, \n";
+        assert_eq!(custom.load_aliases(std::io::Cursor::new(aliases)).unwrap(), 2);
+        assert!(custom.are_variants("Xoli", "xolani"));
+        assert!(!custom.are_variants("Bill", "William"));
+        match custom.load_aliases(std::io::Cursor::new("Thabo, Tabo\nSipho\n")) {
+            Err(NameVariantErr::MalformedLine(line)) => assert_eq!(line, 2),
+            other                                    => panic!("expected a malformed line error, got {:?}", other),
+        }
+        assert_eq!(custom.get_group_count(), 2);
     }
 
     #[test]
-    fn test_creating_organization() {
-        let name = Rc::new("ACME Widgets Inc.".to_string());
-        let tin_number = TIN::from_str("987-65-4321").unwrap();
-        let mut addresses = HashMap::<AddressType, Address>::new();
-        let work_address = Address::from_str("567 Main St, Anytown, NJ 01234, United States").unwrap();
-        addresses.insert("Main".to_string(), work_address);
-        let mut phone_numbers = HashMap::<PhoneNumberType, PhoneNumber>::new();
-        let work_phone = "555-6767".to_string();
-        phone_numbers.insert("Main".to_string(), work_phone);
-        let mut email_addresses = HashMap::<EmailAddressType, EmailAddress>::new();
-        let work_email = "hr@acmewidgets.com".to_string();
-        email_addresses.insert("Main".to_string(), work_email);
-        let organization = Organization::new(name, tin_number, addresses, phone_numbers, email_addresses);
-        println!("Organization: {:?}", organization);
+    fn test_name_variant_calculators() {
+        let dictionary = Arc::new(NameVariantDictionary::with_builtin_variants());
+        let similarity = NameVariantSimilarityCalculator::new(dictionary.clone(), Arc::new(JaroWinklerSimilarityCalculator::new()));
+        assert_eq!(similarity.get_similarity(&String::from("Bill"), &String::from("William")), 1.0);
+        assert!(similarity.get_similarity(&String::from("Bill"), &String::from("Bull")) < 1.0);
+        let distance = NameVariantEditDistanceCalculator::new(dictionary, Arc::new(LevenshteinEditDistanceCalculator::new()));
+        assert_eq!(distance.get_edit_distance(&String::from("Peggy"), &String::from("Margaret")), 0);
+        assert_eq!(distance.get_edit_distance(&String::from("Peggy"), &String::from("Peggie")), 2);
     }
 
     #[test]
-    fn test_creating_human_record() {
-        let name = HumanName::from_str("John Smith").unwrap();
-        let ssn = SSN::from_str("578-90-1234").unwrap();
-        let birth_date = OptionDate::from_str("1980-01-01").unwrap();
-        let mut addresses = HashMap::<AddressType, Address>::new();
-        let home_address = Address::from_str("123 Main St, Anytown, NJ 01234, United States").unwrap();
-        addresses.insert("Home".to_string(), home_address);
-        let work_address = Address::from_str("567 Main St, Anytown, NJ 01234, United States").unwrap();
-        addresses.insert("Work".to_string(), work_address);
-        let mut phone_numbers = HashMap::<PhoneNumberType, PhoneNumber>::new();
-        let home_phone = "555-1212".to_string();
-        phone_numbers.insert("Home".to_string(), home_phone);
-        let work_phone = "555-6767".to_string();
-        phone_numbers.insert("Work".to_string(), work_phone);
-        let mut email_addresses = HashMap::<EmailAddressType, EmailAddress>::new();
-        let work_email = "john.smith@acmewidgets.com".to_string();
-        email_addresses.insert("Work".to_string(), work_email);
-        let employers = HashSet::<Rc<Organization>>::new();
-        let human = Human::new(name, ssn, birth_date, addresses, phone_numbers, email_addresses, employers);
-        println!("Human: {:?}", human);
+    fn test_name_variant_query_expansion() {
+        let dictionary = NameVariantDictionary::with_builtin_variants();
+        let queries = dictionary.expand_query("Bill");
+        assert_eq!(queries[0], "Bill");
+        assert!(queries.contains(&String::from("William")));
+        let (first_str, first_record) = str_str_tuple("Wiliam");
+        let mut bktree = BKTree::new(first_str, first_record, Arc::new(LevenshteinEditDistanceCalculator::new()), 1);
+        for s in &["Bull", "Wilma", "Margaret"] {
+            let (this_str, this_record) = str_str_tuple(s);
+            bktree.insert(this_str, this_record);
+        }
+        let mut found: Vec<String> = queries.iter().flat_map(|q| bktree.search(q)).map(|m| m.get_value().to_string()).collect();
+        found.sort();
+        found.dedup();
+        assert_eq!(found, vec![String::from("Bull"), String::from("Wiliam")]);
     }
 
-    fn str_str_tuple(s: &str) -> (Rc<String>, Rc<String>) {
-        let value_found = Rc::new(s.to_string());
-        let record_found_in = Rc::clone(&value_found);
-        (value_found, record_found_in)
+    #[test]
+    fn test_normalization_steps() {
+        assert_eq!(case_fold("STRASSE"), case_fold("Straße"));
+        assert_eq!(strip_diacritics("Łódź Ærøskøbing"), "Lodz AEroskobing");
+        assert_eq!(expand_german_umlauts("Müller"), "Mueller");
+        assert_eq!(expand_german_umlauts("MÜLLER"), "MUELLER");
+        assert_eq!(expand_german_umlauts("Gro\u{df}e"), "Grosse");
+        assert_eq!(cyrillic_to_latin("Чайковский"), "Chaykovskiy");
+        assert_eq!(cyrillic_to_latin("ЩУКИН"), "SHCHUKIN");
+        assert_eq!(greek_to_latin("Αθηνά"), "Athina");
+        assert_eq!(greek_to_latin("ΘΕΟΔΩΡΟΣ"), "THEODOROS");
+        assert_eq!(collapse_whitespace("  Mary \t Ann  "), "Mary Ann");
+        assert_eq!(NormalizationPipeline::new().normalize("Zoe\u{308}"), "Zoe\u{308}");
+        let nfc = NormalizationPipeline::new().with_step(NormalizationStep::Nfc);
+        assert_eq!(nfc.normalize("Zoe\u{308}"), "Zo\u{eb}");
+        assert_eq!(NormalizationPipeline::for_transliterated_matching().normalize("Дмитрий  Шостакович"), "dmitriy shostakovich");
     }
 
     #[test]
-    fn test_creating_bktree_of_string_and_levenshtein() {
-        let ( first_str, first_record ) = str_str_tuple("bla");
-        let additional_strs = [ "blah", "bleh", "blih", "bloh", "bluh", "bloop", ];
-        let calc = Rc::new(LevenshteinEditDistanceCalculator::new());
-        let mut bktree = BKTree::<String, LevenshteinEditDistanceCalculator>::new(first_str, first_record, Rc::clone(&calc), 1);
-        for s in &additional_strs {
-            let ( this_str, this_record ) = str_str_tuple(s);
+    fn test_normalizing_calculators() {
+        let composed = String::from("Zo\u{eb}");
+        let decomposed = String::from("Zoe\u{308}");
+        let levenshtein = Arc::new(LevenshteinEditDistanceCalculator::new());
+        assert_eq!(levenshtein.get_edit_distance(&composed, &decomposed), 2);
+        let nfc = Arc::new(NormalizationPipeline::new().with_step(NormalizationStep::Nfc));
+        let calc = NormalizingEditDistanceCalculator::new(nfc, levenshtein.clone());
+        assert_eq!(calc.get_edit_distance(&composed, &decomposed), 0);
+
+        let mueller = String::from("Mueller");
+        let mueller_umlaut = String::from("Mu\u{308}ller");
+        assert_eq!(levenshtein.get_edit_distance(&mueller, &String::from("Müller")), 2);
+        let german = NormalizingEditDistanceCalculator::new(Arc::new(NormalizationPipeline::for_german_matching()), levenshtein.clone());
+        assert_eq!(german.get_edit_distance(&mueller, &mueller_umlaut), 0);
+        assert_eq!(german.get_edit_distance(&String::from("MÜLLER"), &mueller), 0);
+        let plain = NormalizingEditDistanceCalculator::new(Arc::new(NormalizationPipeline::for_matching()), levenshtein);
+        assert_eq!(plain.get_edit_distance(&String::from("Müller"), &String::from("muller")), 0);
+
+        let similarity = NormalizingSimilarityCalculator::new(Arc::new(NormalizationPipeline::default()), Arc::new(JaroWinklerSimilarityCalculator::new()));
+        assert_eq!(similarity.get_similarity(&String::from("José"), &String::from("JOSE")), 1.0);
+    }
+
+    #[test]
+    fn test_grapheme_edit_distances() {
+        let family = String::from("\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467} Smith");
+        let smiley = String::from("\u{1f642} Smith");
+        assert_eq!(LevenshteinEditDistanceCalculator::new().get_edit_distance(&family, &smiley), 5);
+        assert_eq!(GraphemeLevenshteinEditDistanceCalculator::new().get_edit_distance(&family, &smiley), 1);
+
+        let accent_first = String::from("Rene\u{301}a");
+        let accent_swapped = String::from("Renae\u{301}");
+        assert_eq!(OsaEditDistanceCalculator::new().get_edit_distance(&accent_first, &accent_swapped), 2);
+        assert_eq!(GraphemeOsaEditDistanceCalculator::new().get_edit_distance(&accent_first, &accent_swapped), 1);
+        assert_eq!(GraphemeDamerauLevenshteinEditDistanceCalculator::new().get_edit_distance(&accent_first, &accent_swapped), 1);
+
+        let ca = String::from("ca");
+        let abc = String::from("abc");
+        assert_eq!(GraphemeOsaEditDistanceCalculator::new().get_edit_distance(&ca, &abc), 3);
+        assert_eq!(GraphemeDamerauLevenshteinEditDistanceCalculator::new().get_edit_distance(&ca, &abc), 2);
+        assert_eq!(generic_osa_distance(&[1, 2, 3], &[1, 2, 3]), 0);
+        assert_eq!(graphemes("Zoe\u{308}").len(), 3);
+    }
+
+    #[test]
+    fn test_weighted_edit_distance() {
+        let s = |v: &str| String::from(v);
+        let plain = WeightedEditDistanceCalculator::new(EditCosts::new());
+        assert_eq!(plain.get_edit_distance(&s("Smith"), &s("Smyth")), DEFAULT_EDIT_COST);
+        assert_eq!(plain.get_edit_distance(&s("Jonh"), &s("John")), DEFAULT_EDIT_COST);
+        assert_eq!(plain.get_edit_distance(&s(""), &s("Ann")), 3 * DEFAULT_EDIT_COST);
+
+        let keyboard = WeightedEditDistanceCalculator::new(EditCosts::qwerty_keyboard());
+        assert_eq!(keyboard.get_edit_distance(&s("Smith"), &s("Snith")), DEFAULT_KEYBOARD_ADJACENT_COST);
+        assert_eq!(keyboard.get_edit_distance(&s("Smith"), &s("Sxith")), DEFAULT_EDIT_COST);
+        assert_eq!(keyboard.get_edit_distance(&s("SMITH"), &s("SNITH")), DEFAULT_KEYBOARD_ADJACENT_COST);
+        assert!(qwerty_adjacent_keys().contains(&('g', 'b')));
+        assert!(!qwerty_adjacent_keys().contains(&('g', 'n')));
+
+        let ocr = WeightedEditDistanceCalculator::new(EditCosts::ocr());
+        assert_eq!(ocr.get_edit_distance(&s("rnartin"), &s("martin")), DEFAULT_OCR_CONFUSION_COST);
+        assert_eq!(ocr.get_edit_distance(&s("martin"), &s("rnartin")), DEFAULT_OCR_CONFUSION_COST);
+        assert_eq!(ocr.get_edit_distance(&s("J0HN"), &s("JOHN")), DEFAULT_OCR_CONFUSION_COST);
+        assert_eq!(ocr.get_edit_distance(&s("Wi1liam"), &s("William")), DEFAULT_OCR_CONFUSION_COST);
+
+        let custom = WeightedEditDistanceCalculator::new(EditCosts::uniform(1).with_insertion_cost(2).with_transposition_cost(3).with_substitution("ph", "f", 1));
+        assert_eq!(custom.get_edit_distance(&s("ab"), &s("abc")), 2);
+        assert_eq!(custom.get_edit_distance(&s("abc"), &s("ab")), 1);
+        assert_eq!(custom.get_edit_distance(&s("ab"), &s("ba")), 2);
+        assert_eq!(custom.get_edit_distance(&s("Stephen"), &s("Stefen")), 1);
+    }
+
+    #[test]
+    fn test_bktree_search() {
+        let (first_str, first_record) = str_str_tuple("bla");
+        let calc = Arc::new(LevenshteinEditDistanceCalculator::new());
+        let mut bktree = BKTree::<String, LevenshteinEditDistanceCalculator>::new(first_str, first_record, calc, 1);
+        for s in &["blah", "bleh", "blih", "bloh", "bluh", "bloop", "zzzz"] {
+            let (this_str, this_record) = str_str_tuple(s);
+            assert!(bktree.insert(this_str, this_record));
+        }
+        assert!(!bktree.insert(Arc::new(String::from("blah")), Arc::new(String::from("blah"))));
+        assert!(bktree.insert(Arc::new(String::from("blah")), Arc::new(String::from("second record"))));
+
+        // The query's distance to the root is 0, which must not underflow when the threshold is subtracted
+        let results = bktree.search(&String::from("bla"));
+        let found: Vec<(String, EditDistance)> = results.iter().map(|m| (m.get_value().to_string(), m.get_distance())).collect();
+        assert_eq!(found, vec![(String::from("bla"), 0), (String::from("blah"), 1)]);
+        let mut blah_records: Vec<String> = results[1].get_records_found_in().iter().map(|r| r.to_string()).collect();
+        blah_records.sort();
+        assert_eq!(blah_records, vec![String::from("blah"), String::from("second record")]);
+
+        let results = bktree.search(&String::from("bloh"));
+        let found: Vec<(String, EditDistance)> = results.iter().map(|m| (m.get_value().to_string(), m.get_distance())).collect();
+        assert_eq!(found, vec![(String::from("bloh"), 0), (String::from("blah"), 1), (String::from("bleh"), 1), (String::from("blih"), 1), (String::from("bluh"), 1)]);
+        assert!(bktree.search(&String::from("qqqqqqq")).is_empty());
+    }
+
+    #[test]
+    fn test_bktree_insert_with_pseudo_metric() {
+        let calc = Arc::new(PhoneticEditDistanceCalculator::new(Arc::new(SoundexEncoder::new()), Arc::new(LevenshteinEditDistanceCalculator::new())));
+        let (first_str, first_record) = str_str_tuple("Smith");
+        let mut bktree = BKTree::new(first_str, first_record, calc, 0);
+        let (second_str, second_record) = str_str_tuple("Smyth");
+        assert!(bktree.insert(second_str, second_record));
+        let found: Vec<String> = bktree.search(&String::from("Smithe")).iter().map(|m| m.get_value().to_string()).collect();
+        assert_eq!(found, vec![String::from("Smith"), String::from("Smyth")]);
+        assert_eq!(bktree.best_match(&String::from("Smyth")).map(|m| m.get_value().to_string()), Some(String::from("Smyth")));
+        assert_eq!(bktree.best_match(&String::from("Smith")).map(|m| m.get_value().to_string()), Some(String::from("Smith")));
+    }
+
+    #[test]
+    fn test_bktree_search_within_and_nearest() {
+        let (first_str, first_record) = str_str_tuple("book");
+        let calc = Arc::new(LevenshteinEditDistanceCalculator::new());
+        let mut bktree = BKTree::<String, LevenshteinEditDistanceCalculator>::new(first_str, first_record, calc, 0);
+        for s in &["books", "cake", "boo", "boon", "cook", "cape", "cart", "brook"] {
+            let (this_str, this_record) = str_str_tuple(s);
             bktree.insert(this_str, this_record);
         }
-        println!("{:?}", bktree);
+        let values = |results: Vec<BKTreeMatch<String>>| results.iter().map(|m| (m.get_value().to_string(), m.get_distance())).collect::<Vec<(String, EditDistance)>>();
+        assert!(bktree.search(&String::from("bok")).is_empty());
+        assert_eq!(values(bktree.search_within(&String::from("bok"), 1)), vec![(String::from("boo"), 1), (String::from("book"), 1)]);
+        assert_eq!(bktree.search_within(&String::from("bok"), 2).len(), 6);
+
+        assert_eq!(values(bktree.nearest(&String::from("caker"), 2)), vec![(String::from("cake"), 1), (String::from("cape"), 2)]);
+        assert_eq!(values(bktree.nearest(&String::from("book"), 1)), vec![(String::from("book"), 0)]);
+        assert!(bktree.nearest(&String::from("book"), 0).is_empty());
+        assert_eq!(bktree.nearest(&String::from("zzz"), 100).len(), 9);
+        let mut exhaustive = bktree.search_within(&String::from("carts"), EditDistance::MAX);
+        exhaustive.truncate(3);
+        assert_eq!(values(bktree.nearest(&String::from("carts"), 3)), values(exhaustive));
+
+        assert_eq!(bktree.best_match(&String::from("cook")).map(|m| (m.get_value().to_string(), m.get_distance())), Some((String::from("cook"), 0)));
+        assert!(bktree.best_match(&String::from("cok")).is_none());
     }
 
-    fn get_value_and_record_for_human_name(name: HumanName) -> (Rc<String>, Rc<HumanName>) {
-        let value_found = Rc::new(name.to_string());
-        let record_found_in = Rc::new(name);
-        (value_found, record_found_in)
+    #[test]
+    fn test_bktree_removal_and_compaction() {
+        let (first_str, first_record) = str_str_tuple("book");
+        let calc = Arc::new(LevenshteinEditDistanceCalculator::new());
+        let mut bktree = BKTree::<String, LevenshteinEditDistanceCalculator>::new(first_str, first_record, calc, 1);
+        for s in &["books", "boo", "boon", "cook", "brook"] {
+            let (this_str, this_record) = str_str_tuple(s);
+            bktree.insert(this_str, this_record);
+        }
+        let patient = Arc::new(String::from("patient 7"));
+        bktree.insert(Arc::new(String::from("boon")), Arc::clone(&patient));
+        let values = |results: Vec<BKTreeMatch<String>>| results.iter().map(|m| m.get_value().to_string()).collect::<Vec<String>>();
+
+        assert!(bktree.remove_record(&String::from("boon"), &Arc::new(String::from("boon"))));
+        assert!(!bktree.remove_record(&String::from("boon"), &Arc::new(String::from("boon"))));
+        assert!(!bktree.remove_record(&String::from("nope"), &patient));
+        assert_eq!(bktree.search(&String::from("boon"))[0].get_records_found_in(), &[Arc::clone(&patient)]);
+
+        // Removing the root's value must not hide values stored beneath it
+        assert!(bktree.remove_value(&String::from("book")));
+        assert!(!bktree.remove_value(&String::from("book")));
+        assert_eq!(values(bktree.search(&String::from("book"))), vec![String::from("boo"), String::from("books"), String::from("boon"), String::from("brook"), String::from("cook")]);
+        assert_eq!(bktree.get_tombstone_count(), 1);
+
+        assert!(bktree.rekey_record(&String::from("boon"), Arc::new(String::from("moon")), Arc::clone(&patient)));
+        assert!(!bktree.rekey_record(&String::from("boon"), Arc::new(String::from("moon")), Arc::clone(&patient)));
+        assert_eq!(bktree.get_tombstone_count(), 2);
+        assert_eq!(bktree.best_match(&String::from("moon")).unwrap().get_records_found_in(), &[Arc::clone(&patient)]);
+        bktree.insert(Arc::new(String::from("cook")), Arc::clone(&patient));
+        assert!(!bktree.rekey_record(&String::from("cook"), Arc::new(String::from("moon")), Arc::clone(&patient)));
+        assert_eq!(bktree.best_match(&String::from("cook")).unwrap().get_records_found_in(), &[Arc::new(String::from("cook"))]);
+        assert_eq!(bktree.best_match(&String::from("moon")).unwrap().get_records_found_in(), &[Arc::clone(&patient)]);
+
+        bktree.compact();
+        assert_eq!(bktree.get_tombstone_count(), 0);
+        assert_eq!(values(bktree.search_within(&String::from("book"), 10)), vec![String::from("boo"), String::from("books"), String::from("brook"), String::from("cook"), String::from("moon")]);
+
+        // Re-inserting a deleted value revives it
+        assert!(bktree.remove_value(&String::from("cook")));
+        assert!(bktree.insert(Arc::new(String::from("cook")), Arc::new(String::from("cook"))));
+        assert_eq!(values(bktree.search(&String::from("cook"))), vec![String::from("cook")]);
     }
 
     #[test]
-    fn test_creating_bktree_of_human_name_and_osa() {
-        let (first_value, first_record) = get_value_and_record_for_human_name(HumanName::from_str("Jane Doe").unwrap());
-        let addl_names = [ "John Doe", "Jon Doe", "Jane Fonda", "Joe Bloe", "Adam Smith" ];
-        let calc = Rc::new(OsaEditDistanceCalculator::new());
-        let mut bktree = BKTree::new(first_value, first_record, Rc::clone(&calc), 2);
-        for n in &addl_names {
-            let (this_value, this_record) = get_value_and_record_for_human_name(HumanName::from_str(n).unwrap());
-            bktree.insert(this_value, this_record);
+    fn test_bktree_empty_and_bulk_built() {
+        let calc = Arc::new(LevenshteinEditDistanceCalculator::new());
+        let mut bktree = BKTree::<String, LevenshteinEditDistanceCalculator>::new_empty(Arc::clone(&calc), 1);
+        assert!(bktree.is_empty());
+        assert!(bktree.search(&String::from("anything")).is_empty());
+        assert!(bktree.nearest(&String::from("anything"), 3).is_empty());
+        assert!(bktree.best_match(&String::from("anything")).is_none());
+        assert!(!bktree.remove_value(&String::from("anything")));
+        assert_eq!(bktree.iter().count(), 0);
+
+        bktree.extend(["book", "boo", "cook"].iter().map(|s| str_str_tuple(s)));
+        bktree.insert(Arc::new(String::from("book")), Arc::new(String::from("second record")));
+        assert_eq!(bktree.len(), 4);
+        assert!(bktree.remove_value(&String::from("book")));
+        assert_eq!(bktree.len(), 2);
+        bktree.compact();
+        let mut pairs: Vec<(String, String)> = bktree.iter().map(|(v, r)| (v.to_string(), r.to_string())).collect();
+        pairs.sort();
+        assert_eq!(pairs, vec![(String::from("boo"), String::from("boo")), (String::from("cook"), String::from("cook"))]);
+
+        let words = ["bla", "blah", "bleh", "blih", "bloh", "bluh", "bloop", "book", "books", "boo", "boon", "cook", "cake", "cape", "cart", "brook"];
+        let collected: BKTree<String, LevenshteinEditDistanceCalculator> = words.iter().map(|s| str_str_tuple(s)).collect();
+        assert_eq!(collected.len(), words.len());
+        assert_eq!((&collected).into_iter().count(), words.len());
+        let loaded = BKTree::bulk_load(words.iter().map(|s| str_str_tuple(s)), calc, 2);
+        for query in &["bok", "blooh", "carts", "zzz"] {
+            let query = String::from(*query);
+            let mut expected: Vec<(String, EditDistance)> = words.iter()
+                .map(|w| (w.to_string(), strsim::levenshtein(w, &query)))
+                .filter(|(_, d)| *d <= 2)
+                .collect();
+            expected.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+            let found: Vec<(String, EditDistance)> = loaded.search(&query).iter().map(|m| (m.get_value().to_string(), m.get_distance())).collect();
+            assert_eq!(found, expected);
+            let collected_found: Vec<(String, EditDistance)> = collected.search(&query).iter().map(|m| (m.get_value().to_string(), m.get_distance())).collect();
+            assert_eq!(collected_found, expected);
         }
-        println!("{:?}", bktree);
     }
 
-    fn get_value_and_record_for_human_by_name(human: Rc<Human>) -> (Rc<String>, Rc<Human>) {
-        let value_found = Rc::new(human.get_name().to_string());
-        let record_found_in = Rc::clone(&human);
-        (value_found, record_found_in)
+    #[test]
+    fn test_bktree_storage_round_trip() {
+        let calc = Arc::new(LevenshteinEditDistanceCalculator::new());
+        let words = ["bla", "blah", "bleh", "blih", "bloh", "bluh", "bloop", "book", "books", "boo", "boon", "cook", "cake", "cape", "cart", "brook"];
+        let mut bktree = BKTree::bulk_load(words.iter().enumerate().map(|(i, w)| (Arc::new(w.to_string()), Arc::new(i as u64))), Arc::clone(&calc), 1);
+        bktree.insert(Arc::new(String::from("book")), Arc::new(100));
+        bktree.remove_value(&String::from("cook"));
+
+        let path = std::env::temp_dir().join(format!("hippocratic_bktree_{}.idx", std::process::id()));
+        write_bktree(&bktree, std::io::BufWriter::new(std::fs::File::create(&path).unwrap()), |id| *id).unwrap();
+        let mapped = MappedBKTree::open(&path, Arc::clone(&calc)).unwrap();
+        assert_eq!(mapped.len(), bktree.len());
+        assert_eq!(mapped.get_node_count(), words.len() as u64);
+        assert_eq!(mapped.get_max_distance_to_consider(), 1);
+        for query in &["bok", "book", "cook", "carts", "zzz"] {
+            let query = String::from(*query);
+            for max_distance in 0..3 {
+                let expected: Vec<(String, EditDistance, Vec<u64>)> = bktree.search_within(&query, max_distance).iter()
+                    .map(|m| {
+                        let mut ids: Vec<u64> = m.get_records_found_in().iter().map(|r| **r).collect();
+                        ids.sort();
+                        (m.get_value().to_string(), m.get_distance(), ids)
+                    })
+                    .collect();
+                let found: Vec<(String, EditDistance, Vec<u64>)> = mapped.search_within(&query, max_distance).unwrap().iter()
+                    .map(|m| (m.get_value().to_string(), m.get_distance(), m.get_record_ids().to_vec()))
+                    .collect();
+                assert_eq!(found, expected);
+            }
+        }
+        assert_eq!(mapped.search(&String::from("book")).unwrap()[0].get_record_ids(), &[7, 100]);
+
+        match MappedBKTree::open(&path, Arc::new(OsaEditDistanceCalculator::new())) {
+            Err(BKTreeStorageErr::CalculatorMismatch { found, .. }) => assert_eq!(found, "Levenshtein"),
+            other                                                   => panic!("expected a calculator mismatch, got {:?}", other),
+        }
+        let soundex = Arc::new(PhoneticEditDistanceCalculator::new(Arc::new(SoundexEncoder::new()), Arc::clone(&calc)));
+        let phonetic_tree = BKTree::bulk_load(words.iter().enumerate().map(|(i, w)| (Arc::new(w.to_string()), Arc::new(i as u64))), Arc::clone(&soundex), 1);
+        write_bktree(&phonetic_tree, std::fs::File::create(&path).unwrap(), |id| *id).unwrap();
+        assert!(MappedBKTree::open(&path, soundex).is_ok());
+        let nysiis = Arc::new(PhoneticEditDistanceCalculator::new(Arc::new(NysiisEncoder::new()), Arc::clone(&calc)));
+        assert!(matches!(MappedBKTree::open(&path, nysiis), Err(BKTreeStorageErr::CalculatorMismatch { .. })));
+        std::fs::write(&path, b"not an index").unwrap();
+        assert!(matches!(MappedBKTree::open(&path, Arc::clone(&calc)), Err(BKTreeStorageErr::BadMagic)));
+
+        let empty = BKTree::<u64, LevenshteinEditDistanceCalculator>::new_empty(Arc::clone(&calc), 2);
+        write_bktree(&empty, std::fs::File::create(&path).unwrap(), |id| *id).unwrap();
+        let mapped = MappedBKTree::open(&path, calc).unwrap();
+        assert!(mapped.is_empty());
+        assert!(mapped.search(&String::from("book")).unwrap().is_empty());
+        drop(mapped);
+        std::fs::remove_file(&path).unwrap();
     }
 
     #[test]
-    fn test_bktree_of_human_by_name_and_levenshtein() {
-        let persons_name = HumanName::from_str("John Smith").unwrap();
-        let ssn = SSN::from_str("578-90-1234").unwrap();
-        let birth_date = OptionDate::from_str("1980-01-01").unwrap();
-        let mut persons_addresses = HashMap::<AddressType, Address>::new();
-        let home_address = Address::from_str("123 Main St, Anytown, NJ 01234, United States").unwrap();
-        persons_addresses.insert("Home".to_string(), home_address);
-        let work_address = Address::from_str("567 Main St, Anytown, NJ 01234, United States").unwrap();
-        persons_addresses.insert("Work".to_string(), work_address);
-        let mut persons_phone_numbers = HashMap::<PhoneNumberType, PhoneNumber>::new();
-        let home_phone = "555-1212".to_string();
-        persons_phone_numbers.insert("Home".to_string(), home_phone);
-        let work_phone = "555-6767".to_string();
-        persons_phone_numbers.insert("Work".to_string(), work_phone);
-        let mut persons_email_addresses = HashMap::<EmailAddressType, EmailAddress>::new();
-        let work_email = "john.smith@acmewidgets.com".to_string();
-        persons_email_addresses.insert("Work".to_string(), work_email);
-        let mut employers = HashSet::<Rc<Organization>>::new();
+    fn test_bktree_with_non_string_keys() {
+        let date = |s: &str| Arc::new(CompleteDate::from_option_date(&OptionDate::from_str(s).unwrap()).unwrap());
+        let dates = ["1980-01-01", "1980-01-03", "1980-02-01", "1981-01-01", "2000-06-15"];
+        let date_tree = BKTree::bulk_load(dates.iter().map(|d| (date(d), Arc::new(d.to_string()))), Arc::new(DateDistanceCalculator::new()), 7);
+        let found: Vec<(String, EditDistance)> = date_tree.search(&date("1979-12-30")).iter().map(|m| (m.get_records_found_in()[0].to_string(), m.get_distance())).collect();
+        assert_eq!(found, vec![(String::from("1980-01-01"), 2), (String::from("1980-01-03"), 4)]);
+        assert_eq!(DateDistanceCalculator::new().get_edit_distance(&*date("2000-02-28"), &*date("2000-03-01")), 2);
+        assert_eq!(DateDistanceCalculator::new().get_edit_distance(&*date("1900-02-28"), &*date("1900-03-01")), 1);
+        assert!(OptionDate::from_str("1900-02-28").unwrap().is_complete());
+        assert!(!OptionDate::new(Some(1980), None, None).unwrap().is_complete());
+        assert_eq!(CompleteDate::from_option_date(&OptionDate::new(Some(1980), Some(1), None).unwrap()), None);
+        assert_eq!(date("1980-01-03").get_day(), 3);
 
-        let employers_name = Rc::new("ACME Widgets Inc.".to_string());
-        let tin_number = TIN::from_str("987-65-4321").unwrap();
-        let mut employers_addresses = HashMap::<AddressType, Address>::new();
-        let employers_main_address = Address::from_str("567 Main St, Anytown, NJ 01234, United States").unwrap();
-        employers_addresses.insert("Main".to_string(), employers_main_address);
-        let mut employers_phone_numbers = HashMap::<PhoneNumberType, PhoneNumber>::new();
-        let employers_main_phone = "555-6767".to_string();
-        employers_phone_numbers.insert("Main".to_string(), employers_main_phone);
-        let mut employers_email_addresses = HashMap::<EmailAddressType, EmailAddress>::new();
-        let employers_hr_email = "hr@acmewidgets.com".to_string();
-        employers_email_addresses.insert("HR".to_string(), employers_hr_email);
-        let organization = Organization::new(employers_name, tin_number, employers_addresses, employers_phone_numbers, employers_email_addresses);
-        employers.insert(Rc::new(organization));
+        let mut code_tree = BKTree::<&str, NumericDistanceCalculator, u64>::new_empty(Arc::new(NumericDistanceCalculator::new()), 5);
+        code_tree.extend(vec![(Arc::new(10_001u64), Arc::new("a")), (Arc::new(10_004), Arc::new("b")), (Arc::new(10_020), Arc::new("c"))]);
+        let found: Vec<u64> = code_tree.search(&10_000).iter().map(|m| *m.get_value()).collect();
+        assert_eq!(found, vec![10_001, 10_004]);
+        assert_eq!(NumericDistanceCalculator::new().get_edit_distance(&-3i64, &4i64), 7);
 
-        let human = Human::new(persons_name, ssn, birth_date, persons_addresses, persons_phone_numbers, persons_email_addresses, employers);
-        let (first_value, first_record) = get_value_and_record_for_human_by_name(Rc::new(human));
-        let calc = Rc::new(LevenshteinEditDistanceCalculator::new());
-        let mut bktree = BKTree::new(first_value, first_record, Rc::clone(&calc), 2);
+        let byte_tree: BKTree<u8, ByteLevenshteinEditDistanceCalculator, Vec<u8>> = vec![(Arc::new(b"S530".to_vec()), Arc::new(1)), (Arc::new(b"J520".to_vec()), Arc::new(2))].into_iter().collect();
+        assert_eq!(byte_tree.nearest(&b"S532".to_vec(), 1)[0].get_records_found_in(), &[Arc::new(1)]);
 
-        let second_persons_name = HumanName::from_str("Jane Doe").unwrap();
-        let second_persons_ssn = SSN::from_str("678-90-1234").unwrap();
-        let second_persons_birth_date = OptionDate::from_str("1980-01-02").unwrap();
-        let second_persons_addresses = HashMap::<AddressType, Address>::new();
-        let second_persons_phones = HashMap::<PhoneNumberType, PhoneNumber>::new();
-        let second_persons_emails = HashMap::<EmailAddressType, EmailAddress>::new();
-        let second_persons_employers = HashSet::<Rc<Organization>>::new();
+        let tokens = |s: &str| Arc::new(tokenize(s));
+        let token_tree = BKTree::bulk_load(vec![(tokens("Mary Ann Smith"), Arc::new(1)), (tokens("Smith Mary"), Arc::new(2))], Arc::new(TokenLevenshteinEditDistanceCalculator::new()), 1);
+        assert_eq!(token_tree.search(&tokenize("mary smith")).len(), 1);
+        assert_eq!(HammingEditDistanceCalculator::new().get_edit_distance(&String::from("08540"), &String::from("08450")), 2);
+        assert_eq!(HammingEditDistanceCalculator::new().get_edit_distance(&String::from("08540"), &String::from("085401234")), 4);
+    }
 
-        let human2 = Human::new(second_persons_name, second_persons_ssn, second_persons_birth_date, second_persons_addresses, second_persons_phones, second_persons_emails, second_persons_employers);
-        let (second_value, second_record) = get_value_and_record_for_human_by_name(Rc::new(human2));
-        bktree.insert(second_value, second_record);
+    #[test]
+    fn test_bktree_degenerate_depth_and_stats() {
+        // Under the discrete metric every value is at distance 1 from every other, so each insert descends the
+        // single edge 1 and the tree degenerates into a chain deep enough to overflow a recursive traversal
+        #[derive(Default)]
+        struct DiscreteMetric;
+        impl DistanceAlgorithm for DiscreteMetric {
+            fn algorithm_name(&self) -> &'static str {
+                return "Discrete";
+            }
+        }
+
+        impl EditDistanceCalculator<u64> for DiscreteMetric {
+            fn get_edit_distance(&self, item1: &u64, item2: &u64) -> EditDistance {
+                if item1 == item2 { 0 } else { 1 }
+            }
+        }
+        // Run on a deliberately small stack so that any recursion proportional to the depth would overflow
+        std::thread::Builder::new().stack_size(64 * 1024).spawn(|| {
+            const DEPTH: u64 = 2_000;
+            let mut chain = BKTree::<u64, DiscreteMetric, u64>::new_empty(Arc::new(DiscreteMetric), 0);
+            for i in 0..DEPTH {
+                assert!(chain.insert(Arc::new(i), Arc::new(i)));
+            }
+            assert!(!chain.insert(Arc::new(DEPTH - 1), Arc::new(DEPTH - 1)));
+            let stats = chain.get_stats();
+            assert_eq!(stats.node_count, DEPTH as usize);
+            assert_eq!(stats.depth, DEPTH as usize);
+            assert_eq!(stats.fan_out_histogram.get(&1), Some(&(DEPTH as usize - 1)));
+            assert_eq!(stats.fan_out_histogram.get(&0), Some(&1));
+            assert_eq!(chain.search(&(DEPTH - 1)).len(), 1);
+            assert_eq!(chain.search_within(&DEPTH, 1).len(), DEPTH as usize);
+            assert_eq!(chain.nearest(&(DEPTH - 1), 1)[0].get_records_found_in(), &[Arc::new(DEPTH - 1)]);
+            assert_eq!(*chain.best_match(&(DEPTH / 2)).unwrap().get_value(), DEPTH / 2);
+            assert!(chain.remove_value(&0));
+            assert_eq!(chain.get_tombstone_count(), 1);
+            assert_eq!(chain.len(), DEPTH as usize - 1);
+            drop(chain);
+        }).unwrap().join().unwrap();
+
+        let words = ["book", "books", "boo", "boon", "cook", "cake", "cape", "cart"];
+        let mut bktree = BKTree::<String, LevenshteinEditDistanceCalculator>::new_empty(Arc::new(LevenshteinEditDistanceCalculator::new()), 1);
+        bktree.extend(words.iter().map(|s| str_str_tuple(s)));
+        let stats = bktree.get_stats();
+        assert_eq!(stats.node_count, words.len());
+        assert_eq!(stats.fan_out_histogram.values().sum::<usize>(), words.len());
+        assert_eq!(stats.fan_out_histogram.iter().map(|(fan_out, n)| fan_out * n).sum::<usize>(), words.len() - 1);
+        assert!(stats.depth >= 2 && stats.average_leaf_depth <= stats.depth as f64);
+        assert_eq!(BKTree::<String, LevenshteinEditDistanceCalculator>::new_empty(Arc::new(LevenshteinEditDistanceCalculator::new()), 1).get_stats().depth, 0);
+    }
+
+    #[test]
+    fn test_fuzzy_indexes_agree_with_linear_scan() {
+        let words = ["bla", "blah", "bleh", "blih", "bloh", "bluh", "bloop", "book", "books", "boo", "boon", "cook", "cake", "cape", "cart", "brook", "smith", "smyth", "smithe", "schmidt", "jones", "johns"];
+        let entries = || words.iter().enumerate().map(|(i, w)| (Arc::new(w.to_string()), Arc::new(i)));
+        let calc = Arc::new(OsaEditDistanceCalculator::new());
+        let mut indexes: Vec<Box<dyn FuzzyIndex<usize>>> = vec![
+            Box::new(BKTree::bulk_load(entries(), Arc::clone(&calc), 2)),
+            Box::new(VPTree::bulk_load(entries(), Arc::clone(&calc), 2)),
+            Box::new(SymSpellIndex::bulk_load(entries(), Arc::clone(&calc), 2)),
+        ];
+        let mut incremental = VPTree::new_empty(Arc::clone(&calc), 2);
+        for (value, record_found_in) in entries() {
+            assert!(FuzzyIndex::insert(&mut incremental, value, record_found_in));
+        }
+        indexes.push(Box::new(incremental));
+        for index in indexes.iter_mut() {
+            assert_eq!(index.len(), words.len());
+            assert!(!index.insert(Arc::new(String::from("book")), Arc::new(7)));
+            assert!(index.insert(Arc::new(String::from("book")), Arc::new(100)));
+        }
+        for query in &["bok", "blooh", "carts", "smoth", "jnoes", "zzz", "book"] {
+            let query = String::from(*query);
+            for max_distance in 0..4 {
+                let mut expected: Vec<(String, EditDistance)> = words.iter()
+                    .map(|w| (w.to_string(), strsim::osa_distance(w, &query)))
+                    .filter(|(_, d)| *d <= max_distance)
+                    .collect();
+                expected.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+                for index in &indexes {
+                    let found: Vec<(String, EditDistance)> = index.search_within(&query, max_distance).iter().map(|m| (m.get_value().to_string(), m.get_distance())).collect();
+                    assert_eq!(found, expected, "{} within {} of {}", index.index_name(), max_distance, query);
+                }
+            }
+        }
+        let mut book_records: Vec<usize> = indexes[2].search(&String::from("book"))[0].get_records_found_in().iter().map(|r| **r).collect();
+        book_records.sort();
+        assert_eq!(book_records, vec![7, 100]);
+
+        let vptree = VPTree::bulk_load(entries(), Arc::clone(&calc), 1);
+        let bktree = BKTree::bulk_load(entries(), Arc::clone(&calc), 1);
+        let nearest_distances = |matches: Vec<BKTreeMatch<usize>>| matches.iter().map(|m| m.get_distance()).collect::<Vec<EditDistance>>();
+        assert_eq!(nearest_distances(vptree.nearest(&String::from("brooks"), 4)), nearest_distances(bktree.nearest(&String::from("brooks"), 4)));
+        assert_eq!(vptree.search_within(&String::from("zzzz"), 10).len(), words.len());
+
+        let symspell = SymSpellIndex::bulk_load(entries(), Arc::clone(&calc), 1);
+        assert_eq!(symspell.get_max_indexed_distance(), Some(1));
+        assert_eq!(bktree.get_max_indexed_distance(), None);
+        assert_eq!(symspell.search_within(&String::from("bok"), 5).len(), bktree.search_within(&String::from("bok"), 5).len());
+        assert_eq!(deletion_neighbourhood("abc", 1).len(), 4);
+        assert_eq!(deletion_neighbourhood("aab", 2).len(), 5);
+    }
+
+    #[test]
+    fn test_bktree_parallel_and_batch_search() {
+        let words = ["bla", "blah", "bleh", "blih", "bloh", "bluh", "bloop", "book", "books", "boo", "boon", "cook", "cake", "cape", "cart", "brook", "smith", "smyth", "smithe", "schmidt", "jones", "johns"];
+        let calc = Arc::new(NormalizingEditDistanceCalculator::new(Arc::new(NormalizationPipeline::for_matching()), Arc::new(LevenshteinEditDistanceCalculator::new())));
+        let bktree = Arc::new(BKTree::bulk_load(words.iter().enumerate().map(|(i, w)| (Arc::new(w.to_string()), Arc::new(i as u64))), calc, 2));
+        let queries: Vec<String> = ["bok", "BLOOH", "carts", "Smoth", "jnoes", "zzz", "book"].iter().map(|q| q.to_string()).collect();
+        let summarize = |matches: &[BKTreeMatch<u64>]| matches.iter().map(|m| (m.get_value().to_string(), m.get_distance())).collect::<Vec<(String, EditDistance)>>();
+
+        let batch = bktree.search_batch(&queries);
+        let best = bktree.best_match_batch(&queries);
+        assert_eq!(batch.len(), queries.len());
+        for (i, query) in queries.iter().enumerate() {
+            assert_eq!(summarize(&batch[i]), summarize(&bktree.search(query)));
+            assert_eq!(best[i].as_ref().map(|m| m.get_distance()), bktree.best_match(query).map(|m| m.get_distance()));
+            for max_distance in 0..5 {
+                assert_eq!(summarize(&bktree.par_search_within(query, max_distance)), summarize(&bktree.search_within(query, max_distance)));
+            }
+        }
+        assert_eq!(bktree.search_within_batch(&queries, 0)[6].len(), 1);
+        assert_eq!(bktree.par_search_within(&String::from("zzzz"), 10).len(), words.len());
+
+        let shared = Arc::clone(&bktree);
+        let found = std::thread::spawn(move || shared.search(&String::from("Jones")).len()).join().unwrap();
+        assert_eq!(found, 2);
+    }
+
+    #[test]
+    fn test_bounded_edit_distances() {
+        let long1 = "abcdefghijklmnopqrstuvwxyz".repeat(3);
+        let long2 = long1.replacen("m", "n", 2).replacen("xy", "yx", 1);
+        let words = ["", "a", "ab", "ba", "book", "books", "boko", "cook", "kitten", "sitting", "Zoë", "Zoe", "Müller", "Mueller", "ca", "abc", long1.as_str(), long2.as_str()];
+        let levenshtein_calc = LevenshteinEditDistanceCalculator::new();
+        let osa_calc = OsaEditDistanceCalculator::new();
+        let grapheme_calc = GraphemeOsaEditDistanceCalculator::new();
+        let byte_calc = ByteLevenshteinEditDistanceCalculator::new();
+        let token_calc = TokenLevenshteinEditDistanceCalculator::new();
+        let bounded = |dist: EditDistance, max_distance: EditDistance| if dist <= max_distance { Some(dist) } else { None };
+        for w1 in &words {
+            for w2 in &words {
+                let (s1, s2) = (w1.to_string(), w2.to_string());
+                for max_distance in 0..8 {
+                    assert_eq!(levenshtein_calc.get_bounded_edit_distance(&s1, &s2, max_distance), bounded(strsim::levenshtein(w1, w2), max_distance), "{} / {} within {}", w1, w2, max_distance);
+                    assert_eq!(osa_calc.get_bounded_edit_distance(&s1, &s2, max_distance), bounded(strsim::osa_distance(w1, w2), max_distance), "{} / {} within {}", w1, w2, max_distance);
+                    assert_eq!(grapheme_calc.get_bounded_edit_distance(&s1, &s2, max_distance), bounded(grapheme_calc.get_edit_distance(&s1, &s2), max_distance));
+                    assert_eq!(byte_calc.get_bounded_edit_distance(&s1.as_bytes().to_vec(), &s2.as_bytes().to_vec(), max_distance), bounded(byte_calc.get_edit_distance(&s1.as_bytes().to_vec(), &s2.as_bytes().to_vec()), max_distance));
+                    assert_eq!(token_calc.get_bounded_edit_distance(&tokenize(w1), &tokenize(w2), max_distance), bounded(token_calc.get_edit_distance(&tokenize(w1), &tokenize(w2)), max_distance));
+                }
+            }
+        }
+        let pattern: Vec<char> = long1.chars().take(64).collect();
+        let text: Vec<char> = long2.chars().take(64).collect();
+        assert_eq!(bounded_myers_levenshtein(&pattern, &text, 64), Some(strsim::levenshtein(&long1[..64], &long2[..64])));
+        let default_bounded = HammingEditDistanceCalculator::new();
+        assert_eq!(default_bounded.get_bounded_edit_distance(&String::from("08540"), &String::from("08450"), 1), None);
+        assert_eq!(default_bounded.get_bounded_edit_distance(&String::from("08540"), &String::from("08450"), 2), Some(2));
+    }
+
+    #[test]
+    fn test_caching_edit_distance_calculator() {
+        let calc = CachingEditDistanceCalculator::new(Arc::new(LevenshteinEditDistanceCalculator::new()), 2);
+        assert_eq!(calc.algorithm_name(), "Levenshtein");
+        assert_eq!(calc.get_capacity(), 2);
+        let (kitten, sitting, book) = (String::from("kitten"), String::from("sitting"), String::from("book"));
+        assert_eq!(calc.get_edit_distance(&kitten, &sitting), 3);
+        assert_eq!(calc.get_edit_distance(&kitten, &sitting), 3);
+        assert_eq!((calc.get_hit_count(), calc.get_miss_count()), (1, 1));
+        assert_eq!(calc.get_bounded_edit_distance(&kitten, &sitting, 2), None);
+        assert_eq!(calc.get_bounded_edit_distance(&kitten, &sitting, 3), Some(3));
+        assert_eq!((calc.get_hit_count(), calc.get_miss_count()), (3, 1));
+
+        // A bound that is exceeded caches nothing, and the least recently used pair is evicted first
+        assert_eq!(calc.get_bounded_edit_distance(&kitten, &book, 1), None);
+        assert_eq!(calc.get_edit_distance(&book, &kitten), 6);
+        assert_eq!(calc.get_edit_distance(&book, &sitting), 7);
+        assert_eq!((calc.get_hit_count(), calc.get_miss_count()), (3, 4));
+        calc.get_edit_distance(&kitten, &sitting);
+        assert_eq!(calc.get_miss_count(), 5);
+        calc.clear();
+        calc.get_edit_distance(&book, &sitting);
+        assert_eq!(calc.get_miss_count(), 6);
+
+        let cached = Arc::new(CachingEditDistanceCalculator::new(Arc::new(OsaEditDistanceCalculator::new()), 100));
+        let words = ["book", "books", "boo", "boon", "cook", "cake", "cape", "cart"];
+        let bktree = BKTree::bulk_load(words.iter().map(|w| (Arc::new(w.to_string()), Arc::new(w.to_string()))), Arc::clone(&cached), 1);
+        let first = bktree.search_within_batch(&[String::from("bok"), String::from("bok")], 1);
+        assert_eq!(first[0].len(), first[1].len());
+        assert!(cached.get_hit_count() > 0);
+    }
+
+    #[test]
+    fn test_human_index_multi_field_search() {
+        let with_email = |human: Human, email: &str| {
+            let mut email_addresses = HashMap::<EmailAddressType, EmailAddress>::new();
+            email_addresses.insert("Home".to_string(), email.to_string());
+            Human::new(human.get_name().clone(), human.get_ssn().clone(), human.get_birth_date().clone(), human.get_addresses().clone(), human.get_phone_numbers().clone(), email_addresses, HashSet::new())
+        };
+        let mut index = HumanIndex::new();
+        assert!(index.is_empty());
+        for human in humans_for_blocking() {
+            index.insert(human);
+        }
+        index.insert(Arc::new(with_email(human_for_export("Jane Smith", "555-55-5555", "1985-03-03", "77 Pine St, Elsewhere, CA 90210, United States", None), "Jane.Smith@example.com")));
+        assert_eq!(index.len(), 7);
+
+        let query = HumanQuery::new().with_name("Smith, Jane").with_birth_date(OptionDate::from_str("1970-01-31").unwrap()).with_postal_code("01234");
+        let found = index.search(&query, 10);
+        assert_eq!(found[0].human.get_name().to_string(), "Jane Smith");
+        assert_eq!(found[0].score, 1.0);
+        assert_eq!(found[0].field_scores, vec![(HumanIndexField::Name, 1.0), (HumanIndexField::BirthDate, 1.0), (HumanIndexField::PostalCode, 1.0)]);
+        assert_eq!(found[1].human.get_name().to_string(), "Jane Smyth");
+        assert!(found[1].score < 1.0 && found[1].score > found[2].score);
+        // "Jane" is within two edits of "Jones", so a weak partial-name candidate trails the rest
+        let adam_jones = found.iter().find(|c| c.human.get_name().to_string() == "Adam Jones").unwrap();
+        assert!(adam_jones.field_scores[0].1 < 0.5 && adam_jones.score < found[2].score);
+
+        // Initials and a mistyped ZIP still find the record, ranked on what does match
+        let found = index.search(&HumanQuery::new().with_name("J Doe").with_postal_code("12346"), 2);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].human.get_name().to_string(), "John Doe");
+        assert_eq!(found[0].field_scores, vec![(HumanIndexField::Name, (INITIAL_SIMILARITY + 1.0) / 2.0), (HumanIndexField::PostalCode, 0.8)]);
+        assert_eq!(found[1].human.get_name().to_string(), "Jon Doe");
+        assert_eq!(found[1].field_scores[1], (HumanIndexField::PostalCode, 0.0));
+
+        let found = index.search(&HumanQuery::new().with_email_address(" jane.smith@example.con ").with_postal_code("90210"), 10);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].human.get_birth_date(), &OptionDate::from_str("1985-03-03").unwrap());
+
+        let found = index.search(&HumanQuery::new().with_birth_date(OptionDate::from_str("1980-05-07").unwrap()), 10);
+        assert_eq!(found.len(), 2);
+        assert!((found[0].score - (1.0 - 2.0 / 8.0)).abs() < 1e-9);
+
+        // A year-only date is not a day or two from the start of its year: it agrees with any date in that year,
+        // as a partial match, and is not near the end of the year before
+        let year_only = human_for_export("Yuri Partial", "444-44-4444", "1980-01-01", "1 Birch Rd, Nowhere, NM 87501, United States", None);
+        index.insert(Arc::new(Human::new(year_only.get_name().clone(), year_only.get_ssn().clone(), OptionDate::new(Some(1980), None, None).unwrap(), year_only.get_addresses().clone(), year_only.get_phone_numbers().clone(), HashMap::new(), HashSet::new())));
+        let is_yuri = |c: &HumanCandidate| c.human.get_name().to_string() == "Yuri Partial";
+        let found = index.search(&HumanQuery::new().with_birth_date(OptionDate::from_str("1980-01-03").unwrap()), 10);
+        assert_eq!(found.iter().find(|c| is_yuri(c)).map(|c| c.score), Some(YEAR_SHARE));
+        let found = index.search(&HumanQuery::new().with_birth_date(OptionDate::from_str("1980-12-30").unwrap()), 10);
+        assert_eq!(found.iter().find(|c| is_yuri(c)).map(|c| c.score), Some(YEAR_SHARE));
+        assert!(!index.search(&HumanQuery::new().with_birth_date(OptionDate::from_str("1979-12-31").unwrap()), 10).iter().any(is_yuri));
+        let found = index.search(&HumanQuery::new().with_birth_date(OptionDate::new(Some(1980), None, None).unwrap()), 10);
+        assert!(found.iter().any(is_yuri));
+        assert!(found.iter().all(|c| c.field_scores == vec![(HumanIndexField::BirthDate, YEAR_SHARE)]));
+        assert!(index.search(&HumanQuery::new().with_name("Zebedee"), 10).is_empty());
+        assert!(index.search(&HumanQuery::new(), 10).is_empty());
+        assert_eq!(normalize_postal_code("08540-1234"), "08540");
+        assert_eq!(normalize_postal_code("ky1 1aa"), "KY11AA");
+    }
+
+    #[test]
+    fn test_human_name_comparator() {
+        let comparator = HumanNameComparator::new();
+        let name = |s: &str| HumanName::from_str(s).unwrap();
+        let jane_doe = name("Jane Doe");
+
+        let same = comparator.compare(&jane_doe, &name("JANE DOE"));
+        assert_eq!(same.similarity, 1.0);
+        assert!(!same.swapped);
+        assert!(same.components.iter().all(|c| c.kind == ComponentMatchKind::Exact));
+
+        // Flattened through Display these are far apart; aligned by role they only differ in order
+        let swapped = comparator.compare(&name("Doe Jane"), &jane_doe);
+        assert!(swapped.swapped);
+        assert!((swapped.similarity - SWAPPED_NAME_FACTOR).abs() < 1e-9);
+        assert!(JaroWinklerSimilarityCalculator::new().get_similarity("doe jane", "jane doe") < swapped.similarity);
+
+        // A middle name on one side only is ignored
+        let middle = comparator.compare(&name("Jane Quinn Doe"), &jane_doe);
+        assert_eq!(middle.similarity, 1.0);
+        let middle_detail = middle.components.iter().find(|c| c.group == NameRoleGroup::Middle).unwrap();
+        assert_eq!(middle_detail.kind, ComponentMatchKind::Missing);
+        assert_eq!(middle_detail.weight, 0.0);
+
+        let initial = comparator.compare(&name("J. Smith"), &name("John Smith"));
+        assert_eq!(initial.components[0].kind, ComponentMatchKind::Initial);
+        assert_eq!(initial.components[0].similarity, INITIAL_SIMILARITY);
+        assert!(initial.similarity > 0.9);
+        assert!(comparator.get_similarity(&name("K. Smith"), &name("John Smith")) < 0.6);
+
+        let hyphenated = comparator.compare(&name("Mary Smith-Jones"), &name("Mary Jones"));
+        let family = hyphenated.components.iter().find(|c| c.group == NameRoleGroup::Family).unwrap();
+        assert_eq!(family.kind, ComponentMatchKind::Partial);
+        assert_eq!(family.component1, Some(Arc::new("Smith-Jones".to_string())));
+        assert!((family.similarity - PARTIAL_COMPONENT_FACTOR).abs() < 1e-9);
+        let spaced = HumanName::FirstLastNoMiddle { first_name: Arc::new("Mary".to_string()), last_name: Arc::new("Smith Jones".to_string()) };
+        assert_eq!(comparator.get_similarity(&name("Mary Smith-Jones"), &spaced), 1.0);
+
+        // Maiden names align with family names, and a missing family name is a real disagreement
+        let maiden = HumanName::FirstMiddleMaidenLast { first_name: Arc::new("Jane".to_string()), middle_name: Arc::new("Q".to_string()), maiden_name: Arc::new("Doe".to_string()), last_name: Arc::new("Roe".to_string()) };
+        assert_eq!(comparator.get_similarity(&maiden, &jane_doe), 1.0);
+        assert!(comparator.get_similarity(&name("Jane"), &jane_doe) < 0.5);
+
+        // Patronymics line up with Western-order middle names
+        let patronymic = HumanName::Patronymic2 { given_name: Arc::new("Ivan".to_string()), middle_name: Arc::new("Sergeevich".to_string()), fathers_name: Arc::new("Ivanovich".to_string()) };
+        let detail = comparator.compare(&patronymic, &name("Ivan Ivanovich Petrov"));
+        let patronymic_detail = detail.components.iter().find(|c| c.group == NameRoleGroup::Patronymic).unwrap();
+        assert_eq!(patronymic_detail.kind, ComponentMatchKind::Exact);
+
+        assert!(comparator.get_similarity(&name("Bill Smith"), &name("William Smith")) < 0.9);
+        let with_variants = HumanNameComparator::new().with_variant_dictionary(Arc::new(NameVariantDictionary::with_builtin_variants()));
+        let variant = with_variants.compare(&name("Bill Smith"), &name("William Smith"));
+        assert_eq!(variant.components[0].kind, ComponentMatchKind::Variant);
+        assert_eq!(variant.similarity, 1.0);
+
+        let a = human_for_export("Doe Jane", "123-45-6789", "1970-01-31", "123 Main St, Anytown, NJ 01234, United States", None);
+        let b = human_for_export("Jane Doe", "123-45-6789", "1970-01-31", "123 Main St, Anytown, NJ 01234, United States", None);
+        assert_eq!(RecordComparator::new().compare_field(LinkageField::Name, &a, &b), FieldComparison::Agree);
+    }
+
+    #[test]
+    fn test_birth_date_comparator() {
+        let comparator = BirthDateComparator::new();
+        let date = |s: &str| OptionDate::from_str(s).unwrap();
+        let kind = |a: &OptionDate, b: &OptionDate| comparator.compare(a, b).kind;
+        let born = date("1970-03-04");
+
+        assert_eq!(comparator.compare(&born, &born), DateComparison { similarity: 1.0, kind: DateMatchKind::Exact, compared_share: 1.0 });
+        assert_eq!(kind(&born, &date("1970-04-03")), DateMatchKind::DayMonthTransposed);
+        assert_eq!(kind(&born, &date("1979-03-04")), DateMatchKind::DigitTypo);
+        assert_eq!(kind(&born, &date("1907-03-04")), DateMatchKind::DigitTypo);
+        assert_eq!(kind(&born, &date("1970-03-14")), DateMatchKind::DigitTypo);
+        assert_eq!(kind(&date("1999-03-04"), &date("2009-03-04")), DateMatchKind::YearOffByDecadeOrCentury);
+        assert_eq!(kind(&born, &date("2070-03-04")), DateMatchKind::YearOffByDecadeOrCentury);
+        assert_eq!(kind(&date("1970-02-27"), &date("1970-03-02")), DateMatchKind::Close);
+        assert_eq!(kind(&born, &date("1985-11-22")), DateMatchKind::Different);
+        assert_eq!(comparator.get_similarity(&born, &date("1985-11-22")), 0.0);
+        let similarities: Vec<Similarity> = ["1970-04-03", "1979-03-04", "2070-03-04"].iter().map(|d| comparator.get_similarity(&born, &date(d))).collect();
+        assert!(similarities[0] > similarities[1] && similarities[1] > similarities[2]);
+
+        // Only components known in both dates are compared, and the score reflects how much of the date that was
+        let year_only = OptionDate::new(Some(1970), None, None).unwrap();
+        let partial = comparator.compare(&born, &year_only);
+        assert_eq!(partial.kind, DateMatchKind::PartialAgreement);
+        assert!((partial.similarity - YEAR_SHARE).abs() < 1e-9);
+        let year_and_month = OptionDate::new(Some(1970), Some(3), None).unwrap();
+        assert!(comparator.get_similarity(&born, &year_and_month) > partial.similarity);
+        assert_eq!(kind(&year_only, &OptionDate::new(Some(1979), None, None).unwrap()), DateMatchKind::DigitTypo);
+        assert_eq!(kind(&OptionDate::new(None, Some(3), Some(4)).unwrap(), &born), DateMatchKind::PartialAgreement);
+        assert_eq!(kind(&year_only, &OptionDate::new(None, Some(3), Some(4)).unwrap()), DateMatchKind::Missing);
+        assert!(OptionDate::new(Some(1970), Some(13), None).is_err());
+        assert!(OptionDate::new(Some(1970), Some(4), Some(31)).is_err());
+        assert!(OptionDate::new(Some(1970), Some(2), Some(29)).is_err());
+        assert!(OptionDate::new(Some(2000), Some(2), Some(29)).is_ok());
+        assert!(OptionDate::new(None, Some(2), Some(29)).is_ok());
+        assert!(OptionDate::new(None, None, Some(0)).is_err());
+        assert!(OptionDate::from_str("1970-02-30").is_err());
+        assert_eq!(OptionDate::from_str("1970-03-04").unwrap().to_string(), "1970-3-4");
+        assert_eq!(year_only.to_string(), "1970-?-?");
+        assert_eq!(year_and_month.to_string(), "1970-3-?");
+        assert_eq!(OptionDate::new(None, Some(3), Some(4)).unwrap().to_string(), "?-3-4");
+
+        let a = human_for_export("Jane Doe", "123-45-6789", "1970-03-04", "123 Main St, Anytown, NJ 01234, United States", None);
+        let b = human_for_export("Jane Doe", "123-45-6789", "1970-04-03", "123 Main St, Anytown, NJ 01234, United States", None);
+        let mut record_comparator = RecordComparator::new();
+        assert_eq!(record_comparator.compare_field(LinkageField::BirthDate, &a, &b), FieldComparison::Agree);
+        record_comparator.set_birth_date_similarity_threshold(1.0);
+        assert_eq!(record_comparator.compare_field(LinkageField::BirthDate, &a, &b), FieldComparison::Disagree);
+    }
+
+    #[test]
+    fn test_address_comparator() {
+        let comparator = AddressComparator::new();
+        let address = |s: &str| Address::from_str(s).unwrap();
+        let home = address("123 North Main Street Apt 4B, Anytown, NJ 01234, United States");
+
+        let standardized = comparator.standardize(&home);
+        assert_eq!(standardized.house_number, Some("123".to_string()));
+        assert_eq!(standardized.street_name, Some("n main".to_string()));
+        assert_eq!(standardized.street_suffix, Some("st".to_string()));
+        assert_eq!(standardized.unit, Some("4b".to_string()));
+        assert_eq!(standardized.postal_code, Some("01234".to_string()));
+        assert_eq!(standardized.country, Some("us".to_string()));
+
+        let same = comparator.compare(&home, &address("123 N. Main St #4B, ANYTOWN, NJ 01234, USA"));
+        assert_eq!(same.similarity, 1.0);
+        assert_eq!(same.locality_match_level, LocalityMatchLevel::PostalCode);
+
+        // A unit on one side only is left out; a different house number on the same street is not the same household
+        assert_eq!(comparator.get_similarity(&home, &address("123 N Main St, Anytown, NJ 01234, US")), 1.0);
+        let neighbour = comparator.compare(&home, &address("125 N Main St Apt 4B, Anytown, NJ 01234, US"));
+        assert_eq!(neighbour.components[0].component, AddressComponent::HouseNumber);
+        assert_eq!(neighbour.components[0].similarity, 0.0);
+        assert!(neighbour.similarity < 0.9);
+
+        let typo = comparator.get_similarity(&home, &address("123 N Mian St Apt 4B, Anytown, NJ 01234, US"));
+        assert!(typo > 0.9 && typo < 1.0);
+        let other_suffix = comparator.get_similarity(&home, &address("123 N Main Ave Apt 4B, Anytown, NJ 01234, US"));
+        assert!(other_suffix < 1.0 && other_suffix > typo - 0.1);
+
+        // Locality falls back from postal code to city, postal code prefix and state
+        let level = |s: &str| comparator.compare(&home, &address(s)).locality_match_level;
+        assert_eq!(level("123 N Main St Apt 4B, Anytown, NJ 01299, US"), LocalityMatchLevel::City);
+        assert_eq!(level("123 N Main St Apt 4B, Othertown, NJ 01299, US"), LocalityMatchLevel::PostalCodePrefix);
+        assert_eq!(level("123 N Main St Apt 4B, Othertown, NJ 07999, US"), LocalityMatchLevel::StateOrProvince);
+        assert_eq!(level("123 N Main St Apt 4B, Othertown, NY 12345, US"), LocalityMatchLevel::None);
+        let scores: Vec<Similarity> = ["01234", "01299"].iter().map(|zip| comparator.get_similarity(&home, &address(&format!("123 N Main St Apt 4B, Anytown, NJ {}, US", zip)))).collect();
+        assert!(scores[0] > scores[1]);
+
+        let a = human_for_export("Jane Doe", "123-45-6789", "1970-01-31", "123 North Main Street, Anytown, NJ 01234, United States", None);
+        let b = human_for_export("Jane Doe", "123-45-6789", "1970-01-31", "123 N Main St, Anytown, NJ 01234, USA", None);
+        let c = human_for_export("Jane Doe", "123-45-6789", "1970-01-31", "321 N Main St, Anytown, NJ 01234, USA", None);
+        assert_eq!(RecordComparator::new().compare_field(LinkageField::Address, &a, &b), FieldComparison::Agree);
+        assert_eq!(RecordComparator::new().compare_field(LinkageField::Address, &a, &c), FieldComparison::Disagree);
     }
 
     // TODO: Add more tests